@@ -5,12 +5,15 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::{Parser, Subcommand};
+use colored::Colorize;
 use docz_lib::{
+    doctest::DocTestOutcome,
+    i18n::TranslationState,
     serve::ServeOptions,
     watch::{WatchEvent, WatchOptions},
-    Service,
+    Service, ServiceBuilder,
 };
 
 /// CLI arguments
@@ -23,6 +26,12 @@ pub struct CliArgs {
     /// Turn debugging information on
     #[arg(short, long)]
     pub dbg: bool,
+    /// Config profile (`[profile.<name>]` in doc.toml)
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+    /// Sets a variable (overrides `[variables]` in doc.toml)
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var, global = true)]
+    pub vars: Vec<(String, String)>,
     /// Commands
     #[command(subcommand)]
     pub command: Command,
@@ -41,6 +50,8 @@ pub enum Command {
     },
     /// Cleans the build folder
     Clean {},
+    /// Runs the test code blocks of the doc (doc tests)
+    Test {},
     /// Servces the doc
     Serve {
         /// Server port
@@ -53,6 +64,34 @@ pub enum Command {
         #[arg(short, long)]
         open: bool,
     },
+    /// Config commands
+    Config {
+        /// Config command
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Translation commands
+    I18n {
+        /// Translation command
+        #[command(subcommand)]
+        command: I18nCommand,
+    },
+}
+
+/// Config command
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Checks the config file
+    Check {},
+    /// Prints the JSON Schema of the config file (eg for editor autocompletion)
+    Schema {},
+}
+
+/// Translation command
+#[derive(Subcommand)]
+pub enum I18nCommand {
+    /// Lists the missing and outdated translations
+    Status {},
 }
 
 /// Runs the CLI
@@ -75,12 +114,41 @@ pub async fn run() -> Result<()> {
             eprintln!("✅ Initialized repo");
         }
         Command::Clean {} => {
-            let service = init_service(&root_dir)?;
+            let service = init_service(&root_dir, args.profile.as_deref(), &args.vars)?;
             service.remove_build_dir()?;
             eprintln!("✅ Cleaned the build folder");
         }
+        Command::Test {} => {
+            let service = init_service(&root_dir, args.profile.as_deref(), &args.vars)?;
+            eprintln!("Running the doc tests ..");
+            let results = service.test()?;
+            let mut failed = 0;
+            let mut ignored = 0;
+            for result in &results {
+                let test = &result.test;
+                let location = format!("{}:{} ({})", test.page.display(), test.line, test.lang);
+                match &result.outcome {
+                    DocTestOutcome::Passed => {}
+                    DocTestOutcome::Ignored => {
+                        ignored += 1;
+                        eprintln!("{}", format!("ignored: {location}").yellow());
+                    }
+                    DocTestOutcome::Failed(msg) => {
+                        failed += 1;
+                        eprintln!("{}", format!("failed: {location}\n{msg}").red());
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(anyhow!("{failed} of {} doc tests failed", results.len()));
+            }
+            eprintln!(
+                "✅ {} doc tests passed ({ignored} ignored)",
+                results.len() - ignored
+            );
+        }
         Command::Build { watch } => {
-            let mut service = init_service(&root_dir)?;
+            let mut service = init_service(&root_dir, args.profile.as_deref(), &args.vars)?;
             if !watch {
                 eprintln!("Building ..");
                 service.build()?;
@@ -95,7 +163,7 @@ pub async fn run() -> Result<()> {
             }
         }
         Command::Serve { port, watch, open } => {
-            let service = init_service(&root_dir)?;
+            let service = init_service(&root_dir, args.profile.as_deref(), &args.vars)?;
             if !watch {
                 service.serve(ServeOptions { port, open }, None).await?;
             } else {
@@ -109,20 +177,102 @@ pub async fn run() -> Result<()> {
                     .await?;
             }
         }
+        Command::Config { command } => match command {
+            ConfigCommand::Check {} => {
+                let diags = service_builder(&root_dir, args.profile.as_deref(), &args.vars).check();
+                for diag in &diags {
+                    if diag.is_error() {
+                        eprintln!("{}", diag.to_string().red());
+                    } else {
+                        eprintln!("{}", diag.to_string().yellow());
+                    }
+                }
+                if diags.iter().any(|diag| diag.is_error()) {
+                    return Err(anyhow!("Invalid config"));
+                }
+                eprintln!("✅ Config is valid");
+            }
+            ConfigCommand::Schema {} => {
+                let schema =
+                    service_builder(&root_dir, args.profile.as_deref(), &args.vars).config_schema();
+                println!("{}", serde_json::to_string_pretty(&schema)?);
+            }
+        },
+        Command::I18n { command } => match command {
+            I18nCommand::Status {} => {
+                let service = init_service(&root_dir, args.profile.as_deref(), &args.vars)?;
+                let status = service.i18n_status()?;
+                let mut up_to_date = true;
+                for entry in &status {
+                    let page = entry.page.display();
+                    match (entry.state, &entry.translation) {
+                        (TranslationState::Missing, _) => {
+                            eprintln!("{}", format!("[{}] missing: {page}", entry.locale).red());
+                        }
+                        (TranslationState::Outdated, Some(translation)) => {
+                            eprintln!(
+                                "{}",
+                                format!(
+                                    "[{}] outdated: {} (source {page} changed since)",
+                                    entry.locale,
+                                    translation.display()
+                                )
+                                .yellow()
+                            );
+                        }
+                        _ => continue,
+                    }
+                    up_to_date = false;
+                }
+                if up_to_date {
+                    eprintln!("✅ Translations are up to date");
+                }
+            }
+        },
     }
 
     Ok(())
 }
 
 /// Initializes the service
-fn init_service(root_dir: &Path) -> Result<Service> {
-    let service = Service::builder()
+fn init_service(
+    root_dir: &Path,
+    profile: Option<&str>,
+    vars: &[(String, String)],
+) -> Result<Service> {
+    service_builder(root_dir, profile, vars).build()
+}
+
+/// Returns the service builder, with all the renderers
+fn service_builder(
+    root_dir: &Path,
+    profile: Option<&str>,
+    vars: &[(String, String)],
+) -> ServiceBuilder {
+    let mut builder = Service::builder();
+    if let Some(profile) = profile {
+        builder = builder.profile(profile);
+    }
+    for (key, value) in vars {
+        builder = builder.var(key, value);
+    }
+    builder
         .root_dir(root_dir)
         .dbg_renderer()
+        .json_renderer()
         .html_renderer()
+        .slides_renderer()
+        .text_renderer()
         .epub_renderer()
-        .build()?;
-    Ok(service)
+        .docx_renderer()
+}
+
+/// Parses a variable (`key=value`)
+fn parse_var(s: &str) -> Result<(String, String)> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or(anyhow!("Invalid variable `{s}`, expected KEY=VALUE"))?;
+    Ok((key.trim().to_string(), value.to_string()))
 }
 
 /// Prints a message on rebuild
@@ -6,7 +6,9 @@ mod cmd;
 async fn main() {
     eprintln!();
     if let Err(err) = cmd::run().await {
-        let msg = format!("❌ {}", err).bright_red();
+        let msg = format!("❌ {:#}", err).bright_red();
         eprintln!("{msg}");
+        // NB: a failed command (eg failed doc tests) fails the CI jobs
+        std::process::exit(1);
     }
 }
@@ -6,6 +6,8 @@ use anyhow::{anyhow, Result};
 use log::{debug, trace};
 
 use crate::{
+    i18n::strip_translations,
+    src::{SourceData, SourceFilter},
     watch::{EventExt, WatchOptions, Watcher},
     Service,
 };
@@ -13,17 +15,26 @@ use crate::{
 impl Service {
     /// Builds the document
     pub fn build(&self) -> Result<()> {
-        let src_tree = self.load_src_dir()?;
+        let src_data = self.load_src_dir()?;
+        let src_tree = SourceData {
+            files: strip_translations(&src_data.files, &self.config),
+            assets: src_data.assets.clone(),
+        };
 
         // (re)create the build dir
         let build_dir = self.config.build_dir();
         self.remove_build_dir()?;
         fs::create_dir(&build_dir)?;
 
-        for id in self.config.output_ids() {
+        // versions (from git)
+        let versions = self.resolve_versions()?;
+        let mut config = self.config.clone();
+        config.set_versions(versions.iter().map(|v| v.name.to_string()).collect());
+
+        for id in config.output_ids() {
             if let Some(renderer) = self.renderers.get(id) {
                 trace!("Rendering output ({id})");
-                renderer.render(&self.config, &src_tree)?;
+                renderer.render(&config, &src_tree)?;
             } else {
                 return Err(anyhow!(
                     "Invalid output type ({}). Check the config file or add a renderer",
@@ -31,6 +42,8 @@ impl Service {
                 ));
             };
         }
+        self.build_versions(&config, &versions)?;
+        self.build_locales(&config, &src_data, self.source_provider.as_ref())?;
 
         Ok(())
     }
@@ -49,7 +62,7 @@ impl Service {
         self.build()?;
 
         let watched_dirs = self.watched_dirs();
-        let mut watcher = Watcher::new(watched_dirs, Some(200))?;
+        let mut watcher = Watcher::new(watched_dirs, SourceFilter::new(&self.config)?, Some(200))?;
         let mut rx_watch = watcher.start()?;
         loop {
             rx_watch.changed().await?;
@@ -1,12 +1,19 @@
 //! Configuration
 
+mod check;
+mod schema;
+
+pub use check::*;
+
 use std::{
-    collections::HashMap,
-    env, fs,
+    collections::{BTreeMap, HashMap},
+    env, fmt, fs,
     path::{Path, PathBuf},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use log::trace;
+use schemars::JsonSchema;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 /// Service configuration
@@ -16,6 +23,22 @@ pub struct Config {
     root_dir: PathBuf,
     /// Config file
     file: ConfigFile,
+    /// Config file source (used to locate keys)
+    source: String,
+    /// Selected profile
+    profile: Option<String>,
+    /// Layer of each value set by the config file, the profile or the environment (eg `build.build_dir`)
+    origins: BTreeMap<String, ConfigLayer>,
+    /// Variables overriding the `[variables]` section (eg from the CLI)
+    var_overrides: BTreeMap<String, String>,
+    /// Version being built (`None` for the working tree)
+    version: Option<String>,
+    /// Names of the built versions
+    versions: Vec<String>,
+    /// Locale being built (`None` for the default language)
+    locale: Option<String>,
+    /// Extensions of the source pages (from the source formats)
+    page_extensions: Vec<String>,
 }
 
 impl Default for Config {
@@ -23,6 +46,14 @@ impl Default for Config {
         Self {
             root_dir: env::current_dir().unwrap(),
             file: ConfigFile::default(),
+            source: String::new(),
+            profile: None,
+            origins: BTreeMap::new(),
+            var_overrides: BTreeMap::new(),
+            version: None,
+            versions: vec![],
+            locale: None,
+            page_extensions: ["md", "markdown"].map(String::from).to_vec(),
         }
     }
 }
@@ -31,6 +62,12 @@ impl Config {
     /// Config file name
     pub(crate) const FILE_NAME: &str = "doc.toml";
 
+    /// Key of the profiles table
+    const PROFILES_KEY: &str = "profile";
+
+    /// Prefix of the environment variables overriding the config (eg `DOCZ_BUILD__BUILD_DIR`)
+    const ENV_PREFIX: &str = "DOCZ_";
+
     /// Creates a new default config
     pub fn new() -> Self {
         Self::default()
@@ -47,6 +84,148 @@ impl Config {
         self.root_dir.to_owned()
     }
 
+    /// Sets the profile (`[profile.<name>]` section merged over the config file)
+    pub fn set_profile(&mut self, profile: Option<&str>) -> &mut Self {
+        self.profile = profile.map(|p| p.to_string());
+        self
+    }
+
+    /// Returns the selected profile
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Overrides a variable of the `[variables]` section
+    pub fn set_var(&mut self, key: &str, value: &str) -> &mut Self {
+        self.var_overrides
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Returns the variables (`[variables]` section, with the overrides)
+    pub fn variables(&self) -> BTreeMap<String, String> {
+        let mut vars = self
+            .file
+            .variables
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    toml::Value::String(s) => s.to_string(),
+                    value => value.to_string(),
+                };
+                (key.to_string(), value)
+            })
+            .collect::<BTreeMap<_, _>>();
+        vars.extend(self.var_overrides.clone());
+        vars
+    }
+
+    /// Sets the version being built
+    pub fn set_version(&mut self, version: Option<&str>) -> &mut Self {
+        self.version = version.map(|v| v.to_string());
+        self
+    }
+
+    /// Returns the version being built (`None` for the working tree)
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Sets the names of the built versions
+    pub fn set_versions(&mut self, versions: Vec<String>) -> &mut Self {
+        self.versions = versions;
+        self
+    }
+
+    /// Returns the names of the built versions
+    pub fn versions(&self) -> &[String] {
+        &self.versions
+    }
+
+    /// Sets the locale being built
+    pub fn set_locale(&mut self, locale: Option<&str>) -> &mut Self {
+        self.locale = locale.map(|l| l.to_string());
+        self
+    }
+
+    /// Returns the locale being built (`None` for the default language)
+    pub fn locale(&self) -> Option<&str> {
+        self.locale.as_deref()
+    }
+
+    /// Returns the locales, other than the default language (`[languages]` section)
+    pub fn locales(&self) -> Vec<String> {
+        match &self.file.languages {
+            Some(languages) => languages
+                .locales
+                .iter()
+                .filter(|locale| **locale != languages.default)
+                .cloned()
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Returns the language being built (eg `en`)
+    pub fn lang(&self) -> String {
+        self.locale.clone().unwrap_or_else(|| self.default_lang())
+    }
+
+    /// Returns the default language (`[languages]` section, or the doc language, or `en`)
+    pub fn default_lang(&self) -> String {
+        self.file
+            .languages
+            .as_ref()
+            .map(|l| l.default.clone())
+            .or(self.file.doc.language.clone())
+            .unwrap_or("en".to_string())
+    }
+
+    /// Sets the extensions of the source pages (from the source formats)
+    pub(crate) fn set_page_extensions(&mut self, extensions: Vec<String>) -> &mut Self {
+        self.page_extensions = extensions;
+        self
+    }
+
+    /// Checks if a source file is a page (its format is supported)
+    pub fn is_page(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| self.page_extensions.contains(&ext.to_lowercase()))
+    }
+
+    /// Overrides the source directory (eg with a version extracted from git)
+    pub(crate) fn set_src_dir(&mut self, src_dir: &Path) -> &mut Self {
+        self.file.src.src_dir = src_dir.to_owned();
+        self
+    }
+
+    /// Overrides the source directory of a locale (eg with a version extracted from git)
+    ///
+    /// NB: without a dir, the translations of the locale are the locale-suffixed files only
+    pub(crate) fn set_locale_dir(&mut self, locale: &str, dir: Option<&Path>) -> &mut Self {
+        if let Some(languages) = self.file.languages.as_mut() {
+            match dir {
+                Some(dir) => languages.dirs.insert(locale.to_string(), dir.to_owned()),
+                None => languages.dirs.remove(locale),
+            };
+        }
+        self
+    }
+
+    /// Returns the layer a value comes from (eg `build.build_dir`)
+    pub fn value_origin(&self, key: &str) -> ConfigLayer {
+        self.origins
+            .get(key)
+            .cloned()
+            .unwrap_or(ConfigLayer::Default)
+    }
+
+    /// Returns the layer of each value which is not a default value
+    pub fn value_origins(&self) -> &BTreeMap<String, ConfigLayer> {
+        &self.origins
+    }
+
     /// Returns the config file
     pub fn file(&self) -> &ConfigFile {
         &self.file
@@ -82,13 +261,90 @@ impl Config {
         self.root_dir.join(&self.file.build.build_dir)
     }
 
+    /// Returns the cache directory of the diagrams
+    pub fn diagrams_cache_dir(&self) -> PathBuf {
+        self.root_dir.join(&self.file.diagrams.cache_dir)
+    }
+
+    /// Returns the path of the bibliography file, if any
+    pub fn bibliography_path(&self) -> Option<PathBuf> {
+        self.file
+            .citations
+            .bibliography
+            .as_ref()
+            .map(|path| self.root_dir.join(path))
+    }
+
+    /// Returns the working directory of the doc tests
+    pub fn doctest_dir(&self) -> PathBuf {
+        self.root_dir.join(&self.file.doctest.dir)
+    }
+
+    /// Returns the path of the glossary file, if any
+    pub fn glossary_path(&self) -> Option<PathBuf> {
+        self.file
+            .glossary
+            .file
+            .as_ref()
+            .map(|path| self.root_dir.join(path))
+    }
+
     /// Loads the configuration from a file
+    ///
+    /// The layers are applied in order: config file, profile, and `DOCZ_*` environment variables.
     pub(crate) fn load_file(&mut self) -> Result<()> {
         let path = self.file_path();
         let data = fs::read(path).context("config file not found")?;
         let data_str = String::from_utf8(data)?;
-        let file = toml::from_str::<ConfigFile>(&data_str)?;
+        let mut table = toml::from_str::<toml::Table>(&data_str)?;
+        let mut origins = BTreeMap::new();
+
+        // profiles
+        let mut profiles = match table.remove(Self::PROFILES_KEY) {
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => return Err(anyhow!("Invalid config, [profile] must be a table")),
+            None => toml::Table::new(),
+        };
+        record_origins(&table, "", &ConfigLayer::File, &mut origins);
+
+        if let Some(profile) = &self.profile {
+            let profile_table = match profiles.remove(profile) {
+                Some(toml::Value::Table(profile_table)) => profile_table,
+                Some(_) => {
+                    return Err(anyhow!(
+                        "Invalid config, [profile.{profile}] must be a table"
+                    ))
+                }
+                None => return Err(anyhow!("Unknown config profile ({profile})")),
+            };
+            let layer = ConfigLayer::Profile(profile.clone());
+            merge_table(&mut table, profile_table, "", &layer, &mut origins);
+        }
+
+        // environment variables
+        let mut env_vars = env::vars()
+            .filter(|(key, _)| key.starts_with(Self::ENV_PREFIX) && key.contains("__"))
+            .collect::<Vec<_>>();
+        env_vars.sort();
+        for (key, value) in env_vars {
+            let path = key[Self::ENV_PREFIX.len()..]
+                .split("__")
+                .map(|part| part.to_lowercase())
+                .collect::<Vec<_>>();
+            let overlay = env_value_to_table(&path, &value);
+            let layer = ConfigLayer::Env(key.clone());
+            merge_table(&mut table, overlay, "", &layer, &mut origins);
+        }
+
+        let file = toml::Value::Table(table)
+            .try_into::<ConfigFile>()
+            .context("Invalid config (run `docz config check` for details)")?;
+        for (key, layer) in &origins {
+            trace!("Config value {key} from {layer}");
+        }
         self.file = file;
+        self.source = data_str;
+        self.origins = origins;
         Ok(())
     }
 
@@ -115,13 +371,113 @@ impl Config {
             None => return Ok(None),
         };
 
-        let t = toml::from_str::<T>(toml::to_string(value)?.as_str())?;
+        let t = value
+            .clone()
+            .try_into::<T>()
+            .context(format!("Invalid config for output `{id}`"))?;
         Ok(Some(t))
     }
 }
 
+/// Layer a config value comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigLayer {
+    /// Default value
+    Default,
+    /// Config file
+    File,
+    /// Profile (`[profile.<name>]`)
+    Profile(String),
+    /// Environment variable
+    Env(String),
+}
+
+impl fmt::Display for ConfigLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigLayer::Default => write!(f, "default"),
+            ConfigLayer::File => write!(f, "{}", Config::FILE_NAME),
+            ConfigLayer::Profile(profile) => write!(f, "profile '{profile}'"),
+            ConfigLayer::Env(key) => write!(f, "env {key}"),
+        }
+    }
+}
+
+/// Merges a table over a base table (recursively)
+fn merge_table(
+    base: &mut toml::Table,
+    overlay: toml::Table,
+    prefix: &str,
+    layer: &ConfigLayer,
+    origins: &mut BTreeMap<String, ConfigLayer>,
+) {
+    for (key, value) in overlay {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(table)) => {
+                merge_table(base_table, table, &path, layer, origins);
+            }
+            (_, value) => {
+                let path_prefix = format!("{path}.");
+                origins.retain(|k, _| k != &path && !k.starts_with(&path_prefix));
+                match &value {
+                    toml::Value::Table(table) => record_origins(table, &path, layer, origins),
+                    _ => {
+                        origins.insert(path, layer.clone());
+                    }
+                }
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Records the layer of all the values of a table (recursively)
+fn record_origins(
+    table: &toml::Table,
+    prefix: &str,
+    layer: &ConfigLayer,
+    origins: &mut BTreeMap<String, ConfigLayer>,
+) {
+    for (key, value) in table {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        match value {
+            toml::Value::Table(table) => record_origins(table, &path, layer, origins),
+            _ => {
+                origins.insert(path, layer.clone());
+            }
+        }
+    }
+}
+
+/// Converts an environment variable to a nested table
+///
+/// NB: the value is parsed as a TOML value (eg `["a", "b"]`, `true`), or kept as a string
+fn env_value_to_table(path: &[String], value: &str) -> toml::Table {
+    let mut value = toml::from_str::<toml::Table>(&format!("value = {value}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()));
+    for key in path[1..].iter().rev() {
+        let mut table = toml::Table::new();
+        table.insert(key.clone(), value);
+        value = toml::Value::Table(table);
+    }
+    let mut table = toml::Table::new();
+    table.insert(path[0].clone(), value);
+    table
+}
+
 /// Configuration file
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ConfigFile {
     /// Documentation configuration
     pub doc: DocConfig,
@@ -132,7 +488,42 @@ pub struct ConfigFile {
     /// Watch config
     pub watch: WatchConfig,
     /// Output config
+    ///
+    /// NB: the schema is given by the renderers
+    #[schemars(skip)]
     pub output: HashMap<String, toml::Value>,
+    /// Variables, substituted in the pages (`{{ var.name }}`)
+    #[serde(default, skip_serializing_if = "toml::Table::is_empty")]
+    #[schemars(with = "BTreeMap<String, serde_json::Value>")]
+    pub variables: toml::Table,
+    /// Versions, built from git
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub versions: Option<VersionsConfig>,
+    /// Languages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub languages: Option<LanguagesConfig>,
+    /// Diagrams
+    #[serde(default)]
+    pub diagrams: DiagramsConfig,
+    /// Callout types (eg `[callouts.security]`), in addition to the built-in types
+    /// (note, tip, important, warning, caution)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub callouts: BTreeMap<String, CalloutConfig>,
+    /// Figures, tables and listings
+    #[serde(default)]
+    pub figures: FiguresConfig,
+    /// Citations
+    #[serde(default)]
+    pub citations: CitationsConfig,
+    /// Glossary
+    #[serde(default)]
+    pub glossary: GlossaryConfig,
+    /// Index
+    #[serde(default)]
+    pub index: IndexConfig,
+    /// Doc tests
+    #[serde(default)]
+    pub doctest: DoctestConfig,
 }
 
 impl Default for ConfigFile {
@@ -147,12 +538,22 @@ impl Default for ConfigFile {
             build: BuildConfig::default(),
             watch: WatchConfig::default(),
             output,
+            variables: toml::Table::new(),
+            versions: None,
+            languages: None,
+            diagrams: DiagramsConfig::default(),
+            callouts: BTreeMap::new(),
+            figures: FiguresConfig::default(),
+            citations: CitationsConfig::default(),
+            glossary: GlossaryConfig::default(),
+            index: IndexConfig::default(),
+            doctest: DoctestConfig::default(),
         }
     }
 }
 
 /// Documentation configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DocConfig {
     /// Doc title
     pub title: String,
@@ -160,6 +561,41 @@ pub struct DocConfig {
     pub summary: String,
     /// Authors
     pub authors: Vec<String>,
+    /// Language (BCP 47, eg `en` or `fr-CA`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Text direction
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<TextDirection>,
+    /// Identifier (eg `urn:isbn:9780000000000` or `urn:uuid:...`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    /// Publisher
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+    /// License (SPDX ID or URL, eg `CC-BY-4.0`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Rights statement (eg `© 2023 Jane Doe`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rights: Option<String>,
+    /// Publication date (ISO 8601, eg `2023-09-01`)
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_date"
+    )]
+    #[schemars(with = "Option<String>")]
+    pub publication_date: Option<String>,
+    /// Subjects (keywords)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subjects: Vec<String>,
+    /// Contributors (other than the authors)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contributors: Vec<ContributorConfig>,
+    /// Series the doc belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series: Option<SeriesConfig>,
 }
 
 impl Default for DocConfig {
@@ -168,17 +604,107 @@ impl Default for DocConfig {
             title: "Doc title".to_string(),
             summary: "Doc description".to_string(),
             authors: vec![],
+            language: None,
+            direction: None,
+            identifier: None,
+            publisher: None,
+            license: None,
+            rights: None,
+            publication_date: None,
+            subjects: vec![],
+            contributors: vec![],
+            series: None,
         }
     }
 }
 
+/// Text direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TextDirection {
+    /// Left to right
+    Ltr,
+    /// Right to left
+    Rtl,
+}
+
+impl TextDirection {
+    /// Returns the direction as a string (`ltr` or `rtl`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TextDirection::Ltr => "ltr",
+            TextDirection::Rtl => "rtl",
+        }
+    }
+}
+
+/// Contributor configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ContributorConfig {
+    /// Name
+    pub name: String,
+    /// Role (MARC relator code, eg `edt`, `trl` or `ill`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+}
+
+/// Series configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SeriesConfig {
+    /// Series name
+    pub name: String,
+    /// Position in the series (eg 2)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<u32>,
+}
+
+/// Deserializes a date, either as a TOML date or a string
+fn deserialize_date<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Date {
+        Toml(toml::value::Datetime),
+        String(String),
+    }
+
+    Ok(
+        Option::<Date>::deserialize(deserializer)?.map(|date| match date {
+            Date::Toml(date) => date.to_string(),
+            Date::String(date) => date,
+        }),
+    )
+}
+
 /// Source configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SourceConfig {
     /// Source files directory
     pub src_dir: PathBuf,
     /// Assets files directory
     pub assets_dir: PathBuf,
+    /// Excluded files (`.gitignore`-style patterns, relative to the source dir)
+    ///
+    /// NB: hidden files (eg `.DS_Store`), editor swap and backup files are always excluded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Included files (`.gitignore`-style patterns, relative to the source dir), all if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Fails on orphan dirs (without a page of the same name, eg `guide.md` for `guide/`,
+    /// or an index page), instead of generating a listing page
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strict: bool,
+    /// Keeps all the raw HTML of the pages (eg the `<script>` of an HTML fragment or a notebook output)
+    ///
+    /// NB: otherwise, the raw HTML written in the Markdown pages is omitted (and the `javascript:`
+    /// links removed), and the unsafe tags (eg `<script>`, `<iframe>`, `<style>`) of the converted
+    /// pages (eg HTML fragments) are filtered with the GitHub Flavored Markdown tagfilter, which
+    /// keeps the attributes of the other tags (eg `onclick`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub raw_html: bool,
 }
 
 impl Default for SourceConfig {
@@ -186,13 +712,18 @@ impl Default for SourceConfig {
         Self {
             src_dir: PathBuf::from("src"),
             assets_dir: PathBuf::from("_assets"),
+            exclude: vec![],
+            include: vec![],
+            strict: false,
+            raw_html: false,
         }
     }
 }
 
 /// Build configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BuildConfig {
+    /// Build directory
     pub build_dir: PathBuf,
 }
 
@@ -204,8 +735,292 @@ impl Default for BuildConfig {
     }
 }
 
+/// Versions configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
+pub struct VersionsConfig {
+    /// Git refs (eg `["v1.0", "release/2.x"]`)
+    #[serde(default)]
+    pub refs: Vec<String>,
+    /// Glob of the git tags (eg `v*`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<String>,
+    /// Git ref aliased as `latest` (defaults to the highest tag, or the first ref)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest: Option<String>,
+}
+
+/// Languages configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LanguagesConfig {
+    /// Default language (eg `en`)
+    pub default: String,
+    /// Other locales (eg `["fr", "de"]`)
+    #[serde(default)]
+    pub locales: Vec<String>,
+    /// Per-locale source dirs (relative to the root dir, eg `{ fr = "src-fr" }`)
+    ///
+    /// NB: translations can also be locale-suffixed source files (eg `01-intro.fr.md`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dirs: BTreeMap<String, PathBuf>,
+}
+
+/// Diagrams configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DiagramsConfig {
+    /// Cache directory of the rendered diagrams (relative to the root dir)
+    #[serde(default = "DiagramsConfig::default_cache_dir")]
+    pub cache_dir: PathBuf,
+    /// Commands rendering the diagrams to SVG, by code block language
+    /// (eg `{ mermaid = "mmdc -i {input} -o {output}" }`)
+    ///
+    /// NB: without `{input}`, the source is piped to stdin, and without `{output}`,
+    /// the SVG is read from stdout. Arguments with spaces are quoted (eg `"my tool" {input}`).
+    /// A command running more than 60s is killed. DOT is rendered without a command.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub commands: BTreeMap<String, String>,
+}
+
+impl DiagramsConfig {
+    /// Default cache dir
+    fn default_cache_dir() -> PathBuf {
+        PathBuf::from(".cache/diagrams")
+    }
+}
+
+impl Default for DiagramsConfig {
+    fn default() -> Self {
+        Self {
+            cache_dir: Self::default_cache_dir(),
+            commands: BTreeMap::new(),
+        }
+    }
+}
+
+/// Callout type configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
+pub struct CalloutConfig {
+    /// Default title (eg `Security`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Icon (eg `🔒`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+}
+
+/// Figures configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
+pub struct FiguresConfig {
+    /// Adds a generated "List of Figures" page
+    #[serde(default)]
+    pub list_of_figures: bool,
+    /// Adds a generated "List of Tables" page
+    #[serde(default)]
+    pub list_of_tables: bool,
+}
+
+/// Citations configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
+pub struct CitationsConfig {
+    /// Bibliography file (BibTeX `.bib` or CSL-JSON `.json`, relative to the root dir)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bibliography: Option<PathBuf>,
+    /// Citation style
+    #[serde(default)]
+    pub style: CitationStyle,
+    /// Placement of the bibliography
+    #[serde(default)]
+    pub placement: BibliographyPlacement,
+    /// Title of the bibliography (`References` in the language of the doc by default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Citation style
+///
+/// NB: the styles are built-in, and approximate the APA and IEEE styles for the common
+/// reference types. CSL style files (`.csl`) are not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CitationStyle {
+    /// Author-date (eg `(Knuth, 1984)`)
+    #[default]
+    Apa,
+    /// Numeric (eg `[1]`)
+    Ieee,
+}
+
+/// Placement of the bibliography
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BibliographyPlacement {
+    /// At the end of each page, with the references cited in the page
+    #[default]
+    Page,
+    /// In a generated page, with the references cited in the doc
+    Book,
+}
+
+/// Glossary configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct GlossaryConfig {
+    /// Glossary file (TOML or YAML, relative to the root dir), from which a glossary page is generated
+    ///
+    /// NB: without a file, the glossary is the `glossary.md` page of the source dir (definition lists)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    /// Title of the generated glossary page (`Glossary` in the language of the doc by default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Index configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct IndexConfig {
+    /// Title of the generated index page (`Index` in the language of the doc by default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Doc tests configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DoctestConfig {
+    /// Working directory of the tests (relative to the root dir)
+    #[serde(default = "DoctestConfig::default_dir")]
+    pub dir: PathBuf,
+    /// Test languages (eg `[doctest.languages.python]`), in addition to the built-in languages
+    /// (rust, sh, bash)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub languages: BTreeMap<String, DoctestLanguageConfig>,
+}
+
+impl DoctestConfig {
+    /// Default working dir
+    fn default_dir() -> PathBuf {
+        PathBuf::from(".cache/doctest")
+    }
+}
+
+impl Default for DoctestConfig {
+    fn default() -> Self {
+        Self {
+            dir: Self::default_dir(),
+            languages: BTreeMap::new(),
+        }
+    }
+}
+
+/// Doc test language configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DoctestLanguageConfig {
+    /// Command running a code block (eg `python3 {input}`)
+    ///
+    /// NB: without `{input}`, the code is piped to stdin, and with `{output}`,
+    /// the command builds an executable which is then run (eg `rustc {input} -o {output}`)
+    pub command: String,
+    /// Extension of the input file (the language by default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ext: Option<String>,
+    /// Prefix of the hidden lines (eg `# `), which are run but not rendered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hidden: Option<String>,
+}
+
 /// Watch configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
 pub struct WatchConfig {
+    /// Extra directories to watch (eg custom templates)
     pub extra_dirs: Vec<PathBuf>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a root dir with a config file
+    fn root_dir_with_config(name: &str, extra: &str) -> PathBuf {
+        let root_dir = env::temp_dir().join(format!("docz-cfg-{name}-{}", std::process::id()));
+        fs::create_dir_all(&root_dir).unwrap();
+        let config = toml::to_string(&ConfigFile::default()).unwrap();
+        fs::write(
+            root_dir.join(Config::FILE_NAME),
+            format!("{config}\n{extra}"),
+        )
+        .unwrap();
+        root_dir
+    }
+
+    #[test]
+    fn test_merge_table() {
+        let mut base = toml::from_str::<toml::Table>(
+            "[build]\nbuild_dir = \"build\"\n[doc]\ntitle = \"Title\"\nsummary = \"Summary\"",
+        )
+        .unwrap();
+        let mut origins = BTreeMap::new();
+        record_origins(&base, "", &ConfigLayer::File, &mut origins);
+
+        let overlay = toml::from_str::<toml::Table>("[build]\nbuild_dir = \"dist\"").unwrap();
+        let layer = ConfigLayer::Profile("release".to_string());
+        merge_table(&mut base, overlay, "", &layer, &mut origins);
+
+        assert_eq!(base["build"]["build_dir"].as_str(), Some("dist"));
+        // NB: the other values of the table are kept
+        assert_eq!(base["doc"]["title"].as_str(), Some("Title"));
+        assert_eq!(origins["build.build_dir"], layer);
+        assert_eq!(origins["doc.title"], ConfigLayer::File);
+    }
+
+    #[test]
+    fn test_merge_table_replaces_values() {
+        let mut base = toml::from_str::<toml::Table>("[a]\nb = { c = 1, d = 2 }").unwrap();
+        let mut origins = BTreeMap::new();
+        record_origins(&base, "", &ConfigLayer::File, &mut origins);
+
+        // NB: a value which is not a table replaces the whole table
+        let overlay = toml::from_str::<toml::Table>("[a]\nb = 3").unwrap();
+        let layer = ConfigLayer::Env("DOCZ_A__B".to_string());
+        merge_table(&mut base, overlay, "", &layer, &mut origins);
+
+        assert_eq!(base["a"]["b"].as_integer(), Some(3));
+        assert!(!origins.contains_key("a.b.c"));
+        assert_eq!(origins["a.b"], layer);
+    }
+
+    #[test]
+    fn test_env_value_to_table() {
+        let path = ["build", "build_dir"].map(String::from);
+        let table = env_value_to_table(&path, "dist");
+        assert_eq!(table["build"]["build_dir"].as_str(), Some("dist"));
+
+        // NB: TOML values are parsed
+        let path = ["watch", "extra_dirs"].map(String::from);
+        let table = env_value_to_table(&path, "[\"a\", \"b\"]");
+        assert_eq!(
+            table["watch"]["extra_dirs"].as_array().map(Vec::len),
+            Some(2)
+        );
+
+        let path = ["figures", "list_of_figures"].map(String::from);
+        let table = env_value_to_table(&path, "true");
+        assert_eq!(table["figures"]["list_of_figures"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn test_load_profile() {
+        let root_dir =
+            root_dir_with_config("profile", "[profile.release.build]\nbuild_dir = \"dist\"\n");
+        let mut config = Config::default();
+        config.set_root_dir(&root_dir);
+        config.set_profile(Some("release"));
+        config.load_file().unwrap();
+        assert_eq!(config.build_dir(), root_dir.join("dist"));
+        assert_eq!(
+            config.value_origin("build.build_dir"),
+            ConfigLayer::Profile("release".to_string())
+        );
+        assert_eq!(config.value_origin("doc.title"), ConfigLayer::File);
+
+        config.set_profile(Some("missing"));
+        assert!(config.load_file().is_err());
+        fs::remove_dir_all(root_dir).unwrap();
+    }
+}
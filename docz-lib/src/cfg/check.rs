@@ -0,0 +1,469 @@
+//! Configuration validation
+
+use std::{fmt, fs, ops::Range};
+
+use serde::{
+    de::{self, DeserializeOwned, Visitor},
+    forward_to_deserialize_any, Deserializer,
+};
+
+use super::{
+    BuildConfig, CalloutConfig, CitationsConfig, Config, ConfigFile, DiagramsConfig, DocConfig,
+    DoctestConfig, FiguresConfig, GlossaryConfig, IndexConfig, LanguagesConfig, SourceConfig,
+    VersionsConfig, WatchConfig,
+};
+
+/// Diagnostic severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Invalid config
+    Error,
+    /// Valid config, but probably not what was intended (eg unknown key)
+    Warning,
+}
+
+/// Config diagnostic
+#[derive(Debug, Clone)]
+pub struct ConfigDiagnostic {
+    /// Severity
+    pub severity: Severity,
+    /// Key (eg `output.html.template`)
+    pub key: Option<String>,
+    /// Message
+    pub message: String,
+    /// Location in the config file (line, column - starting at 1)
+    pub location: Option<(usize, usize)>,
+    /// Suggestion (eg "did you mean")
+    pub suggestion: Option<String>,
+}
+
+impl ConfigDiagnostic {
+    /// Creates an error
+    pub fn error(key: Option<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            key,
+            message: message.into(),
+            location: None,
+            suggestion: None,
+        }
+    }
+
+    /// Creates a warning
+    pub fn warning(key: Option<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(key, message)
+        }
+    }
+
+    /// Sets the location
+    pub fn at(mut self, location: Option<(usize, usize)>) -> Self {
+        self.location = location;
+        self
+    }
+
+    /// Sets the suggestion
+    pub fn suggest(mut self, suggestion: Option<String>) -> Self {
+        self.suggestion = suggestion;
+        self
+    }
+
+    /// Checks if the diagnostic is an error
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Creates a warning for an unknown key (with a suggestion among the valid keys)
+    pub(crate) fn unknown_key(path: &[&str], valid_keys: &[&str]) -> Self {
+        let key = path.join(".");
+        let suggestion = path
+            .last()
+            .and_then(|k| did_you_mean(k, valid_keys))
+            .map(|k| k.to_string());
+        Self::warning(Some(key.clone()), format!("unknown key `{key}`")).suggest(suggestion)
+    }
+}
+
+impl fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Config::FILE_NAME)?;
+        if let Some((line, column)) = self.location {
+            write!(f, ":{line}:{column}")?;
+        }
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, ": {severity}: {}", self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean `{suggestion}`?)")?;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Checks the config file
+    ///
+    /// NB: this reads the file again, and does not require the config to be loaded.
+    /// The output configs are checked by the renderers.
+    pub fn check(&self) -> Vec<ConfigDiagnostic> {
+        let mut diags = vec![];
+
+        let text = match fs::read_to_string(self.file_path()) {
+            Ok(text) => text,
+            Err(err) => {
+                diags.push(ConfigDiagnostic::error(
+                    None,
+                    format!("failed to read the config file ({err})"),
+                ));
+                return diags;
+            }
+        };
+
+        // syntax
+        let mut table = match toml::from_str::<toml::Table>(&text) {
+            Ok(table) => table,
+            Err(err) => {
+                diags.push(
+                    ConfigDiagnostic::error(None, err.message())
+                        .at(span_location(&text, err.span())),
+                );
+                return diags;
+            }
+        };
+
+        // unknown keys
+        let profiles = table.remove(Self::PROFILES_KEY);
+        check_keys(&text, &table, &[], &mut diags);
+        if let Some(toml::Value::Table(profiles)) = profiles {
+            for (name, profile) in &profiles {
+                if let toml::Value::Table(profile) = profile {
+                    check_keys(
+                        &text,
+                        profile,
+                        &[Self::PROFILES_KEY, name.as_str()],
+                        &mut diags,
+                    );
+                }
+            }
+        }
+
+        // types and required values
+        if let Err(err) = toml::from_str::<ConfigFile>(&text) {
+            diags.push(
+                ConfigDiagnostic::error(None, err.message()).at(span_location(&text, err.span())),
+            );
+        }
+
+        diags
+    }
+
+    /// Checks the config of a specific output against its type
+    pub fn check_output_cfg<T>(&self, id: &str) -> Vec<ConfigDiagnostic>
+    where
+        T: DeserializeOwned,
+    {
+        let mut diags = vec![];
+        let value = match self.file.output.get(id) {
+            Some(value) => value,
+            None => return diags,
+        };
+
+        let key = format!("output.{id}");
+        if let Err(err) = value.clone().try_into::<T>() {
+            diags.push(
+                ConfigDiagnostic::error(Some(key), err.message())
+                    .at(self.locate_key(&["output", id])),
+            );
+        }
+
+        if let toml::Value::Table(table) = value {
+            let fields = struct_fields::<T>();
+            for field in table.keys() {
+                if !fields.contains(&field.as_str()) {
+                    let path = ["output", id, field.as_str()];
+                    diags.push(
+                        ConfigDiagnostic::unknown_key(&path, fields).at(self.locate_key(&path)),
+                    );
+                }
+            }
+        }
+
+        diags
+    }
+
+    /// Locates a key in the config file (line, column)
+    pub(crate) fn locate_key(&self, path: &[&str]) -> Option<(usize, usize)> {
+        locate_key(&self.source, path)
+    }
+}
+
+/// Checks the keys of the config sections
+fn check_keys(text: &str, table: &toml::Table, prefix: &[&str], diags: &mut Vec<ConfigDiagnostic>) {
+    let sections = struct_fields::<ConfigFile>();
+    for (section, value) in table {
+        let mut path = prefix.to_vec();
+        path.push(section.as_str());
+        if !sections.contains(&section.as_str()) {
+            diags.push(ConfigDiagnostic::unknown_key(&path, sections).at(locate_key(text, &path)));
+            continue;
+        }
+
+        // NB: the callout types are tables (eg `[callouts.security]`)
+        if section == "callouts" {
+            if let toml::Value::Table(types) = value {
+                for (kind, type_value) in types {
+                    let mut path = path.clone();
+                    path.push(kind.as_str());
+                    check_fields(
+                        text,
+                        type_value,
+                        &path,
+                        struct_fields::<CalloutConfig>(),
+                        diags,
+                    );
+                }
+            }
+            continue;
+        }
+
+        let fields = match section.as_str() {
+            "doc" => struct_fields::<DocConfig>(),
+            "src" => struct_fields::<SourceConfig>(),
+            "build" => struct_fields::<BuildConfig>(),
+            "watch" => struct_fields::<WatchConfig>(),
+            "versions" => struct_fields::<VersionsConfig>(),
+            "languages" => struct_fields::<LanguagesConfig>(),
+            "diagrams" => struct_fields::<DiagramsConfig>(),
+            "figures" => struct_fields::<FiguresConfig>(),
+            "citations" => struct_fields::<CitationsConfig>(),
+            "glossary" => struct_fields::<GlossaryConfig>(),
+            "index" => struct_fields::<IndexConfig>(),
+            "doctest" => struct_fields::<DoctestConfig>(),
+            // NB: output configs are checked by the renderers
+            _ => continue,
+        };
+        check_fields(text, value, &path, fields, diags);
+    }
+}
+
+/// Checks the fields of a table
+fn check_fields(
+    text: &str,
+    value: &toml::Value,
+    path: &[&str],
+    fields: &[&str],
+    diags: &mut Vec<ConfigDiagnostic>,
+) {
+    if let toml::Value::Table(table) = value {
+        for field in table.keys() {
+            let mut path = path.to_vec();
+            path.push(field.as_str());
+            if !fields.contains(&field.as_str()) {
+                diags
+                    .push(ConfigDiagnostic::unknown_key(&path, fields).at(locate_key(text, &path)));
+            }
+        }
+    }
+}
+
+/// Returns the closest valid key, if it is close enough
+pub(crate) fn did_you_mean<'a>(key: &str, valid_keys: &[&'a str]) -> Option<&'a str> {
+    let max_distance = (key.chars().count() / 3).max(2);
+    valid_keys
+        .iter()
+        .map(|valid_key| (strsim::levenshtein(key, valid_key), *valid_key))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, valid_key)| valid_key)
+}
+
+/// Converts a byte span to a location (line, column)
+fn span_location(text: &str, span: Option<Range<usize>>) -> Option<(usize, usize)> {
+    let start = span?.start.min(text.len());
+    let before = &text[..start];
+    let line = before.matches('\n').count() + 1;
+    let column = start - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+    Some((line, column))
+}
+
+/// Locates a key in a TOML text (line, column)
+///
+/// NB: this is a line-based scan, it finds `[table]` headers and `key = value` lines.
+/// For keys inside inline tables, the location of the closest parent key is returned.
+fn locate_key(text: &str, path: &[&str]) -> Option<(usize, usize)> {
+    let mut table = vec![];
+    let mut parent_location = None;
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let location = Some((i + 1, line.len() - trimmed.len() + 1));
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if trimmed.starts_with('[') {
+            let header = trimmed.trim_start_matches('[');
+            table = split_key(header.split(']').next().unwrap_or(""));
+            if keys_eq(&table, path) {
+                return location;
+            }
+            continue;
+        }
+
+        if let Some((key, _)) = trimmed.split_once('=') {
+            let mut full_key = table.clone();
+            full_key.extend(split_key(key));
+            if keys_eq(&full_key, path) {
+                return location;
+            }
+            if parent_location.is_none()
+                && full_key.len() < path.len()
+                && keys_eq(&full_key, &path[..full_key.len()])
+            {
+                parent_location = location;
+            }
+        }
+    }
+    parent_location
+}
+
+/// Splits a dotted key
+fn split_key(key: &str) -> Vec<String> {
+    key.split('.')
+        .map(|k| k.trim().trim_matches('"').trim_matches('\'').to_string())
+        .collect()
+}
+
+/// Compares keys
+fn keys_eq(keys: &[String], path: &[&str]) -> bool {
+    keys.len() == path.len() && keys.iter().zip(path).all(|(a, b)| a == b)
+}
+
+/// Returns the field names of a struct (deriving `Deserialize`)
+pub(crate) fn struct_fields<T>() -> &'static [&'static str]
+where
+    T: DeserializeOwned,
+{
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldsDeserializer {
+        fields: &mut fields,
+    });
+    fields
+}
+
+/// Deserializer which only records the struct fields
+struct FieldsDeserializer<'a> {
+    fields: &'a mut &'static [&'static str],
+}
+
+impl<'de, 'a> Deserializer<'de> for FieldsDeserializer<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        *self.fields = fields;
+        Err(de::Error::custom("fields recorded"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locate_key() {
+        let text = "[doc]\ntitle = \"Title\"\n\n[output.html]\n  template = \"x\"\ndiagrams = { commands = {} }\n";
+        assert_eq!(locate_key(text, &["doc"]), Some((1, 1)));
+        assert_eq!(locate_key(text, &["doc", "title"]), Some((2, 1)));
+        assert_eq!(locate_key(text, &["output", "html"]), Some((4, 1)));
+        assert_eq!(
+            locate_key(text, &["output", "html", "template"]),
+            Some((5, 3))
+        );
+        // NB: keys of inline tables are located at their parent key
+        assert_eq!(
+            locate_key(text, &["output", "html", "diagrams", "commands"]),
+            Some((6, 1))
+        );
+        assert_eq!(locate_key(text, &["src"]), None);
+    }
+
+    #[test]
+    fn test_locate_dotted_key() {
+        let text =
+            "# comment\n[profile.release]\nbuild.build_dir = \"dist\"\n\"doc\".title = \"T\"\n";
+        assert_eq!(
+            locate_key(text, &["profile", "release", "build", "build_dir"]),
+            Some((3, 1))
+        );
+        assert_eq!(
+            locate_key(text, &["profile", "release", "doc", "title"]),
+            Some((4, 1))
+        );
+    }
+
+    #[test]
+    fn test_span_location() {
+        let text = "a = 1\nbb = 2\n";
+        assert_eq!(span_location(text, Some(0..1)), Some((1, 1)));
+        assert_eq!(span_location(text, Some(9..10)), Some((2, 4)));
+        assert_eq!(span_location(text, None), None);
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        let keys = ["build_dir", "src_dir", "assets_dir"];
+        assert_eq!(did_you_mean("buil_dir", &keys), Some("build_dir"));
+        assert_eq!(did_you_mean("asset_dir", &keys), Some("assets_dir"));
+        assert_eq!(did_you_mean("output", &keys), None);
+    }
+
+    #[test]
+    fn test_check_keys() {
+        let text = "[figures]\nlist_of_figure = true\n\n[callouts.security]\ntitle = \"Security\"\nicons = \"🔒\"\n";
+        let table = toml::from_str::<toml::Table>(text).unwrap();
+        let mut diags = vec![];
+        check_keys(text, &table, &[], &mut diags);
+        let keys = diags
+            .iter()
+            .map(|diag| (diag.key.as_deref(), diag.suggestion.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                (Some("callouts.security.icons"), Some("icon")),
+                (Some("figures.list_of_figure"), Some("list_of_figures")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_key_suggestion() {
+        let diag = ConfigDiagnostic::unknown_key(&["build", "buil_dir"], &["build_dir"]);
+        assert!(!diag.is_error());
+        assert_eq!(diag.key.as_deref(), Some("build.buil_dir"));
+        assert_eq!(diag.suggestion.as_deref(), Some("build_dir"));
+    }
+}
@@ -0,0 +1,49 @@
+//! Configuration JSON Schema
+
+use std::collections::BTreeMap;
+
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, RootSchema, Schema, SchemaObject},
+};
+
+use super::{Config, ConfigFile};
+
+impl Config {
+    /// Returns the JSON Schema of the config file, given the schemas of the outputs
+    ///
+    /// NB: the output schemas must have been generated with the same generator
+    pub fn schema(gen: SchemaGenerator, outputs: BTreeMap<String, Schema>) -> RootSchema {
+        let mut root = gen.into_root_schema_for::<ConfigFile>();
+        root.schema.metadata().title = Some(Self::FILE_NAME.to_string());
+
+        // outputs
+        let mut output = object_schema("Outputs (`[output.<id>]`), one per renderer");
+        output.object().properties.extend(outputs);
+        output.object().additional_properties = Some(Box::new(Schema::Bool(false)));
+
+        // profiles
+        let profiles = object_schema("Profiles (`[profile.<name>]`), merged over the config");
+
+        let object = root.schema.object();
+        object
+            .properties
+            .insert("output".to_string(), output.into());
+        object.required.insert("output".to_string());
+        object
+            .properties
+            .insert(Self::PROFILES_KEY.to_string(), profiles.into());
+
+        root
+    }
+}
+
+/// Returns an object schema with a description
+fn object_schema(description: &str) -> SchemaObject {
+    let mut schema = SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        ..Default::default()
+    };
+    schema.metadata().description = Some(description.to_string());
+    schema
+}
@@ -0,0 +1,203 @@
+//! External commands
+//!
+//! The diagram and doc test commands are command lines with `{input}` and `{output}`
+//! placeholders (eg `mmdc -i {input} -o {output}`). Arguments are split like a shell does
+//! (with quotes), and the placeholders are replaced after splitting, so that paths with spaces
+//! are a single argument.
+
+use std::{
+    fmt,
+    io::{Read, Write},
+    path::Path,
+    process::{Command, Output, Stdio},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// Timeout of a command
+pub(crate) const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Interval between the checks of a running command
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Error of a command running longer than its timeout
+#[derive(Debug)]
+pub(crate) struct CommandTimeout {
+    /// Program
+    program: String,
+    /// Timeout
+    timeout: Duration,
+}
+
+impl fmt::Display for CommandTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} timed out after {:?}", self.program, self.timeout)
+    }
+}
+
+impl std::error::Error for CommandTimeout {}
+
+/// Returns the arguments of a command line, with the placeholders replaced (eg `{input}`)
+pub(crate) fn command_args(command: &str, placeholders: &[(&str, &Path)]) -> Result<Vec<String>> {
+    let args = split_args(command)?;
+    if args.is_empty() {
+        bail!("Empty command");
+    }
+    Ok(args
+        .into_iter()
+        .map(|arg| {
+            placeholders.iter().fold(arg, |arg, (name, path)| {
+                arg.replace(&format!("{{{name}}}"), &path.to_string_lossy())
+            })
+        })
+        .collect())
+}
+
+/// Splits a command line into arguments (whitespace separated, with single or double quotes)
+fn split_args(command: &str) -> Result<Vec<String>> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => arg.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (None, c) => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        bail!("Unclosed quote in command `{command}`");
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+/// Runs a command with a stdin, and returns its output
+///
+/// NB: the stdin is written and the outputs are read in threads, so that a command filling
+/// a pipe does not block. A command running longer than the timeout is killed, and the error
+/// is a [CommandTimeout].
+pub(crate) fn run_command(
+    args: &[String],
+    stdin: &[u8],
+    dir: Option<&Path>,
+    timeout: Duration,
+) -> Result<Output> {
+    let (program, args) = args.split_first().ok_or(anyhow!("Empty command"))?;
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Failed to run {program}"))?;
+
+    if let Some(mut pipe) = child.stdin.take() {
+        let stdin = stdin.to_vec();
+        // NB: the command may exit without reading its stdin
+        thread::spawn(move || pipe.write_all(&stdin).ok());
+    }
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() > timeout {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(CommandTimeout {
+                program: program.to_string(),
+                timeout,
+            }
+            .into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// Reads a pipe to the end in a thread
+fn read_pipe(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut data = vec![];
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut data).ok();
+        }
+        data
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_args() {
+        let args = command_args(
+            "'my tool' -i {input} --title=\"A b\" -o {output}",
+            &[
+                ("input", Path::new("/my docs/a.mmd")),
+                ("output", Path::new("/my docs/a.svg")),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            [
+                "my tool",
+                "-i",
+                "/my docs/a.mmd",
+                "--title=A b",
+                "-o",
+                "/my docs/a.svg"
+            ]
+        );
+        assert_eq!(split_args("a '' b").unwrap(), ["a", "", "b"]);
+        assert!(command_args("  ", &[]).is_err());
+        assert!(command_args("a 'b", &[]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_command() {
+        // NB: the output is larger than a pipe buffer, so it must be read while stdin is written
+        let stdin = "x".repeat(1 << 20);
+        let args = command_args("cat", &[]).unwrap();
+        let output = run_command(&args, stdin.as_bytes(), None, COMMAND_TIMEOUT).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout.len(), stdin.len());
+
+        let args = command_args("sh -c 'pwd; exit 3'", &[]).unwrap();
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        let output = run_command(&args, b"", Some(&dir), COMMAND_TIMEOUT).unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap().trim(),
+            dir.to_string_lossy()
+        );
+
+        let args = command_args("sleep 5", &[]).unwrap();
+        let err = run_command(&args, b"", None, Duration::from_millis(100)).unwrap_err();
+        assert_eq!(err.to_string(), "sleep timed out after 100ms");
+        assert!(err.is::<CommandTimeout>());
+    }
+}
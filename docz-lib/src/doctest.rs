@@ -0,0 +1,406 @@
+//! Doc tests
+//!
+//! The code blocks of the test languages (`rust`, `sh`, `bash`, or `[doctest.languages]`) which
+//! are annotated as tests are run with local commands (`docz test`), so that the examples do not
+//! rot. Annotations follow the language (eg ```` ```rust,test ```` or
+//! ```` ```sh test should_fail ````):
+//! - `test`: the code block is run
+//! - `ignore`: the code block is not run (eg temporarily)
+//! - `should_fail`: the code block must fail (eg a panic)
+//!
+//! The commands run in the doc tests dir, and fail after a timeout. Hidden lines (eg
+//! `# use std::fs;` in Rust) are run, but not rendered.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use comrak::nodes::{AstNode, NodeValue};
+use log::trace;
+
+use crate::{
+    cfg::{Config, DoctestLanguageConfig},
+    cmd::{command_args, run_command, CommandTimeout, COMMAND_TIMEOUT},
+    i18n::strip_translations,
+    rend::comrak_options,
+    src::SourceFile,
+    Service,
+};
+
+/// Built-in test languages (language, command, extension, hidden lines prefix)
+const BUILTIN_LANGS: &[(&str, &str, &str, Option<&str>)] = &[
+    (
+        "rust",
+        "rustc --edition 2021 {input} -o {output}",
+        "rs",
+        Some("# "),
+    ),
+    ("sh", "sh {input}", "sh", None),
+    ("bash", "bash {input}", "sh", None),
+];
+
+/// Annotation of a code block which is run
+const TEST: &str = "test";
+
+/// Annotation of a code block which is not run
+const IGNORE: &str = "ignore";
+
+/// Annotation of a code block which must fail
+const SHOULD_FAIL: &str = "should_fail";
+
+/// Doc test (code block)
+#[derive(Debug, Clone)]
+pub struct DocTest {
+    /// Source file (relative to the source dir)
+    pub page: PathBuf,
+    /// Line of the code block (fence)
+    pub line: usize,
+    /// Language
+    pub lang: String,
+    /// Code (with the hidden lines)
+    pub code: String,
+    /// Not run (`ignore`)
+    pub ignore: bool,
+    /// Must fail (`should_fail`)
+    pub should_fail: bool,
+}
+
+/// Doc test result
+#[derive(Debug, Clone)]
+pub struct DocTestResult {
+    /// Test
+    pub test: DocTest,
+    /// Outcome
+    pub outcome: DocTestOutcome,
+}
+
+/// Doc test outcome
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DocTestOutcome {
+    /// Passed
+    Passed,
+    /// Failed (with the error output)
+    Failed(String),
+    /// Not run
+    Ignored,
+}
+
+impl Service {
+    /// Runs the doc tests (code blocks of the test languages, annotated with `test`)
+    pub fn test(&self) -> Result<Vec<DocTestResult>> {
+        let src_data = self.load_src_dir()?;
+        let files = strip_translations(&src_data.files, &self.config);
+        let tester = DocTester::new(&self.config);
+        let mut tests = vec![];
+        tester.collect_iter(&files, &self.config.src_dir(), &mut tests)?;
+
+        let mut results = vec![];
+        for (n, test) in tests.into_iter().enumerate() {
+            let outcome = if test.ignore {
+                DocTestOutcome::Ignored
+            } else {
+                trace!("Running doc test {}:{}", test.page.display(), test.line);
+                tester.run(&test, n)?
+            };
+            results.push(DocTestResult { test, outcome });
+        }
+        Ok(results)
+    }
+}
+
+/// Doc tester
+#[derive(Debug, Clone)]
+pub struct DocTester {
+    /// Languages
+    langs: BTreeMap<String, DoctestLanguageConfig>,
+    /// Working dir
+    dir: PathBuf,
+}
+
+impl DocTester {
+    /// Creates a doc tester (`[doctest]` section)
+    pub fn new(cfg: &Config) -> Self {
+        let mut langs = BUILTIN_LANGS
+            .iter()
+            .map(|(lang, command, ext, hidden)| {
+                (
+                    lang.to_string(),
+                    DoctestLanguageConfig {
+                        command: command.to_string(),
+                        ext: Some(ext.to_string()),
+                        hidden: hidden.map(|hidden| hidden.to_string()),
+                    },
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+        langs.extend(cfg.file().doctest.languages.clone());
+        Self {
+            langs,
+            dir: cfg.doctest_dir(),
+        }
+    }
+
+    /// Removes the hidden lines and the annotations of the test code blocks of a parsed page
+    ///
+    /// NB: the other code blocks are rendered as is
+    pub fn transform<'a>(&self, root: &'a AstNode<'a>) -> Result<()> {
+        for node in root.descendants() {
+            let mut data = node.data.borrow_mut();
+            let block = match &mut data.value {
+                NodeValue::CodeBlock(block) if block.fenced => block,
+                _ => continue,
+            };
+            let (lang, annotations) = parse_info(&block.info);
+            let lang_cfg = match self.langs.get(&lang) {
+                Some(lang_cfg) if annotations.contains(&TEST) => lang_cfg,
+                _ => continue,
+            };
+            block.info = block
+                .info
+                .split([',', ' ', '\t'])
+                .filter(|token| !token.is_empty() && !annotations.contains(token))
+                .collect::<Vec<_>>()
+                .join(" ");
+            if let Some(hidden) = &lang_cfg.hidden {
+                block.literal = block
+                    .literal
+                    .lines()
+                    .filter(|line| hidden_line(line, hidden).is_none())
+                    .map(|line| format!("{line}\n"))
+                    .collect();
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects the doc tests of the source files recursively
+    fn collect_iter(
+        &self,
+        src_files: &[SourceFile],
+        src_dir: &Path,
+        tests: &mut Vec<DocTest>,
+    ) -> Result<()> {
+        // NB: the front matter is not removed, so that the lines are the lines of the file
+        let mut opts = comrak_options();
+        opts.extension.front_matter_delimiter = None;
+        for src_file in src_files {
+            let page = src_file
+                .path
+                .strip_prefix(src_dir)
+                .unwrap_or(&src_file.path)
+                .to_owned();
+            let md = String::from_utf8(src_file.content.to_vec())?;
+            let arena = comrak::Arena::new();
+            let root = comrak::parse_document(&arena, &md, &opts);
+            for node in root.descendants() {
+                let data = node.data.borrow();
+                let block = match &data.value {
+                    NodeValue::CodeBlock(block) if block.fenced => block,
+                    _ => continue,
+                };
+                let (lang, annotations) = parse_info(&block.info);
+                if !self.langs.contains_key(&lang) || !annotations.contains(&TEST) {
+                    continue;
+                }
+                tests.push(DocTest {
+                    page: page.clone(),
+                    line: data.sourcepos.start.line,
+                    lang,
+                    code: block.literal.clone(),
+                    ignore: annotations.contains(&IGNORE),
+                    should_fail: annotations.contains(&SHOULD_FAIL),
+                });
+            }
+
+            self.collect_iter(&src_file.children, src_dir, tests)?;
+        }
+        Ok(())
+    }
+
+    /// Runs a doc test
+    ///
+    /// NB: `n` makes the file names of the test unique. An error is returned if the command
+    /// cannot be run, a failed test (or a test running longer than the timeout) is an outcome.
+    fn run(&self, test: &DocTest, n: usize) -> Result<DocTestOutcome> {
+        let lang_cfg = self.langs.get(&test.lang).ok_or(anyhow!(
+            "No command for the doc test language `{}`",
+            test.lang
+        ))?;
+        fs::create_dir_all(&self.dir).with_context(|| {
+            format!("Failed to create the doc tests dir {}", self.dir.display())
+        })?;
+        // NB: the paths are absolute, since the commands run in the doc tests dir
+        let dir = self.dir.canonicalize()?;
+
+        let code = test_code(test, lang_cfg);
+        let ext = lang_cfg.ext.as_deref().unwrap_or(&test.lang);
+        let output = dir.join(format!("doctest_{n}"));
+        let input = output.with_extension(ext);
+        let command = &lang_cfg.command;
+        let has_input = command.contains("{input}");
+        let has_output = command.contains("{output}");
+        if has_input {
+            fs::write(&input, &code)?;
+        }
+
+        let args = command_args(command, &[("input", &input), ("output", &output)])?;
+        let stdin = if has_input { "" } else { code.as_str() };
+        let mut result = run_command(&args, stdin.as_bytes(), Some(&dir), COMMAND_TIMEOUT);
+        // NB: a test which does not build fails, even if it should fail
+        let built = !has_output || result.as_ref().is_ok_and(|result| result.status.success());
+        if has_output && built {
+            let args = [output.to_string_lossy().to_string()];
+            result = run_command(&args, b"", Some(&dir), COMMAND_TIMEOUT);
+        }
+        fs::remove_file(&input).ok();
+        fs::remove_file(&output).ok();
+        let result = match result {
+            Ok(result) => result,
+            Err(err) if err.is::<CommandTimeout>() => {
+                return Ok(DocTestOutcome::Failed(err.to_string()))
+            }
+            Err(err) => return Err(err),
+        };
+
+        let outcome = match (result.status.success(), test.should_fail && built) {
+            (true, false) | (false, true) => DocTestOutcome::Passed,
+            (true, true) => DocTestOutcome::Failed("Succeeded, but should fail".to_string()),
+            (false, false) => {
+                let mut msg = String::from_utf8_lossy(&result.stderr).trim().to_string();
+                if msg.is_empty() {
+                    msg = String::from_utf8_lossy(&result.stdout).trim().to_string();
+                }
+                if msg.is_empty() {
+                    DocTestOutcome::Failed(result.status.to_string())
+                } else {
+                    DocTestOutcome::Failed(format!("{msg} ({})", result.status))
+                }
+            }
+        };
+        Ok(outcome)
+    }
+}
+
+/// Parses the info string of a code block (eg `rust,test,ignore`), and returns its language
+/// and annotations
+fn parse_info(info: &str) -> (String, Vec<&str>) {
+    let mut tokens = info
+        .split([',', ' ', '\t'])
+        .filter(|token| !token.is_empty());
+    let lang = tokens.next().unwrap_or("").to_string();
+    let annotations = tokens
+        .filter(|token| [TEST, IGNORE, SHOULD_FAIL].contains(token))
+        .collect();
+    (lang, annotations)
+}
+
+/// Returns the code of a hidden line without its prefix, if the line is hidden
+fn hidden_line<'a>(line: &'a str, hidden: &str) -> Option<&'a str> {
+    if line.trim_end() == hidden.trim_end() {
+        return Some("");
+    }
+    line.strip_prefix(hidden)
+}
+
+/// Returns the code run by a doc test (with the hidden lines)
+///
+/// NB: like rustdoc, Rust code without a `main` function is wrapped in one
+fn test_code(test: &DocTest, lang_cfg: &DoctestLanguageConfig) -> String {
+    let code = match &lang_cfg.hidden {
+        Some(hidden) => test
+            .code
+            .lines()
+            .map(|line| format!("{}\n", hidden_line(line, hidden).unwrap_or(line)))
+            .collect(),
+        None => test.code.clone(),
+    };
+    if test.lang == "rust" && !code.contains("fn main") {
+        format!("#![allow(unused)]\nfn main() {{\n{code}}}\n")
+    } else {
+        code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect() {
+        let tester = DocTester::new(&Config::default());
+        let mut file = SourceFile::new("src/01-intro.md");
+        file.content = b"```rust\nnot_run();\n```\n\n```rust,test,should_fail\npanic!();\n```\n\n```sh test ignore\nexit 1\n```\n\n```python,test\nprint()\n```\n".to_vec();
+        let mut tests = vec![];
+        tester
+            .collect_iter(&[file], Path::new("src"), &mut tests)
+            .unwrap();
+        // NB: only the annotated blocks of the test languages are collected
+        assert_eq!(tests.len(), 2);
+        assert_eq!(tests[0].page, PathBuf::from("01-intro.md"));
+        assert_eq!(tests[0].line, 5);
+        assert!(tests[0].should_fail && !tests[0].ignore);
+        assert_eq!(tests[1].lang, "sh");
+        assert!(tests[1].ignore);
+    }
+
+    #[test]
+    fn test_transform() {
+        let tester = DocTester::new(&Config::default());
+        let md =
+            "```rust,test\n# let a = 1;\nprintln!(\"{a}\");\n```\n\n```rust\n# not hidden\n```\n";
+        let opts = comrak_options();
+        let arena = comrak::Arena::new();
+        let root = comrak::parse_document(&arena, md, &opts);
+        tester.transform(root).unwrap();
+        let blocks = root
+            .descendants()
+            .filter_map(|node| match &node.data.borrow().value {
+                NodeValue::CodeBlock(block) => Some((block.info.clone(), block.literal.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            [
+                ("rust".to_string(), "println!(\"{a}\");\n".to_string()),
+                ("rust".to_string(), "# not hidden\n".to_string()),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run() {
+        let root_dir = std::env::temp_dir().join(format!("docz-doctest-{}", std::process::id()));
+        let mut cfg = Config::default();
+        cfg.set_root_dir(&root_dir);
+        let tester = DocTester::new(&cfg);
+        let test = |code: &str, should_fail: bool| DocTest {
+            page: PathBuf::from("01-intro.md"),
+            line: 1,
+            lang: "sh".to_string(),
+            code: code.to_string(),
+            ignore: false,
+            should_fail,
+        };
+
+        // NB: the commands run in the doc tests dir
+        let outcome = tester.run(&test("touch doctest_marker", false), 0).unwrap();
+        assert_eq!(outcome, DocTestOutcome::Passed);
+        assert!(cfg.doctest_dir().join("doctest_marker").exists());
+
+        let outcome = tester.run(&test("exit 1", true), 1).unwrap();
+        assert_eq!(outcome, DocTestOutcome::Passed);
+        let outcome = tester
+            .run(&test("echo oops >&2; exit 2", false), 2)
+            .unwrap();
+        assert_eq!(
+            outcome,
+            DocTestOutcome::Failed("oops (exit status: 2)".to_string())
+        );
+        fs::remove_dir_all(&root_dir).ok();
+    }
+}
@@ -0,0 +1,173 @@
+//! Git (read-only access to the local repository, through the `git` CLI)
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// Mode of the symlinks in a git tree
+const SYMLINK_MODE: &str = "120000";
+
+/// Git repository
+#[derive(Debug)]
+pub(crate) struct GitRepo {
+    /// Root of the working tree
+    root: PathBuf,
+}
+
+impl GitRepo {
+    /// Opens the repository containing a directory
+    pub(crate) fn open(dir: &Path) -> Result<Self> {
+        let output = git(dir, &["rev-parse", "--show-toplevel"])
+            .context("Versions require a git repository")?;
+        let root = PathBuf::from(String::from_utf8(output)?.trim());
+        Ok(Self { root })
+    }
+
+    /// Returns the tags matching a glob (highest version first)
+    pub(crate) fn tags(&self, glob: &str) -> Result<Vec<String>> {
+        let output = git(&self.root, &["tag", "--list", "--sort=-v:refname", glob])?;
+        Ok(String::from_utf8(output)?
+            .lines()
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect())
+    }
+
+    /// Returns a path relative to the root of the repository
+    pub(crate) fn relative_path(&self, path: &Path) -> Result<PathBuf> {
+        let root = self.root.canonicalize()?;
+        let path = path.canonicalize()?;
+        Ok(path
+            .strip_prefix(root)
+            .context("Source dir is not within the git repository")?
+            .to_owned())
+    }
+
+    /// Returns the time of the last commit which modified a path (unix timestamp)
+    pub(crate) fn last_commit_time(&self, path: &Path) -> Result<Option<i64>> {
+        let path = self.relative_path(path)?;
+        let path = path.to_string_lossy();
+        let output = git(&self.root, &["log", "-1", "--format=%ct", "--", &path])?;
+        Ok(String::from_utf8(output)?.trim().parse::<i64>().ok())
+    }
+
+    /// Extracts a directory of a git ref into a destination directory
+    pub(crate) fn extract(&self, git_ref: &str, dir: &Path, dest: &Path) -> Result<()> {
+        let dir = dir
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let mut args = vec!["ls-tree", "-r", "-z", "--full-tree", git_ref];
+        if !dir.is_empty() {
+            args.extend(["--", dir.as_str()]);
+        }
+        let output = git(&self.root, &args)
+            .with_context(|| format!("Failed to read the git ref {git_ref}"))?;
+
+        // <mode> SP <type> SP <object> TAB <path>
+        let mut entries = vec![];
+        for entry in output.split(|b| *b == 0).filter(|e| !e.is_empty()) {
+            let entry = String::from_utf8(entry.to_vec())?;
+            let (info, path) = entry
+                .split_once('\t')
+                .ok_or(anyhow!("Invalid git tree entry: {entry}"))?;
+            let info = info.split(' ').collect::<Vec<_>>();
+            if let [mode, "blob", oid] = info[..] {
+                if mode != SYMLINK_MODE {
+                    entries.push((oid.to_string(), path.to_string()));
+                }
+            }
+        }
+
+        let oids = entries
+            .iter()
+            .map(|(oid, _)| oid.as_str())
+            .collect::<Vec<_>>();
+        let blobs = self.read_blobs(&oids)?;
+        for ((_, path), blob) in entries.iter().zip(blobs) {
+            let rel_path = Path::new(path).strip_prefix(&dir)?;
+            let file = dest.join(rel_path);
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(file, blob)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads blobs from the object database
+    fn read_blobs(&self, oids: &[&str]) -> Result<Vec<Vec<u8>>> {
+        let mut child = Command::new("git")
+            .arg("-C")
+            .arg(&self.root)
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to run git")?;
+
+        // NB: stdin is written from another thread, so that stdout does not fill up
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or(anyhow!("Failed to open git stdin"))?;
+        let input = oids
+            .iter()
+            .map(|oid| format!("{oid}\n"))
+            .collect::<String>();
+        let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or(anyhow!("Failed to open git stdout"))?;
+        let mut stdout = BufReader::new(stdout);
+        let mut blobs = vec![];
+        for oid in oids {
+            // <oid> SP <type> SP <size> LF <contents> LF
+            let mut header = String::new();
+            stdout.read_line(&mut header)?;
+            let size = header
+                .split_whitespace()
+                .nth(2)
+                .and_then(|size| size.parse::<usize>().ok())
+                .ok_or(anyhow!("Failed to read git object {oid}"))?;
+            let mut blob = vec![0; size + 1];
+            stdout.read_exact(&mut blob)?;
+            blob.pop();
+            blobs.push(blob);
+        }
+
+        writer
+            .join()
+            .map_err(|_| anyhow!("Failed to write to git stdin"))??;
+        child.wait()?;
+        Ok(blobs)
+    }
+}
+
+/// Runs a git command and returns its output
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
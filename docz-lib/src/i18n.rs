@@ -0,0 +1,265 @@
+//! Internationalization
+//!
+//! Translations are either locale-suffixed source files (eg `01-intro.fr.md` next to `01-intro.md`),
+//! or files with the same relative path in a per-locale source dir (`[languages.dirs]`).
+//!
+//! The outline always comes from the default language, and untranslated pages fall back
+//! to the default language. Each locale is built into `build/html/<locale>/`.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{Context, Result};
+use log::{trace, warn};
+
+use crate::{
+    cfg::Config,
+    git::GitRepo,
+    src::{load_src_data, SourceData, SourceFile, SourceProvider},
+    Service,
+};
+
+/// Translation status
+#[derive(Debug, Clone)]
+pub struct TranslationStatus {
+    /// Locale
+    pub locale: String,
+    /// Source file of the default language (relative to the source dir)
+    pub page: PathBuf,
+    /// Translation file (relative to the root dir)
+    pub translation: Option<PathBuf>,
+    /// State
+    pub state: TranslationState,
+}
+
+/// Translation state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranslationState {
+    /// Not translated
+    Missing,
+    /// Translated before the last change of the source file
+    Outdated,
+    /// Up to date
+    UpToDate,
+}
+
+impl Service {
+    /// Builds the locales with the renderers which support it
+    ///
+    /// NB: the per-locale source dirs are loaded with `provider` (eg the filesystem for a version)
+    pub(crate) fn build_locales(
+        &self,
+        cfg: &Config,
+        src_data: &SourceData,
+        provider: &dyn SourceProvider,
+    ) -> Result<()> {
+        for locale in cfg.locales() {
+            trace!("Building locale {locale}");
+            let translations = self.load_translations(cfg, src_data, &locale, provider)?;
+
+            let mut locale_cfg = cfg.clone();
+            locale_cfg.set_locale(Some(&locale));
+            let locale_data = SourceData {
+                files: translate_files(&strip_translations(&src_data.files, cfg), &translations),
+                assets: src_data.assets.clone(),
+            };
+
+            for id in cfg.output_ids() {
+                match self.renderers.get(id) {
+                    Some(renderer) if renderer.localized() => {
+                        trace!("Rendering output ({id}) for locale {locale}");
+                        renderer.render(&locale_cfg, &locale_data)?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the status of the translations
+    pub fn i18n_status(&self) -> Result<Vec<TranslationStatus>> {
+        let src_data = self.load_src_dir()?;
+        let src_dir = self.config.src_dir();
+        let repo = GitRepo::open(&self.config.root_dir()).ok();
+
+        let mut pages = vec![];
+        collect_pages_iter(
+            &strip_translations(&src_data.files, &self.config),
+            &mut pages,
+        );
+
+        let mut status = vec![];
+        for locale in self.config.locales() {
+            let translations = self.load_translations(
+                &self.config,
+                &src_data,
+                &locale,
+                self.source_provider.as_ref(),
+            )?;
+            for page in &pages {
+                let translation = translations.get(page);
+                let state = match translation {
+                    None => TranslationState::Missing,
+                    Some(translation) => {
+                        match (
+                            modified_time(repo.as_ref(), page),
+                            modified_time(repo.as_ref(), &translation.path),
+                        ) {
+                            (Some(page_time), Some(translation_time))
+                                if translation_time < page_time =>
+                            {
+                                TranslationState::Outdated
+                            }
+                            _ => TranslationState::UpToDate,
+                        }
+                    }
+                };
+                status.push(TranslationStatus {
+                    locale: locale.clone(),
+                    page: page.strip_prefix(&src_dir).unwrap_or(page).to_owned(),
+                    translation: translation.map(|t| {
+                        t.path
+                            .strip_prefix(self.config.root_dir())
+                            .unwrap_or(&t.path)
+                            .to_owned()
+                    }),
+                    state,
+                });
+            }
+        }
+        Ok(status)
+    }
+
+    /// Loads the translations of a locale (by path of the source file of the default language)
+    fn load_translations(
+        &self,
+        cfg: &Config,
+        src_data: &SourceData,
+        locale: &str,
+        provider: &dyn SourceProvider,
+    ) -> Result<BTreeMap<PathBuf, SourceFile>> {
+        let mut translations = BTreeMap::new();
+
+        // locale-suffixed files
+        collect_suffixed_iter(&src_data.files, locale, &mut translations);
+
+        // per-locale source dir
+        let locale_dir = cfg
+            .file()
+            .languages
+            .as_ref()
+            .and_then(|languages| languages.dirs.get(locale));
+        if let Some(locale_dir) = locale_dir {
+            let mut locale_cfg = cfg.clone();
+            locale_cfg.set_src_dir(locale_dir);
+            let locale_src_dir = locale_cfg.src_dir();
+            let locale_data = load_src_data(provider, &self.source_formats, &locale_cfg)
+                .with_context(|| format!("Failed to load the sources of locale {locale}"))?;
+            let mut files = vec![];
+            flatten_files_iter(&locale_data.files, &mut files);
+            for file in files {
+                let rel_path = file.path.strip_prefix(&locale_src_dir)?;
+                translations.insert(cfg.src_dir().join(rel_path), file);
+            }
+        }
+
+        Ok(translations)
+    }
+}
+
+/// Removes the locale-suffixed files from the source files
+pub(crate) fn strip_translations(files: &[SourceFile], cfg: &Config) -> Vec<SourceFile> {
+    let locales = cfg.locales();
+    files
+        .iter()
+        .filter(|file| file_locale(&file.path, &locales).is_none())
+        .map(|file| SourceFile {
+            path: file.path.clone(),
+            content: file.content.clone(),
+            children: strip_translations(&file.children, cfg),
+        })
+        .collect()
+}
+
+/// Replaces the content of the source files with their translation
+fn translate_files(
+    files: &[SourceFile],
+    translations: &BTreeMap<PathBuf, SourceFile>,
+) -> Vec<SourceFile> {
+    files
+        .iter()
+        .map(|file| SourceFile {
+            path: file.path.clone(),
+            content: match translations.get(&file.path) {
+                Some(translation) => translation.content.clone(),
+                None => file.content.clone(),
+            },
+            children: translate_files(&file.children, translations),
+        })
+        .collect()
+}
+
+/// Returns the locale of a locale-suffixed file (eg `01-intro.fr.md`)
+fn file_locale<'a>(path: &Path, locales: &'a [String]) -> Option<&'a str> {
+    let stem = path.file_stem()?.to_str()?;
+    let (_, suffix) = stem.rsplit_once('.')?;
+    locales
+        .iter()
+        .find(|locale| locale.as_str() == suffix)
+        .map(|locale| locale.as_str())
+}
+
+/// Collects the locale-suffixed files of a locale recursively
+fn collect_suffixed_iter(
+    files: &[SourceFile],
+    locale: &str,
+    translations: &mut BTreeMap<PathBuf, SourceFile>,
+) {
+    let locales = [locale.to_string()];
+    for file in files {
+        if file_locale(&file.path, &locales).is_some() {
+            let stem = file.path.file_stem().unwrap_or_default().to_string_lossy();
+            let stem = stem.strip_suffix(&format!(".{locale}")).unwrap_or(&stem);
+            let mut path = file.path.with_file_name(stem);
+            if let Some(ext) = file.path.extension() {
+                path.set_extension(ext);
+            }
+            if translations.insert(path.clone(), file.clone()).is_some() {
+                warn!("Duplicate translation for {}", path.display());
+            }
+        }
+        collect_suffixed_iter(&file.children, locale, translations);
+    }
+}
+
+/// Flattens the source files
+fn flatten_files_iter(files: &[SourceFile], flat: &mut Vec<SourceFile>) {
+    for file in files {
+        flat.push(file.clone());
+        flatten_files_iter(&file.children, flat);
+    }
+}
+
+/// Collects the paths of the pages
+fn collect_pages_iter(files: &[SourceFile], pages: &mut Vec<PathBuf>) {
+    for file in files {
+        pages.push(file.path.clone());
+        collect_pages_iter(&file.children, pages);
+    }
+}
+
+/// Returns the last modification time of a file (unix timestamp)
+///
+/// NB: the time of the last commit is used when available, as file times are reset by a clone
+fn modified_time(repo: Option<&GitRepo>, path: &Path) -> Option<i64> {
+    if let Some(time) = repo.and_then(|repo| repo.last_commit_time(path).ok().flatten()) {
+        return Some(time);
+    }
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64)
+}
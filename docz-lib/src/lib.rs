@@ -2,30 +2,55 @@
 
 pub mod build;
 pub mod cfg;
+mod cmd;
+pub mod doctest;
+mod git;
+pub mod i18n;
 pub mod rend;
 pub mod serve;
 pub mod src;
+pub mod vars;
+pub mod versions;
 pub mod watch;
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     env, fs,
     path::{Path, PathBuf},
 };
 
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
 
-use cfg::Config;
-use log::trace;
-use rend::{DebugRenderer, HTMLRenderer, Renderer};
+use cfg::{Config, ConfigDiagnostic};
+use log::{trace, warn};
+use rend::{
+    DebugRenderer, HTMLRenderer, JSONRenderer, Renderer, RendererEntry, ServiceRenderer,
+    SlidesRenderer, TextRenderer,
+};
+use schemars::{gen::SchemaGenerator, schema::RootSchema};
+use src::{builtin_formats, FsSourceProvider, SourceFormat, SourceProvider};
 
 /// Documentation service
-#[derive(Default)]
 pub struct Service {
     /// Config
     config: Config,
     /// Renderers
-    renderers: HashMap<String, Box<dyn Renderer + Send + Sync>>,
+    renderers: HashMap<String, Box<dyn ServiceRenderer>>,
+    /// Source provider
+    source_provider: Box<dyn SourceProvider>,
+    /// Source formats
+    source_formats: Vec<Box<dyn SourceFormat>>,
+}
+
+impl Default for Service {
+    fn default() -> Self {
+        Self {
+            config: Config::default(),
+            renderers: HashMap::new(),
+            source_provider: Box::new(FsSourceProvider::new()),
+            source_formats: builtin_formats(),
+        }
+    }
 }
 
 impl Service {
@@ -96,15 +121,27 @@ impl Service {
 pub struct ServiceBuilder {
     /// Root dir
     root_dir: PathBuf,
+    /// Config profile
+    profile: Option<String>,
+    /// Variables overrides
+    vars: Vec<(String, String)>,
     /// Renderers
-    renderers: HashMap<String, Box<dyn Renderer + Send + Sync>>,
+    renderers: HashMap<String, Box<dyn ServiceRenderer>>,
+    /// Source provider
+    source_provider: Option<Box<dyn SourceProvider>>,
+    /// Source formats (other than the built-in formats)
+    source_formats: Vec<Box<dyn SourceFormat>>,
 }
 
 impl Default for ServiceBuilder {
     fn default() -> Self {
         Self {
             root_dir: env::current_dir().unwrap(),
+            profile: None,
+            vars: vec![],
             renderers: HashMap::new(),
+            source_provider: None,
+            source_formats: vec![],
         }
     }
 }
@@ -116,9 +153,34 @@ impl ServiceBuilder {
         self
     }
 
+    /// Sets the config profile
+    pub fn profile(mut self, profile: &str) -> Self {
+        self.profile = Some(profile.to_string());
+        self
+    }
+
+    /// Sets a variable (overrides the `[variables]` section)
+    pub fn var(mut self, key: &str, value: &str) -> Self {
+        self.vars.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sets the source provider (the filesystem by default)
+    pub fn source_provider(mut self, provider: impl SourceProvider + 'static) -> Self {
+        self.source_provider = Some(Box::new(provider));
+        self
+    }
+
+    /// Adds a source format (takes precedence over the built-in formats)
+    pub fn source_format(mut self, format: impl SourceFormat + 'static) -> Self {
+        self.source_formats.push(Box::new(format));
+        self
+    }
+
     /// Adds a renderer
-    pub fn renderer(mut self, id: &str, renderer: impl Renderer + Send + Sync + 'static) -> Self {
-        self.renderers.insert(id.into(), Box::new(renderer));
+    pub fn renderer(mut self, id: &str, renderer: impl Renderer + 'static) -> Self {
+        self.renderers
+            .insert(id.into(), Box::new(RendererEntry::new(id, renderer)));
         self
     }
 
@@ -128,12 +190,30 @@ impl ServiceBuilder {
         self.renderer("debug", dbg_renderer)
     }
 
+    /// Adds the JSON renderer
+    pub fn json_renderer(self) -> Self {
+        let json_renderer = JSONRenderer::new();
+        self.renderer("json", json_renderer)
+    }
+
     /// Adds the HTML renderer
     pub fn html_renderer(self) -> Self {
         let html_renderer = HTMLRenderer::default();
         self.renderer("html", html_renderer)
     }
 
+    /// Adds the slides renderer
+    pub fn slides_renderer(self) -> Self {
+        let slides_renderer = SlidesRenderer::new();
+        self.renderer("slides", slides_renderer)
+    }
+
+    /// Adds the text renderer
+    pub fn text_renderer(self) -> Self {
+        let text_renderer = TextRenderer::new();
+        self.renderer("text", text_renderer)
+    }
+
     /// Adds the EPUB renderer
     #[cfg(feature = "epub")]
     pub fn epub_renderer(self) -> Self {
@@ -141,17 +221,123 @@ impl ServiceBuilder {
         self.renderer("epub", epub_renderer)
     }
 
+    /// Adds the DOCX renderer
+    #[cfg(feature = "docx")]
+    pub fn docx_renderer(self) -> Self {
+        let docx_renderer = rend::DocxRenderer::default();
+        self.renderer("docx", docx_renderer)
+    }
+
     /// Builds the service
     pub fn build(mut self) -> Result<Service> {
         let mut service = Service::default();
         service.config.set_root_dir(&self.root_dir);
+        service.config.set_profile(self.profile.as_deref());
+        for (key, value) in &self.vars {
+            service.config.set_var(key, value);
+        }
         service.config.load_file()?;
+        service.source_formats = self.source_formats;
+        service.source_formats.extend(builtin_formats());
+        let page_extensions = service
+            .source_formats
+            .iter()
+            .flat_map(|format| format.extensions().iter().map(|ext| ext.to_string()))
+            .collect();
+        service.config.set_page_extensions(page_extensions);
         trace!("Service root is: {}", service.config.root_dir().display());
+
+        // validate the output configs
+        let mut errors = vec![];
+        for renderer in self.renderers.values() {
+            for diag in renderer.check_config(&service.config) {
+                if diag.is_error() {
+                    errors.push(diag.to_string());
+                } else {
+                    warn!("{diag}");
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(anyhow!("Invalid output config\n{}", errors.join("\n")));
+        }
+
         for (id, renderer) in self.renderers.iter_mut() {
             trace!("Registering renderer ({id})");
             renderer.register(&service.config)?;
         }
         service.renderers = self.renderers;
+        if let Some(source_provider) = self.source_provider {
+            service.source_provider = source_provider;
+        }
         Ok(service)
     }
+
+    /// Returns the JSON Schema of the config file (including the outputs of the renderers)
+    pub fn config_schema(&self) -> RootSchema {
+        let mut gen = SchemaGenerator::default();
+        let outputs = self
+            .renderers
+            .iter()
+            .map(|(id, renderer)| (id.to_string(), renderer.config_schema(&mut gen)))
+            .collect::<BTreeMap<_, _>>();
+        Config::schema(gen, outputs)
+    }
+
+    /// Checks the config file and the output configs (without building the service)
+    pub fn check(self) -> Vec<ConfigDiagnostic> {
+        let mut config = Config::default();
+        config.set_root_dir(&self.root_dir);
+        config.set_profile(self.profile.as_deref());
+
+        let mut diags = config.check();
+        if diags.iter().any(|diag| diag.is_error()) {
+            return diags;
+        }
+        if let Err(err) = config.load_file() {
+            diags.push(ConfigDiagnostic::error(None, format!("{err:#}")));
+            return diags;
+        }
+        if let Err(err) = rend::load_bibliography(&config) {
+            let path = ["citations", "bibliography"];
+            diags.push(
+                ConfigDiagnostic::error(Some(path.join(".")), format!("{err:#}"))
+                    .at(config.locate_key(&path)),
+            );
+        }
+        if let Err(err) = rend::load_glossary(&config) {
+            let path = ["glossary", "file"];
+            diags.push(
+                ConfigDiagnostic::error(Some(path.join(".")), format!("{err:#}"))
+                    .at(config.locate_key(&path)),
+            );
+        }
+
+        let renderer_ids = self
+            .renderers
+            .keys()
+            .map(|id| id.as_str())
+            .collect::<Vec<_>>();
+        let mut output_ids = config.output_ids();
+        output_ids.sort();
+        for id in output_ids {
+            match self.renderers.get(id) {
+                Some(renderer) => diags.extend(renderer.check_config(&config)),
+                None => {
+                    let path = ["output", id];
+                    let suggestion = cfg::did_you_mean(id, &renderer_ids).map(|id| id.to_string());
+                    diags.push(
+                        ConfigDiagnostic::error(
+                            Some(path.join(".")),
+                            format!("no renderer for output `{id}`"),
+                        )
+                        .at(config.locate_key(&path))
+                        .suggest(suggestion),
+                    );
+                }
+            }
+        }
+
+        diags
+    }
 }
@@ -1,26 +1,200 @@
 //! Rendering
 
+mod book_index;
+mod callout;
+mod citation;
 mod dbg;
+mod diagram;
+#[cfg(feature = "docx")]
+mod docx;
 #[cfg(feature = "epub")]
 mod epub;
+mod figure;
+mod glossary;
 mod html;
+mod json;
+mod math;
+mod slides;
+mod tabs;
+mod text;
 
+pub use book_index::*;
+pub use callout::*;
+pub use citation::*;
 pub use dbg::*;
+pub use diagram::*;
+#[cfg(feature = "docx")]
+pub use docx::*;
 #[cfg(feature = "epub")]
 pub use epub::*;
+pub use figure::*;
+pub use glossary::*;
 pub use html::*;
+pub use json::*;
+pub use math::*;
+pub use slides::*;
+pub use text::*;
 
 use anyhow::Result;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{de::DeserializeOwned, Deserialize};
 
-use crate::{cfg::Config, src::SourceData};
+use crate::{
+    cfg::{Config, ConfigDiagnostic},
+    src::SourceData,
+};
 
 /// Renderer
-pub trait Renderer: Send {
+pub trait Renderer: Send + Sync {
+    /// Output config (`[output.<id>]` in doc.toml)
+    type Config: OutputConfig;
+
     /// Registers the renderer (optional)
-    fn register(&mut self, _cfg: &Config) -> Result<()> {
+    fn register(&mut self, _cfg: &Config, _out_cfg: &Self::Config) -> Result<()> {
         Ok(())
     }
 
+    /// Checks the output config beyond its type (optional)
+    ///
+    /// NB: the diagnostic keys are relative to the output section (eg `template`)
+    fn check_config(&self, _cfg: &Config, _out_cfg: &Self::Config) -> Vec<ConfigDiagnostic> {
+        vec![]
+    }
+
+    /// Renders
+    fn render(&self, cfg: &Config, out_cfg: &Self::Config, data: &SourceData) -> Result<()>;
+
+    /// Renders the versions built from git (optional)
+    ///
+    /// NB: the version being rendered is given by [Config::version]
+    fn versioned(&self) -> bool {
+        false
+    }
+
+    /// Aliases a rendered version (eg `latest`), instead of rendering it again (optional)
+    ///
+    /// NB: `cfg` is the config of the rendered version (see [Config::version])
+    fn alias_version(&self, _cfg: &Config, _alias: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Renders the locales of the `[languages]` section (optional)
+    ///
+    /// NB: the locale being rendered is given by [Config::locale]
+    fn localized(&self) -> bool {
+        false
+    }
+}
+
+/// Output config of a renderer
+///
+/// It is deserialized from `[output.<id>]`, falls back to its default when the section is missing,
+/// and its schema is part of the doc.toml JSON Schema.
+pub trait OutputConfig: DeserializeOwned + JsonSchema + Default + Send + Sync {}
+
+impl<T> OutputConfig for T where T: DeserializeOwned + JsonSchema + Default + Send + Sync {}
+
+/// Output config of the renderers without options
+#[derive(Debug, Deserialize, JsonSchema, Default)]
+pub struct EmptyOutputConfig {}
+
+/// Renderer registered with the service, along with its output config
+pub(crate) trait ServiceRenderer: Send + Sync {
+    /// Parses the output config and registers the renderer
+    fn register(&mut self, cfg: &Config) -> Result<()>;
+
+    /// Checks the output config
+    fn check_config(&self, cfg: &Config) -> Vec<ConfigDiagnostic>;
+
+    /// Returns the schema of the output config
+    fn config_schema(&self, gen: &mut SchemaGenerator) -> Schema;
+
     /// Renders
     fn render(&self, cfg: &Config, data: &SourceData) -> Result<()>;
+
+    /// Renders the versions built from git
+    fn versioned(&self) -> bool;
+
+    /// Aliases a rendered version
+    fn alias_version(&self, cfg: &Config, alias: &str) -> Result<()>;
+
+    /// Renders the locales
+    fn localized(&self) -> bool;
+}
+
+/// Renderer with its output config
+pub(crate) struct RendererEntry<R>
+where
+    R: Renderer,
+{
+    /// Output ID
+    id: String,
+    /// Renderer
+    renderer: R,
+    /// Output config
+    out_cfg: R::Config,
+}
+
+impl<R> RendererEntry<R>
+where
+    R: Renderer,
+{
+    /// Creates a new entry (with the default output config)
+    pub(crate) fn new(id: &str, renderer: R) -> Self {
+        Self {
+            id: id.to_string(),
+            renderer,
+            out_cfg: R::Config::default(),
+        }
+    }
+}
+
+impl<R> ServiceRenderer for RendererEntry<R>
+where
+    R: Renderer,
+{
+    fn register(&mut self, cfg: &Config) -> Result<()> {
+        self.out_cfg = cfg
+            .get_output_cfg::<R::Config>(&self.id)?
+            .unwrap_or_default();
+        self.renderer.register(cfg, &self.out_cfg)
+    }
+
+    fn check_config(&self, cfg: &Config) -> Vec<ConfigDiagnostic> {
+        let mut diags = cfg.check_output_cfg::<R::Config>(&self.id);
+        if let Ok(Some(out_cfg)) = cfg.get_output_cfg::<R::Config>(&self.id) {
+            for mut diag in self.renderer.check_config(cfg, &out_cfg) {
+                let mut path = vec!["output", self.id.as_str()];
+                if let Some(key) = &diag.key {
+                    path.extend(key.split('.'));
+                }
+                if diag.location.is_none() {
+                    diag.location = cfg.locate_key(&path);
+                }
+                diag.key = Some(path.join("."));
+                diags.push(diag);
+            }
+        }
+        diags
+    }
+
+    fn config_schema(&self, gen: &mut SchemaGenerator) -> Schema {
+        gen.subschema_for::<R::Config>()
+    }
+
+    fn render(&self, cfg: &Config, data: &SourceData) -> Result<()> {
+        self.renderer.render(cfg, &self.out_cfg, data)
+    }
+
+    fn versioned(&self) -> bool {
+        self.renderer.versioned()
+    }
+
+    fn alias_version(&self, cfg: &Config, alias: &str) -> Result<()> {
+        self.renderer.alias_version(cfg, alias)
+    }
+
+    fn localized(&self) -> bool {
+        self.renderer.localized()
+    }
 }
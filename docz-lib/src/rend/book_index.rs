@@ -0,0 +1,406 @@
+//! Back-of-book index
+//!
+//! Index entries are inline markers (eg `{{index "memory safety"}}`), or the `keywords` of the
+//! page frontmatter. A `!` separates an entry from its sub-entry (eg `memory safety!borrow checker`).
+//! The entries are collected across the pages into a generated, alphabetized index page. The other
+//! outputs (eg text, DOCX) only remove the markers.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use comrak::nodes::{AstNode, NodeValue};
+use slug::slugify;
+
+use crate::{
+    cfg::Config,
+    src::{FileMetadata, SourceFile},
+};
+
+use super::{
+    comrak_options, escape_xml, frontmatter, node_text, page_path, relative_href, UIStrings,
+    RAW_HTML_MARKER,
+};
+
+/// File name (stem) of the generated index page
+///
+/// NB: `index` pages are the pages of the source dirs
+const INDEX_PAGE: &str = "book-index";
+
+/// Default title of the generated index page
+const DEFAULT_TITLE: &str = "Index";
+
+/// Index entry
+#[derive(Debug, Default, Clone)]
+struct IndexEntry {
+    /// Term (as first written)
+    term: String,
+    /// Locations
+    locations: Vec<Location>,
+    /// Sub-entries, by sort key
+    children: BTreeMap<String, IndexEntry>,
+}
+
+/// Location of an index entry
+#[derive(Debug, Clone, PartialEq)]
+struct Location {
+    /// Output path of the page
+    page: PathBuf,
+    /// Anchor of the heading, if any
+    anchor: Option<String>,
+    /// Label (heading or page title)
+    label: String,
+}
+
+/// Back-of-book index of a doc
+#[derive(Debug, Default, Clone)]
+pub struct BookIndex {
+    /// Entries, by sort key
+    entries: BTreeMap<String, IndexEntry>,
+    /// Output path of the index page
+    page: PathBuf,
+    /// Links the entries to the heading anchors (or to the pages)
+    anchors: bool,
+}
+
+impl BookIndex {
+    /// Collects the index entries of the source files
+    ///
+    /// NB: `ext` is the extension of the output pages (eg `html`)
+    pub fn new(src_files: &[SourceFile], src_dir: &Path, ext: &str) -> Result<Self> {
+        let mut index = Self {
+            page: page_path(&src_dir.join(format!("{INDEX_PAGE}.md")), src_dir, ext)?,
+            ..Default::default()
+        };
+        index.collect_iter(src_files, src_dir, ext)?;
+        Ok(index)
+    }
+
+    /// Links the entries to the anchors of the headings which precede the markers
+    pub fn with_anchors(mut self) -> Self {
+        self.anchors = true;
+        self
+    }
+
+    /// Collects the index entries recursively
+    fn collect_iter(&mut self, src_files: &[SourceFile], src_dir: &Path, ext: &str) -> Result<()> {
+        let opts = comrak_options();
+        for src_file in src_files {
+            let page = page_path(&src_file.path, src_dir, ext)?;
+            let md = String::from_utf8(src_file.content.to_vec())?;
+            let arena = comrak::Arena::new();
+            let root = comrak::parse_document(&arena, &md, &opts);
+
+            let metadata = match frontmatter(root)? {
+                Some(fm) => serde_yaml::from_str::<FileMetadata>(&fm)?,
+                None => FileMetadata::default(),
+            };
+            let page_title = metadata.title.clone().unwrap_or_else(|| {
+                src_file
+                    .path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            });
+
+            // keywords
+            for keyword in &metadata.keywords {
+                self.add(
+                    keyword,
+                    Location {
+                        page: page.clone(),
+                        anchor: None,
+                        label: page_title.clone(),
+                    },
+                );
+            }
+
+            // markers
+            let mut heading = None;
+            for node in root.descendants() {
+                let text = match &node.data.borrow().value {
+                    NodeValue::Heading(..) => {
+                        heading = Some(strip_markers(&node_text(node)).trim().to_string());
+                        continue;
+                    }
+                    NodeValue::Text(text) if text.contains("{{") => text.to_string(),
+                    _ => continue,
+                };
+                for (_, term) in markers(&text) {
+                    let location = match &heading {
+                        Some(heading) => Location {
+                            page: page.clone(),
+                            anchor: Some(slugify(heading)),
+                            label: format!("{page_title} › {heading}"),
+                        },
+                        None => Location {
+                            page: page.clone(),
+                            anchor: None,
+                            label: page_title.clone(),
+                        },
+                    };
+                    self.add(term, location);
+                }
+            }
+
+            self.collect_iter(&src_file.children, src_dir, ext)?;
+        }
+        Ok(())
+    }
+
+    /// Adds an entry (eg `memory safety!borrow checker`)
+    fn add(&mut self, term: &str, location: Location) {
+        let parts = term
+            .split('!')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>();
+        add_entry(&mut self.entries, &parts, location);
+    }
+
+    /// Removes the index markers of a parsed page, and anchors the headings which precede them
+    ///
+    /// NB: the anchors are only added if the entries link to them (see [BookIndex::with_anchors])
+    pub fn transform<'a>(&self, root: &'a AstNode<'a>) -> Result<()> {
+        let mut heading = None;
+        let mut anchored: Vec<&'a AstNode<'a>> = vec![];
+        for node in root.descendants() {
+            let text = match &node.data.borrow().value {
+                NodeValue::Heading(..) => {
+                    heading = Some(node);
+                    continue;
+                }
+                NodeValue::Text(text) if text.contains("{{") => text.to_string(),
+                _ => continue,
+            };
+            if markers(&text).is_empty() {
+                continue;
+            }
+            node.data.borrow_mut().value = NodeValue::Text(strip_markers(&text));
+
+            if let Some(heading) = heading {
+                if self.anchors && !anchored.iter().any(|h| std::ptr::eq(*h, heading)) {
+                    anchored.push(heading);
+                }
+            }
+        }
+
+        for heading in anchored {
+            let id = slugify(node_text(heading).trim());
+            let first_text = heading
+                .descendants()
+                .find(|desc| matches!(desc.data.borrow().value, NodeValue::Text(..)));
+            if let Some(first_text) = first_text {
+                let mut data = first_text.data.borrow_mut();
+                if let NodeValue::Text(text) = &data.value {
+                    let html = format!(
+                        "<span id=\"{}\"></span>{}",
+                        escape_xml(&id),
+                        escape_xml(text)
+                    );
+                    data.value = NodeValue::HtmlInline(html);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the generated index page, if there are entries
+    pub fn list_pages(&self, cfg: &Config) -> Vec<SourceFile> {
+        if self.entries.is_empty() {
+            return vec![];
+        }
+        let title = cfg
+            .file()
+            .index
+            .title
+            .clone()
+            .or(UIStrings::from_config(cfg).get("index").map(String::from))
+            .unwrap_or(DEFAULT_TITLE.to_string());
+
+        // NB: the index is a raw HTML block, so it must not contain blank lines
+        let mut md = format!(
+            "---\ntitle: {}\n---\n\n# {title}\n\n<div class=\"book-index\">\n",
+            serde_json::to_string(&title).unwrap_or_default()
+        );
+        let mut letter = None;
+        for entry in self.entries.values() {
+            let entry_letter = entry
+                .term
+                .chars()
+                .next()
+                .map(|c| c.to_uppercase().to_string());
+            if entry_letter != letter {
+                if letter.is_some() {
+                    md.push_str("</ul>\n");
+                }
+                md.push_str(&format!(
+                    "<h2>{}</h2>\n<ul>\n",
+                    escape_xml(entry_letter.as_deref().unwrap_or(""))
+                ));
+                letter = entry_letter;
+            }
+            self.format_entry(entry, &mut md);
+        }
+        md.push_str(&format!("</ul>\n</div>{RAW_HTML_MARKER}\n"));
+
+        let mut page = SourceFile::new(cfg.src_dir().join(format!("{INDEX_PAGE}.md")));
+        page.content = md.into_bytes();
+        vec![page]
+    }
+
+    /// Formats an entry and its sub-entries (HTML list item)
+    fn format_entry(&self, entry: &IndexEntry, html: &mut String) {
+        html.push_str(&format!(
+            "<li><span class=\"index-term\">{}</span>",
+            escape_xml(&entry.term)
+        ));
+        let links = entry
+            .locations
+            .iter()
+            .map(|location| {
+                let mut href = relative_href(&self.page, &location.page);
+                if let Some(anchor) = location.anchor.as_ref().filter(|_| self.anchors) {
+                    href.push('#');
+                    href.push_str(anchor);
+                }
+                format!(
+                    "<a href=\"{}\">{}</a>",
+                    escape_xml(&href),
+                    escape_xml(&location.label)
+                )
+            })
+            .collect::<Vec<_>>();
+        if !links.is_empty() {
+            html.push_str(": ");
+            html.push_str(&links.join(", "));
+        }
+        if !entry.children.is_empty() {
+            html.push_str("\n<ul>\n");
+            for child in entry.children.values() {
+                self.format_entry(child, html);
+            }
+            html.push_str("</ul>\n");
+        }
+        html.push_str("</li>\n");
+    }
+}
+
+/// Adds an entry and its sub-entries (eg `["memory safety", "borrow checker"]`)
+fn add_entry(entries: &mut BTreeMap<String, IndexEntry>, parts: &[&str], location: Location) {
+    let (part, rest) = match parts.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let entry = entries
+        .entry(part.to_lowercase())
+        .or_insert_with(|| IndexEntry {
+            term: part.to_string(),
+            ..Default::default()
+        });
+    if !rest.is_empty() {
+        add_entry(&mut entry.children, rest, location);
+    } else if !entry.locations.contains(&location) {
+        entry.locations.push(location);
+    }
+}
+
+/// Removes the index markers of a parsed page, for the outputs without an index (eg text)
+pub fn strip_index_markers<'a>(root: &'a AstNode<'a>) {
+    for node in root.descendants() {
+        if let NodeValue::Text(text) = &mut node.data.borrow_mut().value {
+            if text.contains("{{") {
+                *text = strip_markers(text);
+            }
+        }
+    }
+}
+
+/// Removes the index markers of a text
+pub(crate) fn strip_markers(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut pos = 0;
+    for ((start, end), _) in markers(text) {
+        stripped.push_str(&text[pos..start]);
+        pos = end;
+    }
+    stripped.push_str(&text[pos..]);
+    stripped
+}
+
+/// Returns the index markers of a text (byte range and term), eg `{{index "memory safety"}}`
+fn markers(text: &str) -> Vec<((usize, usize), &str)> {
+    let mut markers = vec![];
+    let mut pos = 0;
+    while let Some(start) = text[pos..].find("{{").map(|i| pos + i) {
+        let end = match text[start..].find("}}") {
+            Some(i) => start + i + 2,
+            None => break,
+        };
+        let term = text[start + 2..end - 2]
+            .trim()
+            .strip_prefix("index")
+            .map(str::trim)
+            .and_then(|term| term.strip_prefix('"'))
+            .and_then(|term| term.strip_suffix('"'));
+        match term {
+            Some(term) => {
+                markers.push(((start, end), term));
+                pos = end;
+            }
+            None => pos = start + 2,
+        }
+    }
+    markers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_index_markers() {
+        let opts = comrak_options();
+        let arena = comrak::Arena::new();
+        let root = comrak::parse_document(
+            &arena,
+            "# Memory{{index \"memory\"}}\n\nSafe.{{index \"memory!safety\"}} {{ var.x }}\n",
+            &opts,
+        );
+        strip_index_markers(root);
+        assert_eq!(node_text(root.first_child().unwrap()), "Memory");
+        assert_eq!(node_text(root.last_child().unwrap()), "Safe. {{ var.x }}");
+    }
+
+    #[test]
+    fn test_list_pages() {
+        let cfg = Config::default();
+        let src_dir = cfg.src_dir();
+        let mut file = SourceFile::new(src_dir.join("01-intro.md"));
+        file.content = b"# Intro\n\nSafe.{{index \"memory!safety\"}}\n".to_vec();
+        let index = BookIndex::new(&[file], &src_dir, "html").unwrap();
+
+        let pages = index.list_pages(&cfg);
+        let md = String::from_utf8(pages[0].content.to_vec()).unwrap();
+        assert!(md.starts_with("---\ntitle: \"Index\"\n---\n\n# Index\n"));
+        assert!(md.contains("memory"));
+        assert!(md.contains("safety"));
+    }
+
+    #[test]
+    fn test_translated_title() {
+        let mut cfg = Config::default();
+        cfg.set_locale(Some("de"));
+        let src_dir = cfg.src_dir();
+        let mut file = SourceFile::new(src_dir.join("01-intro.md"));
+        file.content = b"# Intro\n\nSafe.{{index \"memory\"}}\n".to_vec();
+        let index = BookIndex::new(&[file], &src_dir, "html").unwrap();
+
+        let pages = index.list_pages(&cfg);
+        assert!(pages[0].path.ends_with("book-index.md"));
+        let md = String::from_utf8(pages[0].content.to_vec()).unwrap();
+        assert!(md.contains("\n# Stichwortverzeichnis\n"));
+    }
+}
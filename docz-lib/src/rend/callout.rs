@@ -0,0 +1,437 @@
+//! Callouts
+//!
+//! Callouts are GitHub-style alerts (`> [!NOTE]`) or fenced containers (`:::tip`), with an optional
+//! title after the type (eg `> [!TIP] Shortcut`). A `-` or `+` after the type makes the callout
+//! collapsible (collapsed or expanded, eg `> [!NOTE]- Details`).
+//!
+//! NB: callouts are converted to raw HTML around their Markdown content, before parsing (or to
+//! blockquotes, for the print outputs).
+
+use std::collections::BTreeMap;
+
+use log::warn;
+
+use crate::cfg::Config;
+
+use super::{
+    escape_xml,
+    tabs::{render_tabs, TAB_KINDS},
+    UIStrings, RAW_HTML_MARKER,
+};
+
+/// Built-in callout types (type, UI string of the title, icon)
+const BUILTIN_TYPES: &[(&str, &str, &str)] = &[
+    ("note", "callout_note", "ℹ️"),
+    ("tip", "callout_tip", "💡"),
+    ("important", "callout_important", "❗"),
+    ("warning", "callout_warning", "⚠️"),
+    ("caution", "callout_caution", "⛔"),
+];
+
+/// Markup of the callouts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalloutMarkup {
+    /// HTML (collapsible callouts are `<details>` elements, and tab groups are tabs)
+    HTML,
+    /// XHTML for EPUB (callouts are always expanded, and tabs are sequential)
+    XHTML,
+    /// Markdown for the print outputs (callouts are blockquotes, and tabs are sequential)
+    Markdown,
+}
+
+/// Callout type
+#[derive(Debug, Clone)]
+struct CalloutType {
+    /// Default title
+    title: String,
+    /// Icon
+    icon: String,
+}
+
+/// Callout
+#[derive(Debug)]
+struct Callout<'a> {
+    /// Type (lowercase)
+    kind: String,
+    /// Title (custom)
+    title: Option<&'a str>,
+    /// Collapsible (`Some(true)` if expanded)
+    open: Option<bool>,
+}
+
+/// Callout renderer
+#[derive(Debug, Clone)]
+pub struct CalloutRenderer {
+    /// Types
+    types: BTreeMap<String, CalloutType>,
+    /// Markup
+    markup: CalloutMarkup,
+}
+
+impl CalloutRenderer {
+    /// Creates a callout renderer, with the built-in types and the types of the config
+    ///
+    /// NB: the titles of the built-in types are in the language of the config
+    pub fn new(cfg: &Config, markup: CalloutMarkup) -> Self {
+        let ui_strings = UIStrings::from_config(cfg);
+        let mut types = BUILTIN_TYPES
+            .iter()
+            .map(|(kind, title_key, icon)| {
+                let callout_type = CalloutType {
+                    title: ui_strings
+                        .get(title_key)
+                        .map(|title| title.to_string())
+                        .unwrap_or_else(|| capitalize(kind)),
+                    icon: icon.to_string(),
+                };
+                (kind.to_string(), callout_type)
+            })
+            .collect::<BTreeMap<_, _>>();
+        for (kind, callout_cfg) in &cfg.file().callouts {
+            let kind = kind.to_lowercase();
+            let builtin = types.get(&kind).cloned();
+            let callout_type = CalloutType {
+                title: callout_cfg
+                    .title
+                    .clone()
+                    .or(builtin.as_ref().map(|t| t.title.clone()))
+                    .unwrap_or_else(|| capitalize(&kind)),
+                icon: callout_cfg
+                    .icon
+                    .clone()
+                    .or(builtin.map(|t| t.icon))
+                    .unwrap_or_default(),
+            };
+            types.insert(kind, callout_type);
+        }
+        Self { types, markup }
+    }
+
+    /// Converts the callouts of a Markdown page to HTML
+    pub fn process(&self, md: &str) -> String {
+        self.process_iter(md, "")
+    }
+
+    /// Converts the callouts recursively
+    ///
+    /// NB: `id_prefix` makes the ids of the nested tab groups unique (eg `-3-1`)
+    fn process_iter(&self, md: &str, id_prefix: &str) -> String {
+        let lines = md.lines().collect::<Vec<_>>();
+        let mut out = String::new();
+        let mut fence: Option<(char, usize)> = None;
+        let mut i = 0;
+        while i < lines.len() {
+            let line = lines[i];
+            i += 1;
+
+            // fenced code blocks
+            if in_code_block(&mut fence, line) {
+                push_line(&mut out, line);
+                continue;
+            }
+
+            // tab groups (`:::tabs` ... `:::`)
+            if let Some(callout) =
+                container_start(line).filter(|c| TAB_KINDS.contains(&c.kind.as_str()))
+            {
+                let end = container_end(&lines, i);
+                let id_prefix = format!("{id_prefix}-{i}");
+                out.push_str(&render_tabs(
+                    &callout.kind,
+                    &lines[i..end],
+                    &id_prefix,
+                    self.markup,
+                    |content, id_prefix| self.process_iter(content, id_prefix),
+                ));
+                i = (end + 1).min(lines.len());
+                continue;
+            }
+
+            // fenced containers (`:::tip Title` ... `:::`)
+            if let Some(callout) = container_start(line).and_then(|c| self.known(c)) {
+                let end = container_end(&lines, i);
+                let inner = lines[i..end].join("\n");
+                let id_prefix = format!("{id_prefix}-{i}");
+                out.push_str(&self.render(&callout, &self.process_iter(&inner, &id_prefix)));
+                i = (end + 1).min(lines.len());
+                continue;
+            }
+
+            // alerts (`> [!NOTE] Title`)
+            if let Some(callout) = alert_start(line).and_then(|c| self.known(c)) {
+                let id_prefix = format!("{id_prefix}-{i}");
+                let mut inner = vec![];
+                while i < lines.len() {
+                    match unquote(lines[i]) {
+                        Some(quoted) => inner.push(quoted),
+                        None => break,
+                    }
+                    i += 1;
+                }
+                let content = self.process_iter(&inner.join("\n"), &id_prefix);
+                out.push_str(&self.render(&callout, &content));
+                continue;
+            }
+
+            push_line(&mut out, line);
+        }
+        out
+    }
+
+    /// Returns a callout if its type is known
+    fn known<'a>(&self, callout: Callout<'a>) -> Option<Callout<'a>> {
+        if self.types.contains_key(&callout.kind) {
+            Some(callout)
+        } else {
+            warn!("Unknown callout type ({})", callout.kind);
+            None
+        }
+    }
+
+    /// Renders a callout around its (Markdown) content
+    fn render(&self, callout: &Callout, content: &str) -> String {
+        let callout_type = &self.types[&callout.kind];
+        if self.markup == CalloutMarkup::Markdown {
+            return render_quote(callout, callout_type, content);
+        }
+        let kind = escape_xml(&callout.kind);
+        let title = escape_xml(callout.title.unwrap_or(&callout_type.title));
+        let icon = if callout_type.icon.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "<span class=\"callout-icon\" aria-hidden=\"true\">{}</span>",
+                escape_xml(&callout_type.icon)
+            )
+        };
+
+        // NB: the blank lines end the raw HTML blocks, so that the content is parsed as Markdown,
+        // and the raw HTML blocks are marked as generated
+        let (open_tag, title_tag, close_tag) = match (self.markup, callout.open) {
+            (CalloutMarkup::HTML, Some(open)) => (
+                format!(
+                    "<details class=\"callout callout-{kind}\"{}>",
+                    if open { " open" } else { "" }
+                ),
+                format!("<summary class=\"callout-title\">{icon}{title}</summary>"),
+                "</details>",
+            ),
+            (CalloutMarkup::HTML, None) => (
+                format!("<aside class=\"callout callout-{kind}\" role=\"note\">"),
+                format!("<p class=\"callout-title\">{icon}{title}</p>"),
+                "</aside>",
+            ),
+            (CalloutMarkup::XHTML | CalloutMarkup::Markdown, _) => (
+                format!("<div class=\"callout callout-{kind}\">"),
+                format!("<p class=\"callout-title\">{icon}{title}</p>"),
+                "</div>",
+            ),
+        };
+        format!(
+            "{open_tag}\n{title_tag}{RAW_HTML_MARKER}\n\n{}\n\n{close_tag}{RAW_HTML_MARKER}\n\n",
+            content.trim_matches('\n')
+        )
+    }
+}
+
+/// Renders a callout as a blockquote with a bold title (Markdown)
+fn render_quote(callout: &Callout, callout_type: &CalloutType, content: &str) -> String {
+    let title = callout.title.unwrap_or(&callout_type.title);
+    let mut md = if callout_type.icon.is_empty() {
+        format!("> **{title}**\n>\n")
+    } else {
+        format!("> **{} {title}**\n>\n", callout_type.icon)
+    };
+    for line in content.trim_matches('\n').lines() {
+        md.push('>');
+        if !line.is_empty() {
+            md.push(' ');
+            md.push_str(line);
+        }
+        md.push('\n');
+    }
+    md.push('\n');
+    md
+}
+
+/// Appends a line
+pub(crate) fn push_line(out: &mut String, line: &str) {
+    out.push_str(line);
+    out.push('\n');
+}
+
+/// Returns the char and the length of a code fence (eg ```` ```rust ````), and if it has an info string
+pub(crate) fn code_fence(line: &str) -> Option<(char, usize, bool)> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let c = trimmed.chars().next()?;
+    let run = trimmed.chars().take_while(|x| *x == c).count();
+    if (c == '`' || c == '~') && run >= 3 {
+        Some((c, run, !trimmed[run..].trim().is_empty()))
+    } else {
+        None
+    }
+}
+
+/// Tracks the fenced code blocks, and checks if a line is a fence or inside a code block
+fn in_code_block(fence: &mut Option<(char, usize)>, line: &str) -> bool {
+    if let Some((c, run, has_info)) = code_fence(line) {
+        *fence = match *fence {
+            Some((fence_c, fence_run)) if c == fence_c && run >= fence_run && !has_info => None,
+            Some(fence) => Some(fence),
+            None => Some((c, run)),
+        };
+        return true;
+    }
+    fence.is_some()
+}
+
+/// Parses the type of a callout (eg `tip-`), and its title
+fn parse_callout<'a>(kind: &str, title: &'a str) -> Option<Callout<'a>> {
+    let (kind, open) = match kind.strip_suffix('-') {
+        Some(kind) => (kind, Some(false)),
+        None => match kind.strip_suffix('+') {
+            Some(kind) => (kind, Some(true)),
+            None => (kind, None),
+        },
+    };
+    if kind.is_empty()
+        || !kind
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    {
+        return None;
+    }
+    let title = title.trim();
+    Some(Callout {
+        kind: kind.to_lowercase(),
+        title: if title.is_empty() { None } else { Some(title) },
+        open,
+    })
+}
+
+/// Parses the start of a fenced container (eg `:::tip Title`)
+fn container_start(line: &str) -> Option<Callout<'_>> {
+    let rest = line.trim().strip_prefix(":::")?;
+    let rest = rest.trim_start_matches(':').trim_start();
+    let (kind, title) = rest.split_once(' ').unwrap_or((rest, ""));
+    parse_callout(kind, title)
+}
+
+/// Returns the index of the line closing a fenced container (or the number of lines)
+///
+/// NB: containers can be nested, and the markers inside fenced code blocks are skipped
+fn container_end(lines: &[&str], start: usize) -> usize {
+    let mut depth = 0;
+    let mut fence = None;
+    for (i, line) in lines.iter().enumerate().skip(start) {
+        if in_code_block(&mut fence, line) {
+            continue;
+        }
+        let trimmed = line.trim();
+        if trimmed.starts_with(":::") && trimmed.trim_start_matches(':').is_empty() {
+            if depth == 0 {
+                return i;
+            }
+            depth -= 1;
+        } else if container_start(line).is_some() {
+            depth += 1;
+        }
+    }
+    lines.len()
+}
+
+/// Parses the start of an alert (eg `> [!NOTE] Title`)
+fn alert_start(line: &str) -> Option<Callout<'_>> {
+    let rest = unquote(line)?.trim_start().strip_prefix("[!")?;
+    let (kind, title) = rest.split_once(']')?;
+    // NB: the collapsible marker follows the bracket (eg `[!NOTE]-`)
+    match title.chars().next() {
+        Some(marker @ ('-' | '+')) => parse_callout(&format!("{kind}{marker}"), &title[1..]),
+        _ => parse_callout(kind, title),
+    }
+}
+
+/// Removes the blockquote marker of a line (`> `)
+fn unquote(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let rest = trimmed.strip_prefix('>')?;
+    Some(rest.strip_prefix(' ').unwrap_or(rest))
+}
+
+/// Capitalizes a type (eg `security` -> `Security`)
+pub(crate) fn capitalize(kind: &str) -> String {
+    let mut chars = kind.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_callouts() {
+        let renderer = CalloutRenderer::new(&Config::default(), CalloutMarkup::HTML);
+        let html = renderer.process("> [!TIP]- Shortcut\n> Press `?`\n\n:::warning\nHot\n:::\n");
+        // NB: the raw HTML blocks are marked as generated
+        assert!(html.contains(&format!("</details>{RAW_HTML_MARKER}\n")));
+        let html = html.replace(RAW_HTML_MARKER, "");
+        assert!(html.contains("<details class=\"callout callout-tip\">"));
+        assert!(html.contains("<summary class=\"callout-title\"><span class=\"callout-icon\" aria-hidden=\"true\">💡</span>Shortcut</summary>\n\nPress `?`\n"));
+        assert!(html.contains("<aside class=\"callout callout-warning\" role=\"note\">"));
+        assert!(html.contains("⚠️</span>Warning</p>\n\nHot\n"));
+    }
+
+    #[test]
+    fn test_translated_titles() {
+        let mut cfg = Config::default();
+        cfg.set_locale(Some("fr"));
+        let renderer = CalloutRenderer::new(&cfg, CalloutMarkup::XHTML);
+        let html = renderer.process(":::note\nTexte\n:::\n");
+        assert!(html.contains("ℹ️</span>Remarque</p>"));
+    }
+
+    #[test]
+    fn test_markdown_markup() {
+        // NB: the nested tabs are sequential in the blockquote
+        let renderer = CalloutRenderer::new(&Config::default(), CalloutMarkup::Markdown);
+        let md = renderer.process(
+            ":::tip+ Build
+:::code-group
+```sh
+make
+```
+:::
+
+Done
+:::
+",
+        );
+        assert_eq!(
+            md,
+            "> **💡 Build**\n>\n> **Sh**\n>\n> ```sh\n> make\n> ```\n>\n>\n> Done\n\n"
+        );
+    }
+
+    #[test]
+    fn test_container_in_code_block() {
+        // NB: the marker of the code block does not close the container
+        let renderer = CalloutRenderer::new(&Config::default(), CalloutMarkup::XHTML);
+        let html = renderer.process(":::tip\n```md\n:::\n```\nAfter\n:::\n");
+        assert_eq!(
+            html.replace(RAW_HTML_MARKER, ""),
+            "<div class=\"callout callout-tip\">\n\
+            <p class=\"callout-title\"><span class=\"callout-icon\" aria-hidden=\"true\">💡</span>Tip</p>\n\n\
+            ```md\n:::\n```\nAfter\n\n</div>\n\n"
+        );
+        assert_eq!(container_end(&["```", ":::", "```", ":::"], 0), 3);
+    }
+}
@@ -0,0 +1,463 @@
+//! Citations
+//!
+//! Citations (eg `[@knuth84]`, `[see @knuth84, p. 12; @lamport94]`) are resolved against the
+//! bibliography of the `[citations]` section (BibTeX or CSL-JSON), and formatted with a built-in
+//! style (APA or IEEE, see [CitationStyle]). The cited references are listed at the end of each
+//! page, or in a generated page.
+
+mod bibtex;
+mod csl;
+mod style;
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use comrak::nodes::{AstNode, NodeValue};
+use log::warn;
+use slug::slugify;
+
+use crate::{
+    cfg::{BibliographyPlacement, CitationStyle, Config},
+    src::SourceFile,
+};
+
+use self::{
+    bibtex::parse_bibtex,
+    csl::parse_csl_json,
+    style::{citation_label, format_citation, format_entry},
+};
+use super::{comrak_options, escape_xml, page_path, relative_href, UIStrings, RAW_HTML_MARKER};
+
+/// Default title of the bibliography
+const DEFAULT_TITLE: &str = "References";
+
+/// Bibliography reference
+#[derive(Debug, Clone)]
+pub struct Reference {
+    /// Key (eg `knuth84`)
+    pub key: String,
+    /// CSL type (eg `article-journal`, `book`)
+    pub kind: String,
+    /// Authors (or editors)
+    pub authors: Vec<Name>,
+    /// Title
+    pub title: Option<String>,
+    /// Journal, book or proceedings
+    pub container: Option<String>,
+    /// Publisher (or institution)
+    pub publisher: Option<String>,
+    /// Year
+    pub year: Option<String>,
+    /// Volume
+    pub volume: Option<String>,
+    /// Issue
+    pub issue: Option<String>,
+    /// Pages (eg `97–111`)
+    pub pages: Option<String>,
+    /// DOI
+    pub doi: Option<String>,
+    /// URL
+    pub url: Option<String>,
+}
+
+/// Name of an author
+#[derive(Debug, Clone)]
+pub struct Name {
+    /// Family name (or literal name, eg an organization)
+    pub family: String,
+    /// Given names
+    pub given: Option<String>,
+}
+
+/// Loads the bibliography of the config (`[citations]` section), by key
+///
+/// NB: the format is given by the extension of the file (`.bib` or `.json`)
+pub fn load_bibliography(cfg: &Config) -> Result<BTreeMap<String, Reference>> {
+    let path = match cfg.bibliography_path() {
+        Some(path) => path,
+        None => return Ok(BTreeMap::new()),
+    };
+    let text = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read the bibliography {}", path.display()))?;
+    let refs = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("bib") => parse_bibtex(&text),
+        Some("json") => parse_csl_json(&text),
+        _ => bail!(
+            "Invalid bibliography {} (expected a .bib or .json file)",
+            path.display()
+        ),
+    }
+    .with_context(|| format!("Invalid bibliography {}", path.display()))?;
+
+    let mut bibliography = BTreeMap::new();
+    for reference in refs {
+        if bibliography.contains_key(&reference.key) {
+            warn!("Duplicate bibliography key {}", reference.key);
+        }
+        bibliography.insert(reference.key.clone(), reference);
+    }
+    Ok(bibliography)
+}
+
+/// Item of a citation (eg `see @knuth84, p. 12`)
+#[derive(Debug, PartialEq)]
+struct CitationItem<'a> {
+    /// Prefix (eg `see`)
+    prefix: &'a str,
+    /// Key
+    key: &'a str,
+    /// Locator (eg `p. 12`)
+    locator: &'a str,
+}
+
+/// Index of the citations of a doc
+#[derive(Debug, Default, Clone)]
+pub struct CitationIndex {
+    /// Bibliography (if configured)
+    bibliography: Option<BTreeMap<String, Reference>>,
+    /// Style
+    style: CitationStyle,
+    /// Placement of the bibliography
+    placement: BibliographyPlacement,
+    /// Title of the bibliography
+    title: String,
+    /// Cited keys by page, in order of first citation
+    pages: BTreeMap<PathBuf, Vec<String>>,
+    /// Cited keys of the doc, in order of first citation
+    keys: Vec<String>,
+    /// Output path of the bibliography page
+    bibliography_page: PathBuf,
+}
+
+impl CitationIndex {
+    /// Loads the bibliography, and collects the citations of the source files
+    ///
+    /// NB: `ext` is the extension of the output pages (eg `html`)
+    pub fn new(cfg: &Config, src_files: &[SourceFile], src_dir: &Path, ext: &str) -> Result<Self> {
+        let citations_cfg = &cfg.file().citations;
+        if citations_cfg.bibliography.is_none() {
+            return Ok(Self::default());
+        }
+        let mut index = Self {
+            bibliography: Some(load_bibliography(cfg)?),
+            style: citations_cfg.style,
+            placement: citations_cfg.placement,
+            title: citations_cfg
+                .title
+                .clone()
+                .or(UIStrings::from_config(cfg)
+                    .get("references")
+                    .map(String::from))
+                .unwrap_or(DEFAULT_TITLE.to_string()),
+            bibliography_page: page_path(&bibliography_file(cfg), src_dir, ext)?,
+            ..Default::default()
+        };
+        index.collect_iter(src_files, src_dir, ext)?;
+        Ok(index)
+    }
+
+    /// Collects the citations recursively
+    fn collect_iter(&mut self, src_files: &[SourceFile], src_dir: &Path, ext: &str) -> Result<()> {
+        let opts = comrak_options();
+        for src_file in src_files {
+            let page = page_path(&src_file.path, src_dir, ext)?;
+            let md = String::from_utf8(src_file.content.to_vec())?;
+            let arena = comrak::Arena::new();
+            let root = comrak::parse_document(&arena, &md, &opts);
+            merge_texts(root);
+            for text in texts(root) {
+                for (_, items) in citations(&text) {
+                    for item in items {
+                        self.cite(&page, item.key);
+                    }
+                }
+            }
+            self.collect_iter(&src_file.children, src_dir, ext)?;
+        }
+        Ok(())
+    }
+
+    /// Records the citation of a key in a page
+    fn cite(&mut self, page: &Path, key: &str) {
+        let known = self
+            .bibliography
+            .as_ref()
+            .is_some_and(|refs| refs.contains_key(key));
+        if !known {
+            return;
+        }
+        let page_keys = self.pages.entry(page.to_owned()).or_default();
+        if !page_keys.iter().any(|k| k == key) {
+            page_keys.push(key.to_string());
+        }
+        if !self.keys.iter().any(|k| k == key) {
+            self.keys.push(key.to_string());
+        }
+    }
+
+    /// Formats the citations of a parsed page, and links them to the bibliography
+    ///
+    /// NB: `page` is the output path of the page, and unknown keys are reported
+    pub fn transform<'a>(&self, root: &'a AstNode<'a>, page: &Path) -> Result<()> {
+        if self.bibliography.is_none() {
+            return Ok(());
+        }
+        merge_texts(root);
+        for node in root.descendants() {
+            let text = match &node.data.borrow().value {
+                NodeValue::Text(text) if text.contains('@') => text.to_string(),
+                _ => continue,
+            };
+            if in_link(node) {
+                continue;
+            }
+            if let Some(html) = self.format_text(&text, page) {
+                node.data.borrow_mut().value = NodeValue::HtmlInline(html);
+            }
+        }
+        Ok(())
+    }
+
+    /// Formats the citations of a text, if any
+    fn format_text(&self, text: &str, page: &Path) -> Option<String> {
+        let citations = citations(text);
+        if citations.is_empty() {
+            return None;
+        }
+        let mut html = String::new();
+        let mut pos = 0;
+        for ((start, end), items) in citations {
+            html.push_str(&escape_xml(&text[pos..start]));
+            let items = items
+                .iter()
+                .map(|item| {
+                    let link = self.format_link(item.key, page);
+                    (item.prefix.to_string(), link, item.locator.to_string())
+                })
+                .collect::<Vec<_>>();
+            html.push_str(&format!(
+                "<span class=\"citation\">{}</span>",
+                format_citation(self.style, &items)
+            ));
+            pos = end;
+        }
+        html.push_str(&escape_xml(&text[pos..]));
+        Some(html)
+    }
+
+    /// Formats the link of a cited key (eg `Knuth, 1984`)
+    fn format_link(&self, key: &str, page: &Path) -> String {
+        let (reference, number) = match self.lookup(key, page) {
+            Some(found) => found,
+            None => {
+                warn!("Unknown citation key {key} ({})", page.display());
+                return format!("{}?", escape_xml(key));
+            }
+        };
+        let href = match self.placement {
+            BibliographyPlacement::Page => format!("#ref-{key}"),
+            BibliographyPlacement::Book => {
+                format!("{}#ref-{key}", relative_href(page, &self.bibliography_page))
+            }
+        };
+        format!(
+            "<a href=\"{}\">{}</a>",
+            escape_xml(&href),
+            escape_xml(&citation_label(self.style, reference, number))
+        )
+    }
+
+    /// Returns a cited reference, and its number
+    fn lookup(&self, key: &str, page: &Path) -> Option<(&Reference, usize)> {
+        let reference = self.bibliography.as_ref()?.get(key)?;
+        let keys = match self.placement {
+            BibliographyPlacement::Page => self.pages.get(page)?,
+            BibliographyPlacement::Book => &self.keys,
+        };
+        let number = keys.iter().position(|k| k == key)? + 1;
+        Some((reference, number))
+    }
+
+    /// Returns the bibliography section of a page (HTML), if the bibliography is per page
+    pub fn bibliography_html(&self, page: &Path) -> String {
+        if self.placement != BibliographyPlacement::Page {
+            return String::new();
+        }
+        match self.pages.get(page) {
+            Some(keys) => format!(
+                "<section class=\"bibliography\">\n<h2>{}</h2>\n{}\n</section>\n",
+                escape_xml(&self.title),
+                self.format_references(keys)
+            ),
+            None => String::new(),
+        }
+    }
+
+    /// Returns the generated bibliography page, if the bibliography is book-level
+    pub fn list_pages(&self, cfg: &Config) -> Vec<SourceFile> {
+        if self.placement != BibliographyPlacement::Book || self.keys.is_empty() {
+            return vec![];
+        }
+        // NB: the list is a raw HTML block, so it must not contain blank lines
+        let md = format!(
+            "---\ntitle: {}\n---\n\n# {}\n\n{}{RAW_HTML_MARKER}\n",
+            serde_json::to_string(&self.title).unwrap_or_default(),
+            self.title,
+            self.format_references(&self.keys)
+        );
+        let mut page = SourceFile::new(bibliography_file(cfg));
+        page.content = md.into_bytes();
+        vec![page]
+    }
+
+    /// Formats the list of the references of cited keys (numbered in order)
+    fn format_references(&self, keys: &[String]) -> String {
+        let bibliography = match &self.bibliography {
+            Some(bibliography) => bibliography,
+            None => return String::new(),
+        };
+        let mut entries = keys
+            .iter()
+            .enumerate()
+            .filter_map(|(i, key)| bibliography.get(key).map(|reference| (i + 1, reference)))
+            .collect::<Vec<_>>();
+        if self.style == CitationStyle::Apa {
+            entries.sort_by_key(|(_, reference)| sort_key(reference));
+        }
+
+        let mut html = "<ul class=\"references\">\n".to_string();
+        for (number, reference) in entries {
+            html.push_str(&format!(
+                "<li id=\"ref-{}\">{}</li>\n",
+                escape_xml(&reference.key),
+                format_entry(self.style, reference, number)
+            ));
+        }
+        html.push_str("</ul>");
+        html
+    }
+}
+
+/// Returns the path of the generated bibliography page (at the root of the source dir)
+///
+/// NB: without a title, the file name does not depend on the language (`references.md`)
+fn bibliography_file(cfg: &Config) -> PathBuf {
+    let title = cfg
+        .file()
+        .citations
+        .title
+        .as_deref()
+        .unwrap_or(DEFAULT_TITLE);
+    cfg.src_dir().join(format!("{}.md", slugify(title)))
+}
+
+/// Returns the sort key of a reference (authors, year, title)
+fn sort_key(reference: &Reference) -> String {
+    let authors = reference
+        .authors
+        .iter()
+        .map(|name| format!("{} {}", name.family, name.given.as_deref().unwrap_or("")))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "{} {} {}",
+        authors,
+        reference.year.as_deref().unwrap_or(""),
+        reference.title.as_deref().unwrap_or("")
+    )
+    .to_lowercase()
+}
+
+/// Merges the adjacent text nodes of a parsed page
+///
+/// NB: comrak splits the text at the brackets which are not links (eg `[@knuth84]`)
+fn merge_texts<'a>(root: &'a AstNode<'a>) {
+    for node in root.descendants().collect::<Vec<_>>() {
+        while let Some(next) = node.next_sibling() {
+            let next_text = match &next.data.borrow().value {
+                NodeValue::Text(text) => text.to_string(),
+                _ => break,
+            };
+            match &mut node.data.borrow_mut().value {
+                NodeValue::Text(text) => text.push_str(&next_text),
+                _ => break,
+            }
+            next.detach();
+        }
+    }
+}
+
+/// Returns the texts of a parsed page (outside links)
+fn texts<'a>(root: &'a AstNode<'a>) -> Vec<String> {
+    root.descendants()
+        .filter(|node| !in_link(node))
+        .filter_map(|node| match &node.data.borrow().value {
+            NodeValue::Text(text) if text.contains('@') => Some(text.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Checks if a node is inside a link
+fn in_link<'a>(node: &'a AstNode<'a>) -> bool {
+    node.ancestors()
+        .any(|a| matches!(a.data.borrow().value, NodeValue::Link(..)))
+}
+
+/// Returns the citations of a text (byte range and items)
+fn citations(text: &str) -> Vec<((usize, usize), Vec<CitationItem<'_>>)> {
+    let mut citations = vec![];
+    let mut pos = 0;
+    while let Some(open) = text[pos..].find('[').map(|i| pos + i) {
+        let close = match text[open + 1..].find([']', '[']) {
+            Some(i) if text[open + 1 + i..].starts_with(']') => open + 1 + i,
+            Some(i) => {
+                pos = open + 1 + i;
+                continue;
+            }
+            None => break,
+        };
+        let items = text[open + 1..close]
+            .split(';')
+            .map(citation_item)
+            .collect::<Option<Vec<_>>>();
+        match items {
+            Some(items) if !items.is_empty() => {
+                citations.push(((open, close + 1), items));
+                pos = close + 1;
+            }
+            _ => pos = open + 1,
+        }
+    }
+    citations
+}
+
+/// Parses a citation item (eg `see @knuth84, p. 12`)
+///
+/// NB: the key starts with a letter, a digit or `_`, and can contain internal punctuation
+/// (eg `@doe:2020.a`)
+fn citation_item(item: &str) -> Option<CitationItem<'_>> {
+    let at = item.find('@')?;
+    let prefix = &item[..at];
+    if !(prefix.is_empty() || prefix.ends_with(char::is_whitespace)) {
+        return None;
+    }
+    let rest = &item[at + 1..];
+    if !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    let len = rest
+        .find(|c: char| !(c.is_alphanumeric() || "_-:.#$%&+?<>~/".contains(c)))
+        .unwrap_or(rest.len());
+    let key = rest[..len].trim_end_matches(|c: char| !(c.is_alphanumeric() || c == '_'));
+    let locator = rest[key.len()..].trim().trim_start_matches(',').trim();
+    Some(CitationItem {
+        prefix: prefix.trim(),
+        key,
+        locator,
+    })
+}
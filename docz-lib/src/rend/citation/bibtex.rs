@@ -0,0 +1,492 @@
+//! BibTeX bibliography
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+
+use super::{Name, Reference};
+
+/// Month macros
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Parses a BibTeX file
+///
+/// NB: `@string` macros are expanded, `@comment` and `@preamble` are skipped, and the LaTeX
+/// markup of the values (braces, escapes, accents) is converted to plain text.
+pub fn parse_bibtex(text: &str) -> Result<Vec<Reference>> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+        macros: BTreeMap::new(),
+    };
+    let mut refs = vec![];
+    while parser.skip_to('@') {
+        parser.pos += 1;
+        let entry_type = parser.ident().to_lowercase();
+        parser.skip_ws();
+        let close = match parser.peek() {
+            Some('{') => '}',
+            Some('(') => ')',
+            _ => bail!(
+                "Invalid BibTeX entry @{entry_type} (line {})",
+                parser.line()
+            ),
+        };
+        parser.pos += 1;
+
+        match entry_type.as_str() {
+            "comment" | "preamble" => {
+                parser.pos -= 1;
+                parser.skip_group();
+            }
+            "string" => {
+                let fields = parser.fields(close)?;
+                parser.macros.extend(fields);
+            }
+            _ => {
+                parser.skip_ws();
+                let key = parser.take_while(|c| c != ',' && c != close && !c.is_whitespace());
+                if key.is_empty() {
+                    bail!("Missing BibTeX key (line {})", parser.line());
+                }
+                parser.skip_ws();
+                if parser.peek() == Some(',') {
+                    parser.pos += 1;
+                }
+                let fields = parser.fields(close)?;
+                refs.push(to_reference(key, &entry_type, fields));
+            }
+        }
+    }
+    Ok(refs)
+}
+
+/// BibTeX parser
+struct Parser {
+    /// Chars
+    chars: Vec<char>,
+    /// Position
+    pos: usize,
+    /// `@string` macros
+    macros: BTreeMap<String, String>,
+}
+
+impl Parser {
+    /// Returns the current char
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// Returns the current line (for errors)
+    fn line(&self) -> usize {
+        self.chars[..self.pos.min(self.chars.len())]
+            .iter()
+            .filter(|c| **c == '\n')
+            .count()
+            + 1
+    }
+
+    /// Skips to the next char `c`, and returns `false` at the end
+    fn skip_to(&mut self, c: char) -> bool {
+        while let Some(next) = self.peek() {
+            if next == c {
+                return true;
+            }
+            self.pos += 1;
+        }
+        false
+    }
+
+    /// Skips whitespace (and `%` comments)
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '%' {
+                self.take_while(|c| c != '\n');
+            } else if c.is_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Consumes the chars matching a predicate
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// Consumes an identifier (entry type, field name or macro)
+    fn ident(&mut self) -> String {
+        self.take_while(|c| c.is_alphanumeric() || "_-:.+/".contains(c))
+    }
+
+    /// Consumes a delimited group (`{...}` or `(...)`, or `"..."`), and returns its content
+    ///
+    /// NB: the braces inside the group are kept
+    fn skip_group(&mut self) -> String {
+        let close = match self.peek() {
+            Some('(') => ')',
+            Some('"') => '"',
+            _ => '}',
+        };
+        self.pos += 1;
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            match c {
+                // NB: escaped chars (eg `\{`) are not delimiters
+                '\\' => self.pos += 1,
+                '{' => depth += 1,
+                '}' if depth > 0 => depth -= 1,
+                _ if c == close && depth == 0 => break,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        let content = self.chars[start..self.pos.min(self.chars.len())]
+            .iter()
+            .collect();
+        self.pos += 1;
+        content
+    }
+
+    /// Consumes the fields of an entry, up to its closing char
+    fn fields(&mut self, close: char) -> Result<BTreeMap<String, String>> {
+        let mut fields = BTreeMap::new();
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    return Ok(fields);
+                }
+                Some(',') => {
+                    self.pos += 1;
+                    continue;
+                }
+                None => bail!("Unterminated BibTeX entry"),
+                _ => {}
+            }
+
+            let name = self.ident().to_lowercase();
+            if name.is_empty() {
+                bail!("Invalid BibTeX field (line {})", self.line());
+            }
+            self.skip_ws();
+            if self.peek() != Some('=') {
+                bail!(
+                    "Missing `=` after BibTeX field {name} (line {})",
+                    self.line()
+                );
+            }
+            self.pos += 1;
+            let value = self.value()?;
+            fields.insert(name, value);
+        }
+    }
+
+    /// Consumes a field value (parts concatenated with `#`)
+    fn value(&mut self) -> Result<String> {
+        let mut value = String::new();
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                Some('{') | Some('"') => value.push_str(&self.skip_group()),
+                Some(c) if c.is_ascii_digit() => {
+                    value.push_str(&self.take_while(|c| c.is_ascii_digit()));
+                }
+                Some(c) if c.is_alphabetic() => {
+                    let name = self.ident().to_lowercase();
+                    let expanded = match self.macros.get(&name) {
+                        Some(expanded) => expanded.clone(),
+                        None => match MONTHS.iter().position(|m| *m == name) {
+                            Some(i) => (i + 1).to_string(),
+                            None => name,
+                        },
+                    };
+                    value.push_str(&expanded);
+                }
+                _ => return Err(anyhow!("Invalid BibTeX value (line {})", self.line())),
+            }
+            self.skip_ws();
+            if self.peek() == Some('#') {
+                self.pos += 1;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+/// Converts a BibTeX entry to a reference
+fn to_reference(key: String, entry_type: &str, mut fields: BTreeMap<String, String>) -> Reference {
+    let authors = fields
+        .remove("author")
+        .or_else(|| fields.remove("editor"))
+        .map(|authors| parse_names(&authors))
+        .unwrap_or_default();
+    let mut take = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| fields.remove(*name))
+            .map(|value| latex_to_text(&value))
+            .filter(|value| !value.is_empty())
+    };
+    let kind = match entry_type {
+        "article" => "article-journal",
+        "book" | "booklet" => "book",
+        "inbook" | "incollection" => "chapter",
+        "inproceedings" | "conference" | "proceedings" => "paper-conference",
+        "phdthesis" | "mastersthesis" | "thesis" => "thesis",
+        "techreport" | "report" => "report",
+        "online" | "electronic" | "www" => "webpage",
+        _ => "document",
+    };
+    let year = take(&["year"])
+        .or_else(|| take(&["date"]).map(|date| date.chars().take_while(|c| *c != '-').collect()));
+
+    Reference {
+        key,
+        kind: kind.to_string(),
+        authors,
+        title: take(&["title"]),
+        container: take(&["journal", "journaltitle", "booktitle"]),
+        publisher: take(&["publisher", "school", "institution", "organization"]),
+        year,
+        volume: take(&["volume"]),
+        issue: take(&["number", "issue"]),
+        pages: take(&["pages"]),
+        doi: take(&["doi"]),
+        url: take(&["url"]),
+    }
+}
+
+/// Parses a list of names (eg `Knuth, Donald E. and Leslie Lamport`)
+///
+/// NB: a braced name is a literal (eg `{World Health Organization}`)
+fn parse_names(names: &str) -> Vec<Name> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    let bytes = names.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            b' ' if depth == 0 && names[i..].starts_with(" and ") => {
+                parts.push(&names[start..i]);
+                start = i + " and ".len();
+                i = start;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    parts.push(&names[start..]);
+
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            if name.starts_with('{') && name.ends_with('}') {
+                return Name {
+                    family: latex_to_text(name),
+                    given: None,
+                };
+            }
+            let name = latex_to_text(name);
+            match name.split_once(',') {
+                Some((family, given)) => Name {
+                    family: family.trim().to_string(),
+                    given: Some(given.trim().to_string()).filter(|g| !g.is_empty()),
+                },
+                None => match name.rsplit_once(' ') {
+                    Some((given, family)) => Name {
+                        family: family.to_string(),
+                        given: Some(given.trim().to_string()),
+                    },
+                    None => Name {
+                        family: name,
+                        given: None,
+                    },
+                },
+            }
+        })
+        .collect()
+}
+
+/// Converts LaTeX markup to plain text (eg `{\'E}cole -- {B}ern` -> `École – Bern`)
+fn latex_to_text(latex: &str) -> String {
+    let mut text = String::new();
+    let mut chars = latex.chars().peekable();
+    let mut pending_accent: Option<char> = None;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => {}
+            '~' => text.push('\u{a0}'),
+            '\\' => match chars.next() {
+                Some(escaped @ ('&' | '%' | '$' | '#' | '_' | '{' | '}')) => text.push(escaped),
+                Some(accent @ ('\'' | '`' | '^' | '"' | '~' | '=' | '.')) => {
+                    pending_accent = Some(accent);
+                    continue;
+                }
+                Some(letter) if letter.is_alphabetic() => {
+                    let mut command = letter.to_string();
+                    while let Some(next) = chars.peek().filter(|c| c.is_alphabetic()) {
+                        command.push(*next);
+                        chars.next();
+                    }
+                    // NB: the space after a command is a separator
+                    if chars.peek() == Some(&' ') {
+                        chars.next();
+                    }
+                    match command.as_str() {
+                        "c" | "v" | "u" | "H" | "k" | "r" => {
+                            pending_accent = command.chars().next();
+                            continue;
+                        }
+                        "ss" => text.push('ß'),
+                        "o" => text.push('ø'),
+                        "O" => text.push('Ø'),
+                        "ae" => text.push('æ'),
+                        "AE" => text.push('Æ'),
+                        "l" => text.push('ł'),
+                        "L" => text.push('Ł'),
+                        "TeX" | "LaTeX" | "BibTeX" => text.push_str(&command),
+                        // NB: other commands are dropped (eg `\emph`), but not their arguments
+                        _ => {}
+                    }
+                }
+                Some(other) => text.push(other),
+                None => {}
+            },
+            '-' if chars.peek() == Some(&'-') => {
+                chars.next();
+                if chars.peek() == Some(&'-') {
+                    chars.next();
+                    text.push('—');
+                } else {
+                    text.push('–');
+                }
+            }
+            c if c.is_whitespace() => {
+                if !text.ends_with(' ') {
+                    text.push(' ');
+                }
+            }
+            c => {
+                text.push(c);
+                if let Some(accent) = pending_accent.take() {
+                    if let Some(combining) = combining_accent(accent) {
+                        text.push(combining);
+                    }
+                }
+            }
+        }
+    }
+    text.trim().to_string()
+}
+
+/// Returns the combining char of a LaTeX accent (eg `\'` -> U+0301)
+fn combining_accent(accent: char) -> Option<char> {
+    Some(match accent {
+        '`' => '\u{300}',
+        '\'' => '\u{301}',
+        '^' => '\u{302}',
+        '~' => '\u{303}',
+        '=' => '\u{304}',
+        'u' => '\u{306}',
+        '.' => '\u{307}',
+        '"' => '\u{308}',
+        'r' => '\u{30a}',
+        'H' => '\u{30b}',
+        'v' => '\u{30c}',
+        'c' => '\u{327}',
+        'k' => '\u{328}',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bibtex() {
+        let refs = parse_bibtex(
+            r#"@string{tug = "TeX Users Group"}
+@comment{ignored @article{x, title = {X}} }
+@book{knuth84,
+  author = {Knuth, Donald E. and Leslie Lamport and {World Health Organization}},
+  title = {The {\TeX}book: {A} {\em guide}},
+  publisher = tug # ", Portland",
+  year = 1984,
+  month = mar,
+  pages = {1--10},
+}
+@article(erdos, author = "Paul Erd{\H o}s and Ren{\'e} {D}escartes and M{\"o}bius, A.", title = "\'Etude", date = {1950-03-01})"#,
+        )
+        .unwrap();
+        assert_eq!(refs.len(), 2);
+
+        let book = &refs[0];
+        assert_eq!(book.key, "knuth84");
+        assert_eq!(book.kind, "book");
+        let names = |r: &Reference| {
+            r.authors
+                .iter()
+                .map(|n| (n.family.clone(), n.given.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(book),
+            [
+                ("Knuth".to_string(), Some("Donald E.".to_string())),
+                ("Lamport".to_string(), Some("Leslie".to_string())),
+                ("World Health Organization".to_string(), None),
+            ]
+        );
+        assert_eq!(book.title.as_deref(), Some("The TeXbook: A guide"));
+        assert_eq!(book.publisher.as_deref(), Some("TeX Users Group, Portland"));
+        assert_eq!(book.year.as_deref(), Some("1984"));
+        assert_eq!(book.pages.as_deref(), Some("1–10"));
+
+        let article = &refs[1];
+        assert_eq!(article.kind, "article-journal");
+        assert_eq!(
+            names(article),
+            [
+                ("Erdo\u{30b}s".to_string(), Some("Paul".to_string())),
+                ("Descartes".to_string(), Some("Rene\u{301}".to_string())),
+                ("Mo\u{308}bius".to_string(), Some("A.".to_string())),
+            ]
+        );
+        assert_eq!(article.title.as_deref(), Some("E\u{301}tude"));
+        assert_eq!(article.year.as_deref(), Some("1950"));
+    }
+
+    #[test]
+    fn test_parse_bibtex_errors() {
+        let err = parse_bibtex("@book{, title = {X}}").unwrap_err();
+        assert_eq!(err.to_string(), "Missing BibTeX key (line 1)");
+        assert!(parse_bibtex("\n@book knuth").is_err());
+    }
+
+    #[test]
+    fn test_latex_to_text() {
+        assert_eq!(latex_to_text(r"{\'E}cole -- {B}ern"), "E\u{301}cole – Bern");
+        assert_eq!(
+            latex_to_text(r"\c{c}a~va --- \ss{} \& \%"),
+            "c\u{327}a\u{a0}va — ß & %"
+        );
+    }
+}
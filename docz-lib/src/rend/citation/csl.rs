@@ -0,0 +1,162 @@
+//! CSL-JSON bibliography
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use super::{Name, Reference};
+
+/// CSL-JSON item
+#[derive(Debug, Deserialize)]
+struct CslItem {
+    id: serde_json::Value,
+    #[serde(rename = "type", default)]
+    kind: Option<String>,
+    #[serde(default)]
+    author: Vec<CslName>,
+    #[serde(default)]
+    editor: Vec<CslName>,
+    title: Option<String>,
+    #[serde(rename = "container-title")]
+    container_title: Option<String>,
+    publisher: Option<String>,
+    issued: Option<CslDate>,
+    volume: Option<serde_json::Value>,
+    issue: Option<serde_json::Value>,
+    page: Option<serde_json::Value>,
+    #[serde(rename = "DOI")]
+    doi: Option<String>,
+    #[serde(rename = "URL")]
+    url: Option<String>,
+}
+
+/// CSL-JSON name
+#[derive(Debug, Deserialize)]
+struct CslName {
+    family: Option<String>,
+    given: Option<String>,
+    literal: Option<String>,
+}
+
+/// CSL-JSON date
+#[derive(Debug, Deserialize)]
+struct CslDate {
+    #[serde(rename = "date-parts", default)]
+    date_parts: Vec<Vec<serde_json::Value>>,
+    literal: Option<String>,
+}
+
+/// Parses a CSL-JSON file (an array of items)
+pub fn parse_csl_json(text: &str) -> Result<Vec<Reference>> {
+    let items = serde_json::from_str::<Vec<CslItem>>(text)?;
+    Ok(items.into_iter().map(to_reference).collect())
+}
+
+/// Converts a CSL-JSON item to a reference
+fn to_reference(item: CslItem) -> Reference {
+    let names = if item.author.is_empty() {
+        item.editor
+    } else {
+        item.author
+    };
+    let authors = names
+        .into_iter()
+        .filter_map(|name| match (name.family, name.literal) {
+            (Some(family), _) => Some(Name {
+                family,
+                given: name.given,
+            }),
+            (None, Some(literal)) => Some(Name {
+                family: literal,
+                given: None,
+            }),
+            (None, None) => None,
+        })
+        .collect();
+    let year = item.issued.and_then(|date| {
+        date.date_parts
+            .first()
+            .and_then(|parts| parts.first())
+            .map(value_to_string)
+            .or(date.literal)
+    });
+
+    Reference {
+        key: value_to_string(&item.id),
+        kind: item.kind.unwrap_or_else(|| "document".to_string()),
+        authors,
+        title: item.title,
+        container: item.container_title,
+        publisher: item.publisher,
+        year,
+        volume: item.volume.as_ref().map(value_to_string),
+        issue: item.issue.as_ref().map(value_to_string),
+        pages: item
+            .page
+            .as_ref()
+            .map(|page| value_to_string(page).replace('-', "–")),
+        doi: item.doi,
+        url: item.url,
+    }
+}
+
+/// Converts a string or number value to a string
+fn value_to_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csl_json() {
+        let refs = parse_csl_json(
+            r#"[
+  {
+    "id": "knuth84",
+    "type": "book",
+    "author": [{"family": "Knuth", "given": "Donald E."}, {"literal": "TUG"}],
+    "title": "The TeXbook",
+    "publisher": "Addison-Wesley",
+    "issued": {"date-parts": [[1984, 3]]},
+    "page": "1-10",
+    "DOI": "10.1000/x"
+  },
+  {
+    "id": 42,
+    "editor": [{"family": "Lamport"}],
+    "volume": 2,
+    "issued": {"literal": "circa 1990"}
+  }
+]"#,
+        )
+        .unwrap();
+        assert_eq!(refs.len(), 2);
+
+        let book = &refs[0];
+        assert_eq!(book.key, "knuth84");
+        assert_eq!(book.kind, "book");
+        assert_eq!(book.authors.len(), 2);
+        assert_eq!(book.authors[0].family, "Knuth");
+        assert_eq!(book.authors[0].given.as_deref(), Some("Donald E."));
+        assert_eq!(book.authors[1].family, "TUG");
+        assert_eq!(book.authors[1].given, None);
+        assert_eq!(book.title.as_deref(), Some("The TeXbook"));
+        assert_eq!(book.publisher.as_deref(), Some("Addison-Wesley"));
+        assert_eq!(book.year.as_deref(), Some("1984"));
+        assert_eq!(book.pages.as_deref(), Some("1–10"));
+        assert_eq!(book.doi.as_deref(), Some("10.1000/x"));
+
+        let other = &refs[1];
+        assert_eq!(other.key, "42");
+        assert_eq!(other.kind, "document");
+        assert_eq!(other.authors[0].family, "Lamport");
+        assert_eq!(other.volume.as_deref(), Some("2"));
+        assert_eq!(other.year.as_deref(), Some("circa 1990"));
+
+        assert!(parse_csl_json("{}").is_err());
+    }
+}
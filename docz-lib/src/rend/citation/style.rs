@@ -0,0 +1,255 @@
+//! Citation styles
+
+use crate::cfg::CitationStyle;
+
+use super::{escape_xml, Reference};
+
+/// Kinds of references which are published by themselves (the title is italic)
+const STANDALONE_KINDS: &[&str] = &["book", "report", "thesis"];
+
+/// Kinds of references which are part of a book or proceedings (`In ...`)
+const PART_KINDS: &[&str] = &["chapter", "paper-conference"];
+
+/// Returns the text of an in-text citation (eg `Knuth, 1984` or `1`)
+pub fn citation_label(style: CitationStyle, reference: &Reference, number: usize) -> String {
+    match style {
+        CitationStyle::Apa => {
+            let authors = match reference.authors.as_slice() {
+                [] => reference.title.clone().unwrap_or(reference.key.clone()),
+                [a] => a.family.clone(),
+                [a, b] => format!("{} & {}", a.family, b.family),
+                [a, ..] => format!("{} et al.", a.family),
+            };
+            format!("{authors}, {}", year(reference))
+        }
+        CitationStyle::Ieee => number.to_string(),
+    }
+}
+
+/// Formats a citation from its items (prefix, link, locator), eg `(see Knuth, 1984, p. 12)`
+///
+/// NB: the links are HTML, the prefixes and locators are escaped
+pub fn format_citation(style: CitationStyle, items: &[(String, String, String)]) -> String {
+    let items = items.iter().map(|(prefix, link, locator)| {
+        let mut item = String::new();
+        if !prefix.is_empty() && style == CitationStyle::Apa {
+            item.push_str(&escape_xml(prefix));
+            item.push(' ');
+        }
+        item.push_str(link);
+        if !locator.is_empty() {
+            item.push_str(", ");
+            item.push_str(&escape_xml(locator));
+        }
+        (prefix, item)
+    });
+    match style {
+        CitationStyle::Apa => format!(
+            "({})",
+            items.map(|(_, item)| item).collect::<Vec<_>>().join("; ")
+        ),
+        CitationStyle::Ieee => items
+            .map(|(prefix, item)| {
+                if prefix.is_empty() {
+                    format!("[{item}]")
+                } else {
+                    format!("{} [{item}]", escape_xml(prefix))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// Formats a bibliography entry (HTML)
+pub fn format_entry(style: CitationStyle, reference: &Reference, number: usize) -> String {
+    match style {
+        CitationStyle::Apa => format_apa(reference),
+        CitationStyle::Ieee => format!(
+            "<span class=\"reference-label\">[{number}]</span> {}",
+            format_ieee(reference)
+        ),
+    }
+}
+
+/// Formats an entry in the APA style
+/// (eg `Knuth, D. E. (1984). Literate programming. <em>The Computer Journal</em>, <em>27</em>(2), 97–111.`)
+fn format_apa(reference: &Reference) -> String {
+    let mut entry = String::new();
+    let authors = reference
+        .authors
+        .iter()
+        .map(|name| match &name.given {
+            Some(given) => format!("{}, {}", name.family, initials(given)),
+            None => name.family.clone(),
+        })
+        .collect::<Vec<_>>();
+    let authors = match authors.as_slice() {
+        [] => String::new(),
+        [a] => a.clone(),
+        [rest @ .., last] => format!("{}, & {last}", rest.join(", ")),
+    };
+    if !authors.is_empty() {
+        entry.push_str(&sentence(&escape_xml(&authors)));
+        entry.push(' ');
+    }
+    entry.push_str(&format!("({}). ", year(reference)));
+
+    let kind = reference.kind.as_str();
+    if let Some(title) = &reference.title {
+        let title = escape_xml(title);
+        if STANDALONE_KINDS.contains(&kind) {
+            entry.push_str(&sentence(&format!("<em>{title}</em>")));
+        } else {
+            entry.push_str(&sentence(&title));
+        }
+        entry.push(' ');
+    }
+
+    if let Some(container) = &reference.container {
+        let container = escape_xml(container);
+        if PART_KINDS.contains(&kind) {
+            let mut part = format!("In <em>{container}</em>");
+            if let Some(pages) = &reference.pages {
+                part.push_str(&format!(" (pp. {})", escape_xml(pages)));
+            }
+            entry.push_str(&sentence(&part));
+        } else {
+            let mut part = format!("<em>{container}</em>");
+            if let Some(volume) = &reference.volume {
+                part.push_str(&format!(", <em>{}</em>", escape_xml(volume)));
+            }
+            if let Some(issue) = &reference.issue {
+                part.push_str(&format!("({})", escape_xml(issue)));
+            }
+            if let Some(pages) = &reference.pages {
+                part.push_str(&format!(", {}", escape_xml(pages)));
+            }
+            entry.push_str(&sentence(&part));
+        }
+        entry.push(' ');
+    }
+
+    if let Some(publisher) = &reference.publisher {
+        if reference.container.is_none() || PART_KINDS.contains(&kind) {
+            entry.push_str(&sentence(&escape_xml(publisher)));
+            entry.push(' ');
+        }
+    }
+
+    entry.push_str(&link(reference));
+    entry.trim_end().to_string()
+}
+
+/// Formats an entry in the IEEE style
+/// (eg `D. E. Knuth, “Literate programming,” <em>The Computer Journal</em>, vol. 27, no. 2, pp. 97–111, 1984.`)
+fn format_ieee(reference: &Reference) -> String {
+    let authors = reference
+        .authors
+        .iter()
+        .map(|name| match &name.given {
+            Some(given) => format!("{} {}", initials(given), name.family),
+            None => name.family.clone(),
+        })
+        .collect::<Vec<_>>();
+    let authors = match authors.as_slice() {
+        [] => String::new(),
+        [a] => a.clone(),
+        [a, b] => format!("{a} and {b}"),
+        [a, ..] if authors.len() > 6 => format!("{a} et al."),
+        [rest @ .., last] => format!("{}, and {last}", rest.join(", ")),
+    };
+
+    let kind = reference.kind.as_str();
+    let mut parts = vec![];
+    if !authors.is_empty() {
+        parts.push(escape_xml(&authors));
+    }
+    let standalone = STANDALONE_KINDS.contains(&kind);
+    if let Some(title) = &reference.title {
+        if standalone {
+            parts.push(format!("<em>{}</em>", escape_xml(title)));
+        } else {
+            parts.push(format!("“{},”", escape_xml(title)));
+        }
+    }
+    let mut details = vec![];
+    if let Some(container) = &reference.container {
+        if PART_KINDS.contains(&kind) {
+            details.push(format!("in <em>{}</em>", escape_xml(container)));
+        } else {
+            details.push(format!("<em>{}</em>", escape_xml(container)));
+        }
+    }
+    if let Some(publisher) = &reference.publisher {
+        details.push(escape_xml(publisher));
+    }
+    if let Some(volume) = &reference.volume {
+        details.push(format!("vol. {}", escape_xml(volume)));
+    }
+    if let Some(issue) = &reference.issue {
+        details.push(format!("no. {}", escape_xml(issue)));
+    }
+    if let Some(pages) = &reference.pages {
+        details.push(format!("pp. {}", escape_xml(pages)));
+    }
+    details.push(year(reference));
+
+    // NB: the title of an article ends with a comma (inside the quotes)
+    let mut entry = parts.join(", ");
+    if standalone || reference.title.is_none() {
+        entry = sentence(&entry);
+    }
+    entry.push(' ');
+    entry.push_str(&sentence(&details.join(", ")));
+    match (&reference.doi, &reference.url) {
+        (Some(doi), _) => entry.push_str(&format!(" doi: {}.", escape_xml(doi))),
+        (None, Some(_)) => {
+            entry.push_str(" [Online]. Available: ");
+            entry.push_str(&link(reference));
+        }
+        (None, None) => {}
+    }
+    entry
+}
+
+/// Returns the year of a reference (or `n.d.`)
+fn year(reference: &Reference) -> String {
+    reference.year.clone().unwrap_or("n.d.".to_string())
+}
+
+/// Returns the initials of given names (eg `Donald Ervin` -> `D. E.`)
+fn initials(given: &str) -> String {
+    given
+        .split_whitespace()
+        .map(|part| {
+            part.split('-')
+                .filter_map(|p| p.chars().next())
+                .map(|c| format!("{c}."))
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the link of a reference (DOI or URL), if any
+fn link(reference: &Reference) -> String {
+    let url = match (&reference.doi, &reference.url) {
+        (Some(doi), _) => format!("https://doi.org/{doi}"),
+        (None, Some(url)) => url.clone(),
+        (None, None) => return String::new(),
+    };
+    let url = escape_xml(&url);
+    format!("<a href=\"{url}\">{url}</a>")
+}
+
+/// Ends a sentence with a period (unless it already ends with a punctuation mark)
+fn sentence(text: &str) -> String {
+    let plain = text.trim_end_matches("</em>");
+    if plain.ends_with(['.', '?', '!']) {
+        text.to_string()
+    } else {
+        format!("{text}.")
+    }
+}
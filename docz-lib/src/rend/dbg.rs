@@ -4,7 +4,7 @@ use std::fs;
 
 use crate::{cfg::Config, src::SourceData};
 
-use super::Renderer;
+use super::{EmptyOutputConfig, Renderer};
 use anyhow::Result;
 
 /// Renderer for debugging
@@ -19,7 +19,9 @@ impl DebugRenderer {
 }
 
 impl Renderer for DebugRenderer {
-    fn render(&self, cfg: &Config, data: &SourceData) -> Result<()> {
+    type Config = EmptyOutputConfig;
+
+    fn render(&self, cfg: &Config, _out_cfg: &Self::Config, data: &SourceData) -> Result<()> {
         let data_str = format!("{data:#?}");
 
         let build_dir = cfg.build_dir();
@@ -0,0 +1,276 @@
+//! Diagrams
+//!
+//! Diagram code blocks (eg ```` ```dot ````) are rendered to SVG at build time. DOT is rendered
+//! in-process, and the other languages with the commands of the `[diagrams]` section.
+//! The SVGs are cached by content hash, so unchanged diagrams are not rendered again.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use comrak::nodes::{AstNode, NodeHtmlBlock, NodeValue};
+use layout::{
+    backends::svg::SVGWriter,
+    gv::{DotParser, GraphBuilder},
+};
+use log::{trace, warn};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    cfg::Config,
+    cmd::{command_args, run_command, COMMAND_TIMEOUT},
+};
+
+use super::escape_xml;
+
+/// Languages rendered in-process (DOT)
+const DOT_LANGS: &[&str] = &["dot", "graphviz"];
+
+/// Dir of the SVG files (EPUB resources)
+const SVG_FILES_DIR: &str = "diagrams";
+
+/// Diagram renderer
+#[derive(Debug, Clone)]
+pub struct DiagramRenderer {
+    /// Cache dir
+    cache_dir: PathBuf,
+    /// Commands, by language
+    commands: BTreeMap<String, String>,
+}
+
+impl DiagramRenderer {
+    /// Creates a diagram renderer (`[diagrams]` section)
+    pub fn new(cfg: &Config) -> Self {
+        Self {
+            cache_dir: cfg.diagrams_cache_dir(),
+            commands: cfg.file().diagrams.commands.clone(),
+        }
+    }
+
+    /// Checks if a code block language is a diagram
+    pub fn is_diagram(&self, lang: &str) -> bool {
+        self.commands.contains_key(lang) || DOT_LANGS.contains(&lang)
+    }
+
+    /// Replaces the diagram code blocks of a parsed page with inline SVGs
+    ///
+    /// NB: the SVG ids (eg the `startarrow` marker of DOT) are prefixed, so that the diagrams
+    /// of a page do not share them
+    pub fn inline_svgs<'a>(&self, root: &'a AstNode<'a>) -> Result<()> {
+        let mut n = 0;
+        self.replace_blocks(root, |lang, _, svg| {
+            n += 1;
+            let svg = svg.find("<svg").map(|start| &svg[start..]).unwrap_or(&svg);
+            let svg = prefix_svg_ids(svg, &format!("diagram-{n}-"));
+            format!("<div class=\"diagram diagram-{lang}\">{svg}</div>\n")
+        })
+    }
+
+    /// Replaces the diagram code blocks of a parsed page with SVG images, and returns the SVG files
+    ///
+    /// NB: `page` is the output path of the page, so that the image links are relative to it.
+    pub fn svg_files<'a>(
+        &self,
+        root: &'a AstNode<'a>,
+        page: &Path,
+    ) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+        let depth = page.components().count().saturating_sub(1);
+        let mut files = vec![];
+        self.replace_blocks(root, |lang, hash, svg| {
+            let file = Path::new(SVG_FILES_DIR).join(format!("{hash}.svg"));
+            let src = format!("{}{}", "../".repeat(depth), file.to_string_lossy());
+            let html = format!(
+                "<div class=\"diagram diagram-{lang}\"><img src=\"{}\" alt=\"{lang} diagram\" /></div>\n",
+                escape_xml(&src)
+            );
+            files.push((file, svg.into_bytes()));
+            html
+        })?;
+        Ok(files)
+    }
+
+    /// Replaces the diagram code blocks of a parsed page with the HTML of their SVGs
+    ///
+    /// NB: a diagram which fails to render is reported, and kept as a code block
+    fn replace_blocks<'a>(
+        &self,
+        root: &'a AstNode<'a>,
+        mut to_html: impl FnMut(&str, &str, String) -> String,
+    ) -> Result<()> {
+        for node in root.descendants() {
+            let (lang, source) = match &node.data.borrow().value {
+                NodeValue::CodeBlock(block) => {
+                    let lang = block.info.split_whitespace().next().unwrap_or("");
+                    if !self.is_diagram(lang) {
+                        continue;
+                    }
+                    (lang.to_string(), block.literal.to_string())
+                }
+                _ => continue,
+            };
+            let hash = self.hash(&lang, &source);
+            match self.render(&lang, &source, &hash) {
+                Ok(svg) => {
+                    node.data.borrow_mut().value = NodeValue::HtmlBlock(NodeHtmlBlock {
+                        block_type: 6,
+                        literal: to_html(&lang, &hash, svg),
+                    });
+                }
+                Err(err) => warn!("Failed to render diagram ({lang}): {err:#}"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the hash of a diagram (language, command and source)
+    fn hash(&self, lang: &str, source: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(lang);
+        hasher.update([0]);
+        hasher.update(self.commands.get(lang).map(|c| c.as_str()).unwrap_or(""));
+        hasher.update([0]);
+        hasher.update(source);
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Renders a diagram to SVG (or reads it from the cache)
+    fn render(&self, lang: &str, source: &str, hash: &str) -> Result<String> {
+        let cache_file = self.cache_dir.join(format!("{hash}.svg"));
+        if cache_file.exists() {
+            trace!("Cached diagram: {}", cache_file.display());
+            return Ok(fs::read_to_string(&cache_file)?);
+        }
+
+        fs::create_dir_all(&self.cache_dir).with_context(|| {
+            format!(
+                "Failed to create the cache dir {}",
+                self.cache_dir.display()
+            )
+        })?;
+        let svg = match self.commands.get(lang) {
+            Some(command) => self.render_command(command, lang, source, &cache_file)?,
+            None => render_dot(source)?,
+        };
+        fs::write(&cache_file, &svg)?;
+        Ok(svg)
+    }
+
+    /// Renders a diagram with a command
+    ///
+    /// NB: the source is written to a file next to the cached SVG, if the command has an `{input}`
+    fn render_command(
+        &self,
+        command: &str,
+        lang: &str,
+        source: &str,
+        output: &Path,
+    ) -> Result<String> {
+        let input = output.with_extension(lang);
+        let has_input = command.contains("{input}");
+        let has_output = command.contains("{output}");
+        let args = command_args(command, &[("input", &input), ("output", output)])?;
+        if has_input {
+            fs::write(&input, source)?;
+        }
+
+        trace!("Running diagram command: {command}");
+        let stdin = if has_input { "" } else { source };
+        let result = run_command(&args, stdin.as_bytes(), None, COMMAND_TIMEOUT);
+        if has_input {
+            fs::remove_file(&input).ok();
+        }
+        let result = result?;
+        if !result.status.success() {
+            // NB: a partial output must not be cached
+            fs::remove_file(output).ok();
+            bail!(
+                "{} failed ({}): {}",
+                args[0],
+                result.status,
+                String::from_utf8_lossy(&result.stderr).trim()
+            );
+        }
+
+        if has_output {
+            Ok(fs::read_to_string(output)?)
+        } else {
+            Ok(String::from_utf8(result.stdout)?)
+        }
+    }
+}
+
+/// Prefixes the ids of an SVG, and their references (eg `url(#endarrow)`, `href="#a"`)
+fn prefix_svg_ids(svg: &str, prefix: &str) -> String {
+    let mut ids = vec![];
+    let mut rest = svg;
+    while let Some(start) = rest.find(" id=\"") {
+        rest = &rest[start + 5..];
+        if let Some(end) = rest.find('"') {
+            ids.push(&rest[..end]);
+        }
+    }
+    ids.sort_unstable();
+    ids.dedup();
+
+    let mut svg = svg.to_string();
+    for id in ids.into_iter().filter(|id| !id.is_empty()) {
+        svg = svg.replace(&format!(" id=\"{id}\""), &format!(" id=\"{prefix}{id}\""));
+        svg = replace_id_refs(&svg, id, prefix);
+    }
+    svg
+}
+
+/// Prefixes the references to an id (`#id`, not followed by an id character)
+fn replace_id_refs(svg: &str, id: &str, prefix: &str) -> String {
+    let pattern = format!("#{id}");
+    let mut out = String::new();
+    let mut rest = svg;
+    while let Some(start) = rest.find(&pattern) {
+        let end = start + pattern.len();
+        out.push_str(&rest[..start + 1]);
+        let is_ref =
+            !rest[end..].starts_with(|c: char| c.is_alphanumeric() || c == '-' || c == '_');
+        if is_ref {
+            out.push_str(prefix);
+        }
+        out.push_str(id);
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Renders a DOT graph to SVG
+fn render_dot(source: &str) -> Result<String> {
+    let mut parser = DotParser::new(source);
+    let graph = parser
+        .process()
+        .map_err(|err| anyhow!("Invalid DOT graph: {err}"))?;
+    let mut builder = GraphBuilder::new();
+    builder.visit_graph(&graph);
+    let mut visual_graph = builder.get();
+    let mut writer = SVGWriter::new();
+    visual_graph.do_it(false, false, false, &mut writer);
+    Ok(writer.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_svg_ids() {
+        let svg = "<svg><defs><marker id=\"endarrow\"/><marker id=\"endarrow-open\"/></defs>\
+            <path style=\"marker-end: url(#endarrow)\"/><use href=\"#endarrow-open\"/>\
+            <rect fill=\"#endarrowed\"/></svg>";
+        assert_eq!(
+            prefix_svg_ids(svg, "diagram-1-"),
+            "<svg><defs><marker id=\"diagram-1-endarrow\"/><marker id=\"diagram-1-endarrow-open\"/></defs>\
+            <path style=\"marker-end: url(#diagram-1-endarrow)\"/><use href=\"#diagram-1-endarrow-open\"/>\
+            <rect fill=\"#endarrowed\"/></svg>"
+        );
+    }
+}
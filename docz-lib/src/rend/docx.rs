@@ -0,0 +1,1008 @@
+//! DOCX renderer
+
+mod template;
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Cursor, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use comrak::nodes::{AstNode, ListType, NodeValue};
+use log::{trace, warn};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    cfg::Config,
+    src::{SourceAsset, SourceData, SourceFile},
+};
+
+use self::template::{DOCX_SETTINGS, DOCX_STYLES, DOCX_STYLES_PATH};
+
+use super::{
+    comrak_options, escape_xml, find_asset, node_text, strip_index_markers, CalloutMarkup,
+    CalloutRenderer, MathIndex, Renderer, UIStrings,
+};
+
+/// DOCX output config (from doc.toml)
+#[derive(Debug, Deserialize, JsonSchema, Default)]
+pub struct DocxOutputConfig {
+    /// Reference .docx to take the styles from (relative to the root dir)
+    pub reference_docx: Option<PathBuf>,
+}
+
+/// Renderer for Word documents (DOCX)
+#[derive(Debug)]
+pub struct DocxRenderer {
+    /// Styles (word/styles.xml)
+    styles: String,
+}
+
+impl Default for DocxRenderer {
+    fn default() -> Self {
+        Self {
+            styles: DOCX_STYLES.to_string(),
+        }
+    }
+}
+
+impl DocxRenderer {
+    /// Creates a new DOCX renderer
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Renderer for DocxRenderer {
+    type Config = DocxOutputConfig;
+
+    fn register(&mut self, cfg: &Config, docx_config: &Self::Config) -> Result<()> {
+        self.styles = match &docx_config.reference_docx {
+            Some(path) => {
+                let path = cfg.root_dir().join(path);
+                trace!("DOCX, using styles from: {}", path.display());
+                read_reference_styles(&path)?
+            }
+            None => DOCX_STYLES.to_string(),
+        };
+
+        Ok(())
+    }
+
+    fn render(&self, cfg: &Config, _out_cfg: &Self::Config, src_data: &SourceData) -> Result<()> {
+        let callouts = CalloutRenderer::new(cfg, CalloutMarkup::Markdown);
+        let math = MathIndex::new(&src_data.files, &cfg.src_dir(), "docx")?;
+        let mut writer = DocxWriter::new(&src_data.assets, callouts, math);
+
+        // title page + TOC
+        let doc = &cfg.file().doc;
+        writer.body.push_str(&styled_paragraph("Title", &doc.title));
+        if !doc.summary.is_empty() {
+            writer
+                .body
+                .push_str(&styled_paragraph("Subtitle", &doc.summary));
+        }
+        for author in &doc.authors {
+            writer.body.push_str(&styled_paragraph("Author", author));
+        }
+        writer
+            .body
+            .push_str(&toc_field(&UIStrings::from_config(cfg)));
+
+        // pages
+        writer.write_pages_iter(&src_data.files, 0)?;
+
+        // write
+        let data = writer.finish(cfg, &self.styles)?;
+        let docx_file = cfg.build_dir().join("doc.docx");
+        fs::write(docx_file, data)?;
+
+        Ok(())
+    }
+}
+
+/// Reads the styles of a reference .docx
+fn read_reference_styles(path: &Path) -> Result<String> {
+    let file = fs::File::open(path).context(format!(
+        "DOCX reference file not found ({})",
+        path.display()
+    ))?;
+    let mut archive = ZipArchive::new(file)?;
+    let mut styles_file = archive
+        .by_name(DOCX_STYLES_PATH)
+        .context(format!("Missing styles in {}", path.display()))?;
+    let mut styles = String::new();
+    styles_file.read_to_string(&mut styles)?;
+    Ok(styles)
+}
+
+/// Main namespaces of word/document.xml and word/footnotes.xml
+const WORD_NAMESPACES: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships" xmlns:wp="http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing" xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:pic="http://schemas.openxmlformats.org/drawingml/2006/picture""#;
+
+/// Returns the table of contents, in the language of the doc (Word updates the field when
+/// the document is opened)
+fn toc_field(ui_strings: &UIStrings) -> String {
+    let heading = ui_strings.get("table_of_contents").unwrap_or("Contents");
+    let placeholder = ui_strings.get("toc_update_field").unwrap_or_default();
+    format!(
+        r#"<w:p><w:pPr><w:pStyle w:val="TOCHeading"/></w:pPr><w:r><w:t>{}</w:t></w:r></w:p><w:p><w:r><w:fldChar w:fldCharType="begin" w:dirty="true"/></w:r><w:r><w:instrText xml:space="preserve"> TOC \o "1-3" \h \z \u </w:instrText></w:r><w:r><w:fldChar w:fldCharType="separate"/></w:r><w:r><w:t>{}</w:t></w:r><w:r><w:fldChar w:fldCharType="end"/></w:r></w:p>"#,
+        escape_xml(heading),
+        escape_xml(placeholder)
+    )
+}
+
+/// Page break
+const PAGE_BREAK: &str = r#"<w:p><w:r><w:br w:type="page"/></w:r></w:p>"#;
+
+/// Section properties (A4, 1 inch margins)
+const SECTION_PROPERTIES: &str = r#"<w:sectPr><w:pgSz w:w="11906" w:h="16838"/><w:pgMar w:top="1440" w:right="1440" w:bottom="1440" w:left="1440" w:header="708" w:footer="708" w:gutter="0"/></w:sectPr>"#;
+
+/// Relationship types
+const REL_STYLES: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles";
+const REL_NUMBERING: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering";
+const REL_FOOTNOTES: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/footnotes";
+const REL_SETTINGS: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/settings";
+const REL_IMAGE: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/image";
+const REL_HYPERLINK: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink";
+
+/// Abstract numbering ID for bullet lists
+const ABSTRACT_NUM_BULLET: u32 = 1;
+
+/// Abstract numbering ID for ordered lists
+const ABSTRACT_NUM_DECIMAL: u32 = 2;
+
+/// Max image width (6 inches, in EMU)
+const MAX_IMAGE_WIDTH_EMU: u64 = 5_486_400;
+
+/// EMU per pixel (at 96 DPI)
+const EMU_PER_PX: u64 = 9525;
+
+/// Block context
+#[derive(Debug, Clone, Copy, Default)]
+struct BlockCtx {
+    /// Offset added to the heading levels (depth of the page)
+    heading_offset: usize,
+    /// Paragraph style
+    style: Option<&'static str>,
+    /// List level
+    list_level: usize,
+    /// Inside a footnote (no images)
+    in_footnote: bool,
+}
+
+/// Run properties
+#[derive(Debug, Clone, Copy, Default)]
+struct RunProps {
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    superscript: bool,
+    code: bool,
+    hyperlink: bool,
+}
+
+impl RunProps {
+    /// Returns the `<w:rPr>` element
+    fn to_xml(self) -> String {
+        let mut xml = String::new();
+        if self.code {
+            xml.push_str(r#"<w:rStyle w:val="VerbatimChar"/>"#);
+        } else if self.hyperlink {
+            xml.push_str(r#"<w:rStyle w:val="Hyperlink"/>"#);
+        }
+        if self.bold {
+            xml.push_str("<w:b/>");
+        }
+        if self.italic {
+            xml.push_str("<w:i/>");
+        }
+        if self.strike {
+            xml.push_str("<w:strike/>");
+        }
+        if self.superscript {
+            xml.push_str(r#"<w:vertAlign w:val="superscript"/>"#);
+        }
+        if xml.is_empty() {
+            xml
+        } else {
+            format!("<w:rPr>{xml}</w:rPr>")
+        }
+    }
+}
+
+/// Accumulates the parts of the .docx package
+struct DocxWriter<'a> {
+    /// Source assets
+    assets: &'a [SourceAsset],
+    /// Body of word/document.xml
+    body: String,
+    /// Footnotes of word/footnotes.xml
+    footnotes: String,
+    /// Relationships of word/document.xml (ID, type, target, external)
+    rels: Vec<(String, &'static str, String, bool)>,
+    /// Media files (path inside the package, data)
+    media: Vec<(String, Vec<u8>)>,
+    /// Embedded assets (asset path -> relationship ID, size in EMU)
+    images: HashMap<PathBuf, (String, (u64, u64))>,
+    /// Numbering instances (abstract numbering ID, start)
+    nums: Vec<(u32, usize)>,
+    /// Footnotes of the current page (name -> ID)
+    footnote_ids: HashMap<String, usize>,
+    /// Next footnote ID
+    next_footnote_id: usize,
+    /// Next drawing ID
+    next_drawing_id: usize,
+    /// Callouts and tabs (sequential)
+    callouts: CalloutRenderer,
+    /// Math (as code)
+    math: MathIndex,
+}
+
+impl<'a> DocxWriter<'a> {
+    /// Creates a new writer
+    fn new(assets: &'a [SourceAsset], callouts: CalloutRenderer, math: MathIndex) -> Self {
+        let rels = vec![
+            (
+                "rId1".to_string(),
+                REL_STYLES,
+                "styles.xml".to_string(),
+                false,
+            ),
+            (
+                "rId2".to_string(),
+                REL_NUMBERING,
+                "numbering.xml".to_string(),
+                false,
+            ),
+            (
+                "rId3".to_string(),
+                REL_FOOTNOTES,
+                "footnotes.xml".to_string(),
+                false,
+            ),
+            (
+                "rId4".to_string(),
+                REL_SETTINGS,
+                "settings.xml".to_string(),
+                false,
+            ),
+        ];
+
+        Self {
+            assets,
+            body: String::new(),
+            footnotes: String::new(),
+            rels,
+            media: vec![],
+            images: HashMap::new(),
+            nums: vec![],
+            footnote_ids: HashMap::new(),
+            next_footnote_id: 1,
+            next_drawing_id: 1,
+            callouts,
+            math,
+        }
+    }
+
+    /// Adds a relationship and returns its ID
+    fn add_rel(&mut self, rel_type: &'static str, target: String, external: bool) -> String {
+        let id = format!("rId{}", self.rels.len() + 1);
+        self.rels.push((id.clone(), rel_type, target, external));
+        id
+    }
+
+    /// Adds a numbering instance and returns its ID
+    fn add_num(&mut self, abstract_num_id: u32, start: usize) -> usize {
+        self.nums.push((abstract_num_id, start));
+        self.nums.len()
+    }
+
+    /// Writes the pages recursively
+    fn write_pages_iter(&mut self, src_files: &[SourceFile], depth: usize) -> Result<()> {
+        for src_file in src_files {
+            if depth == 0 {
+                self.body.push_str(PAGE_BREAK);
+            }
+
+            // NB: the callouts, tabs and math are converted to plain Markdown
+            let content_str = String::from_utf8(src_file.content.to_vec())?;
+            let content_str = self.callouts.process(&content_str);
+            let content_str = self.math.markdown_to_code(&content_str, &src_file.path);
+            let comrak_opts = comrak_options();
+            let arena = comrak::Arena::new();
+            let root = comrak::parse_document(&arena, &content_str, &comrak_opts);
+            strip_index_markers(root);
+
+            // NB: footnote names are scoped to a page
+            self.footnote_ids.clear();
+            let ctx = BlockCtx {
+                heading_offset: depth,
+                ..Default::default()
+            };
+            let xml = self.blocks(root, ctx)?;
+            self.body.push_str(&xml);
+
+            self.write_pages_iter(&src_file.children, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Converts the child blocks of a node
+    fn blocks<'b>(&mut self, node: &'b AstNode<'b>, ctx: BlockCtx) -> Result<String> {
+        let mut xml = String::new();
+        for child in node.children() {
+            xml.push_str(&self.block(child, ctx)?);
+        }
+        Ok(xml)
+    }
+
+    /// Converts a block
+    fn block<'b>(&mut self, node: &'b AstNode<'b>, ctx: BlockCtx) -> Result<String> {
+        let value = node.data.borrow().value.clone();
+        let xml = match value {
+            NodeValue::Document => self.blocks(node, ctx)?,
+            NodeValue::FrontMatter(_) | NodeValue::HtmlBlock(_) => String::new(),
+            NodeValue::Paragraph => self.paragraph(node, ctx, None, "")?,
+            NodeValue::Heading(heading) => {
+                let level = (heading.level as usize + ctx.heading_offset).clamp(1, 6);
+                let ctx = BlockCtx {
+                    style: Some(HEADING_STYLES[level - 1]),
+                    ..ctx
+                };
+                self.paragraph(node, ctx, None, "")?
+            }
+            NodeValue::BlockQuote => self.blocks(
+                node,
+                BlockCtx {
+                    style: Some("Quote"),
+                    ..ctx
+                },
+            )?,
+            NodeValue::CodeBlock(code_block) => {
+                let mut xml = String::new();
+                for line in code_block.literal.trim_end_matches('\n').lines() {
+                    xml.push_str(&format!(
+                        r#"<w:p><w:pPr><w:pStyle w:val="SourceCode"/></w:pPr><w:r><w:t xml:space="preserve">{}</w:t></w:r></w:p>"#,
+                        escape_xml(line)
+                    ));
+                }
+                xml
+            }
+            NodeValue::ThematicBreak => {
+                r#"<w:p><w:pPr><w:pStyle w:val="HorizontalRule"/></w:pPr></w:p>"#.to_string()
+            }
+            NodeValue::List(list) => {
+                let abstract_num_id = match list.list_type {
+                    ListType::Bullet => ABSTRACT_NUM_BULLET,
+                    ListType::Ordered => ABSTRACT_NUM_DECIMAL,
+                };
+                let num_id = self.add_num(abstract_num_id, list.start);
+                let mut xml = String::new();
+                for item in node.children() {
+                    xml.push_str(&self.list_item(item, ctx, num_id)?);
+                }
+                xml
+            }
+            NodeValue::Table(..) => self.table(node)?,
+            NodeValue::FootnoteDefinition(name) => {
+                if let Some(id) = self.footnote_ids.get(&name).copied() {
+                    let ctx = BlockCtx {
+                        style: Some("FootnoteText"),
+                        in_footnote: true,
+                        ..ctx
+                    };
+                    let content = self.blocks(node, ctx)?.replacen(
+                        "</w:pPr>",
+                        r#"</w:pPr><w:r><w:rPr><w:rStyle w:val="FootnoteReference"/></w:rPr><w:footnoteRef/></w:r><w:r><w:t xml:space="preserve"> </w:t></w:r>"#,
+                        1,
+                    );
+                    self.footnotes.push_str(&format!(
+                        r#"<w:footnote w:id="{id}">{content}</w:footnote>"#
+                    ));
+                } else {
+                    warn!("Unreferenced footnote ({name})");
+                }
+                String::new()
+            }
+            NodeValue::DescriptionList | NodeValue::DescriptionItem(..) => {
+                self.blocks(node, ctx)?
+            }
+            NodeValue::DescriptionTerm => self.blocks(
+                node,
+                BlockCtx {
+                    style: Some("DefinitionTerm"),
+                    ..ctx
+                },
+            )?,
+            NodeValue::DescriptionDetails => self.blocks(
+                node,
+                BlockCtx {
+                    style: Some("Definition"),
+                    ..ctx
+                },
+            )?,
+            _ => self.blocks(node, ctx)?,
+        };
+        Ok(xml)
+    }
+
+    /// Converts a list item
+    fn list_item<'b>(
+        &mut self,
+        node: &'b AstNode<'b>,
+        ctx: BlockCtx,
+        num_id: usize,
+    ) -> Result<String> {
+        let prefix = match node.data.borrow().value {
+            NodeValue::TaskItem(Some(_)) => "\u{2612} ",
+            NodeValue::TaskItem(None) => "\u{2610} ",
+            _ => "",
+        };
+
+        let item_ctx = BlockCtx {
+            style: Some("ListParagraph"),
+            list_level: ctx.list_level,
+            ..ctx
+        };
+        let mut xml = String::new();
+        let mut numbered = false;
+        for child in node.children() {
+            let is_paragraph = matches!(child.data.borrow().value, NodeValue::Paragraph);
+            let is_list = matches!(child.data.borrow().value, NodeValue::List(_));
+            if is_paragraph && !numbered {
+                numbered = true;
+                let num = Some((num_id, ctx.list_level));
+                xml.push_str(&self.paragraph(child, item_ctx, num, prefix)?);
+            } else if is_list {
+                let ctx = BlockCtx {
+                    list_level: ctx.list_level + 1,
+                    ..ctx
+                };
+                xml.push_str(&self.block(child, ctx)?);
+            } else {
+                xml.push_str(&self.block(child, item_ctx)?);
+            }
+        }
+        Ok(xml)
+    }
+
+    /// Converts a paragraph (or a heading)
+    fn paragraph<'b>(
+        &mut self,
+        node: &'b AstNode<'b>,
+        ctx: BlockCtx,
+        num: Option<(usize, usize)>,
+        prefix: &str,
+    ) -> Result<String> {
+        let mut ppr = String::new();
+        if let Some(style) = ctx.style {
+            ppr.push_str(&format!(r#"<w:pStyle w:val="{style}"/>"#));
+        }
+        if let Some((num_id, level)) = num {
+            ppr.push_str(&format!(
+                r#"<w:numPr><w:ilvl w:val="{}"/><w:numId w:val="{num_id}"/></w:numPr>"#,
+                level.min(8)
+            ));
+        }
+        if !ppr.is_empty() {
+            ppr = format!("<w:pPr>{ppr}</w:pPr>");
+        }
+
+        let mut runs = String::new();
+        if !prefix.is_empty() {
+            runs.push_str(&text_run(prefix, RunProps::default()));
+        }
+        runs.push_str(&self.inlines(node, RunProps::default(), ctx)?);
+
+        Ok(format!("<w:p>{ppr}{runs}</w:p>"))
+    }
+
+    /// Converts a table
+    fn table<'b>(&mut self, node: &'b AstNode<'b>) -> Result<String> {
+        let n_cols = node
+            .first_child()
+            .map(|row| row.children().count())
+            .unwrap_or(1)
+            .max(1);
+        let col_width = 9000 / n_cols;
+
+        let mut xml = String::from(
+            r#"<w:tbl><w:tblPr><w:tblStyle w:val="TableGrid"/><w:tblW w:w="5000" w:type="pct"/></w:tblPr><w:tblGrid>"#,
+        );
+        for _ in 0..n_cols {
+            xml.push_str(&format!(r#"<w:gridCol w:w="{col_width}"/>"#));
+        }
+        xml.push_str("</w:tblGrid>");
+
+        for row in node.children() {
+            let header = matches!(row.data.borrow().value, NodeValue::TableRow(true));
+            xml.push_str("<w:tr>");
+            if header {
+                xml.push_str("<w:trPr><w:tblHeader/></w:trPr>");
+            }
+            for cell in row.children() {
+                let props = RunProps {
+                    bold: header,
+                    ..Default::default()
+                };
+                let runs = self.inlines(cell, props, BlockCtx::default())?;
+                xml.push_str(&format!(
+                    r#"<w:tc><w:tcPr><w:tcW w:w="{col_width}" w:type="dxa"/></w:tcPr><w:p>{runs}</w:p></w:tc>"#
+                ));
+            }
+            xml.push_str("</w:tr>");
+        }
+        xml.push_str("</w:tbl>");
+
+        // NB: Word merges consecutive tables without a paragraph in between
+        xml.push_str("<w:p/>");
+        Ok(xml)
+    }
+
+    /// Converts the child inlines of a node
+    fn inlines<'b>(
+        &mut self,
+        node: &'b AstNode<'b>,
+        props: RunProps,
+        ctx: BlockCtx,
+    ) -> Result<String> {
+        let mut xml = String::new();
+        for child in node.children() {
+            xml.push_str(&self.inline(child, props, ctx)?);
+        }
+        Ok(xml)
+    }
+
+    /// Converts an inline
+    fn inline<'b>(
+        &mut self,
+        node: &'b AstNode<'b>,
+        props: RunProps,
+        ctx: BlockCtx,
+    ) -> Result<String> {
+        let value = node.data.borrow().value.clone();
+        let xml = match value {
+            NodeValue::Text(text) => text_run(&text, props),
+            NodeValue::Code(code) => text_run(
+                &code.literal,
+                RunProps {
+                    code: true,
+                    ..props
+                },
+            ),
+            NodeValue::SoftBreak => text_run(" ", props),
+            NodeValue::LineBreak => "<w:r><w:br/></w:r>".to_string(),
+            NodeValue::HtmlInline(_) => String::new(),
+            NodeValue::Emph => self.inlines(
+                node,
+                RunProps {
+                    italic: true,
+                    ..props
+                },
+                ctx,
+            )?,
+            NodeValue::Strong => self.inlines(
+                node,
+                RunProps {
+                    bold: true,
+                    ..props
+                },
+                ctx,
+            )?,
+            NodeValue::Strikethrough => self.inlines(
+                node,
+                RunProps {
+                    strike: true,
+                    ..props
+                },
+                ctx,
+            )?,
+            NodeValue::Superscript => self.inlines(
+                node,
+                RunProps {
+                    superscript: true,
+                    ..props
+                },
+                ctx,
+            )?,
+            NodeValue::Link(link) => {
+                let link_props = RunProps {
+                    hyperlink: true,
+                    ..props
+                };
+                let runs = self.inlines(node, link_props, ctx)?;
+                if is_external_url(&link.url) {
+                    let rel_id = self.add_rel(REL_HYPERLINK, escape_xml(&link.url), true);
+                    format!(r#"<w:hyperlink r:id="{rel_id}">{runs}</w:hyperlink>"#)
+                } else {
+                    runs
+                }
+            }
+            NodeValue::Image(link) => {
+                let alt = node_text(node);
+                if ctx.in_footnote {
+                    text_run(&alt, props)
+                } else {
+                    match self.embed_image(&link.url)? {
+                        Some((rel_id, size)) => self.drawing(&rel_id, size, &alt),
+                        None => {
+                            warn!("DOCX, image not found in assets: {}", link.url);
+                            text_run(&alt, props)
+                        }
+                    }
+                }
+            }
+            NodeValue::FootnoteReference(name) => {
+                let id = match self.footnote_ids.get(&name) {
+                    Some(id) => *id,
+                    None => {
+                        let id = self.next_footnote_id;
+                        self.next_footnote_id += 1;
+                        self.footnote_ids.insert(name, id);
+                        id
+                    }
+                };
+                format!(
+                    r#"<w:r><w:rPr><w:rStyle w:val="FootnoteReference"/></w:rPr><w:footnoteReference w:id="{id}"/></w:r>"#
+                )
+            }
+            _ => self.inlines(node, props, ctx)?,
+        };
+        Ok(xml)
+    }
+
+    /// Embeds an image from the assets, and returns its relationship ID and size
+    fn embed_image(&mut self, url: &str) -> Result<Option<(String, (u64, u64))>> {
+        let asset = match find_asset(self.assets, url) {
+            Some(asset) => asset,
+            None => return Ok(None),
+        };
+        if let Some(image) = self.images.get(&asset.path) {
+            return Ok(Some(image.clone()));
+        }
+
+        let data = asset.content.clone();
+        let (width_px, height_px) = image_size(&data).unwrap_or((640, 480));
+        let mut width = width_px as u64 * EMU_PER_PX;
+        let mut height = height_px as u64 * EMU_PER_PX;
+        if width > MAX_IMAGE_WIDTH_EMU {
+            height = height * MAX_IMAGE_WIDTH_EMU / width;
+            width = MAX_IMAGE_WIDTH_EMU;
+        }
+
+        let ext = asset
+            .path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("bin")
+            .to_lowercase();
+        let media_path = format!("media/image{}.{ext}", self.media.len() + 1);
+        let rel_id = self.add_rel(REL_IMAGE, media_path.clone(), false);
+        self.media.push((format!("word/{media_path}"), data));
+
+        let image = (rel_id, (width, height));
+        self.images.insert(asset.path.clone(), image.clone());
+        Ok(Some(image))
+    }
+
+    /// Returns an inline drawing
+    fn drawing(&mut self, rel_id: &str, (cx, cy): (u64, u64), alt: &str) -> String {
+        let id = self.next_drawing_id;
+        self.next_drawing_id += 1;
+        let alt = escape_xml(alt);
+        format!(
+            r#"<w:r><w:drawing><wp:inline distT="0" distB="0" distL="0" distR="0"><wp:extent cx="{cx}" cy="{cy}"/><wp:docPr id="{id}" name="Picture {id}" descr="{alt}"/><a:graphic><a:graphicData uri="http://schemas.openxmlformats.org/drawingml/2006/picture"><pic:pic><pic:nvPicPr><pic:cNvPr id="{id}" name="Picture {id}"/><pic:cNvPicPr/></pic:nvPicPr><pic:blipFill><a:blip r:embed="{rel_id}"/><a:stretch><a:fillRect/></a:stretch></pic:blipFill><pic:spPr><a:xfrm><a:off x="0" y="0"/><a:ext cx="{cx}" cy="{cy}"/></a:xfrm><a:prstGeom prst="rect"><a:avLst/></a:prstGeom></pic:spPr></pic:pic></a:graphicData></a:graphic></wp:inline></w:drawing></w:r>"#
+        )
+    }
+
+    /// Zips the package
+    fn finish(self, cfg: &Config, styles: &str) -> Result<Vec<u8>> {
+        let doc = &cfg.file().doc;
+
+        // [Content_Types].xml
+        let mut content_types = String::from(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/>"#,
+        );
+        let mut exts = self
+            .media
+            .iter()
+            .filter_map(|(path, _)| Path::new(path).extension().and_then(|ext| ext.to_str()))
+            .collect::<Vec<_>>();
+        exts.sort();
+        exts.dedup();
+        for ext in exts {
+            let mime = mime_guess::from_ext(ext).first_or_octet_stream();
+            content_types.push_str(&format!(
+                r#"<Default Extension="{ext}" ContentType="{mime}"/>"#
+            ));
+        }
+        content_types.push_str(r#"<Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/><Override PartName="/word/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml"/><Override PartName="/word/numbering.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml"/><Override PartName="/word/footnotes.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.footnotes+xml"/><Override PartName="/word/settings.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.settings+xml"/><Override PartName="/docProps/core.xml" ContentType="application/vnd.openxmlformats-package.core-properties+xml"/></Types>"#);
+
+        // _rels/.rels
+        let root_rels = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="docProps/core.xml"/></Relationships>"#;
+
+        // docProps/core.xml
+        let mut core_extra = String::new();
+        if let Some(language) = &doc.language {
+            core_extra.push_str(&format!(
+                "<dc:language>{}</dc:language>",
+                escape_xml(language)
+            ));
+        }
+        if let Some(identifier) = &doc.identifier {
+            core_extra.push_str(&format!(
+                "<dc:identifier>{}</dc:identifier>",
+                escape_xml(identifier)
+            ));
+        }
+        if !doc.subjects.is_empty() {
+            core_extra.push_str(&format!(
+                "<cp:keywords>{}</cp:keywords>",
+                escape_xml(&doc.subjects.join(", "))
+            ));
+        }
+        let core = format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"><dc:title>{}</dc:title><dc:description>{}</dc:description><dc:creator>{}</dc:creator>{core_extra}</cp:coreProperties>"#,
+            escape_xml(&doc.title),
+            escape_xml(&doc.summary),
+            escape_xml(&doc.authors.join("; "))
+        );
+
+        // word/_rels/document.xml.rels
+        let mut doc_rels = String::from(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+        );
+        for (id, rel_type, target, external) in &self.rels {
+            let target_mode = if *external {
+                r#" TargetMode="External""#
+            } else {
+                ""
+            };
+            doc_rels.push_str(&format!(
+                r#"<Relationship Id="{id}" Type="{rel_type}" Target="{target}"{target_mode}/>"#
+            ));
+        }
+        doc_rels.push_str("</Relationships>");
+
+        // word/document.xml
+        let document = format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><w:document {WORD_NAMESPACES}><w:body>{}{SECTION_PROPERTIES}</w:body></w:document>"#,
+            self.body
+        );
+
+        // word/footnotes.xml
+        let footnotes = format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><w:footnotes {WORD_NAMESPACES}><w:footnote w:type="separator" w:id="-1"><w:p><w:r><w:separator/></w:r></w:p></w:footnote><w:footnote w:type="continuationSeparator" w:id="0"><w:p><w:r><w:continuationSeparator/></w:r></w:p></w:footnote>{}</w:footnotes>"#,
+            self.footnotes
+        );
+
+        // word/numbering.xml
+        let numbering = numbering_xml(&self.nums);
+
+        // zip
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        let parts: Vec<(&str, &[u8])> = vec![
+            ("[Content_Types].xml", content_types.as_bytes()),
+            ("_rels/.rels", root_rels.as_bytes()),
+            ("docProps/core.xml", core.as_bytes()),
+            ("word/_rels/document.xml.rels", doc_rels.as_bytes()),
+            ("word/document.xml", document.as_bytes()),
+            (DOCX_STYLES_PATH, styles.as_bytes()),
+            ("word/numbering.xml", numbering.as_bytes()),
+            ("word/footnotes.xml", footnotes.as_bytes()),
+            ("word/settings.xml", DOCX_SETTINGS.as_bytes()),
+        ];
+        for (path, data) in parts {
+            zip.start_file(path, options)?;
+            zip.write_all(data)?;
+        }
+        for (path, data) in &self.media {
+            zip.start_file(path.as_str(), options)?;
+            zip.write_all(data)?;
+        }
+        let cursor = zip.finish()?;
+
+        Ok(cursor.into_inner())
+    }
+}
+
+/// Heading styles
+const HEADING_STYLES: [&str; 6] = [
+    "Heading1", "Heading2", "Heading3", "Heading4", "Heading5", "Heading6",
+];
+
+/// Returns word/numbering.xml
+fn numbering_xml(nums: &[(u32, usize)]) -> String {
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><w:numbering {WORD_NAMESPACES}>"#
+    );
+
+    // abstract numberings
+    for (abstract_num_id, format) in [
+        (ABSTRACT_NUM_BULLET, "bullet"),
+        (ABSTRACT_NUM_DECIMAL, "decimal"),
+    ] {
+        xml.push_str(&format!(
+            r#"<w:abstractNum w:abstractNumId="{abstract_num_id}"><w:multiLevelType w:val="hybridMultilevel"/>"#
+        ));
+        for level in 0..9 {
+            let text = if format == "bullet" {
+                ["\u{2022}", "\u{25e6}", "\u{25aa}"][level % 3].to_string()
+            } else {
+                format!("%{}.", level + 1)
+            };
+            let indent = 720 * (level + 1);
+            xml.push_str(&format!(
+                r#"<w:lvl w:ilvl="{level}"><w:start w:val="1"/><w:numFmt w:val="{format}"/><w:lvlText w:val="{text}"/><w:lvlJc w:val="left"/><w:pPr><w:ind w:left="{indent}" w:hanging="360"/></w:pPr></w:lvl>"#
+            ));
+        }
+        xml.push_str("</w:abstractNum>");
+    }
+
+    // instances (NB: ordered lists restart at their own start value)
+    for (i, (abstract_num_id, start)) in nums.iter().enumerate() {
+        let num_id = i + 1;
+        xml.push_str(&format!(
+            r#"<w:num w:numId="{num_id}"><w:abstractNumId w:val="{abstract_num_id}"/>"#
+        ));
+        if *abstract_num_id == ABSTRACT_NUM_DECIMAL {
+            xml.push_str(&format!(
+                r#"<w:lvlOverride w:ilvl="0"><w:startOverride w:val="{start}"/></w:lvlOverride>"#
+            ));
+        }
+        xml.push_str("</w:num>");
+    }
+
+    xml.push_str("</w:numbering>");
+    xml
+}
+
+/// Returns a paragraph with a style and plain text
+fn styled_paragraph(style: &str, text: &str) -> String {
+    format!(
+        r#"<w:p><w:pPr><w:pStyle w:val="{style}"/></w:pPr>{}</w:p>"#,
+        text_run(text, RunProps::default())
+    )
+}
+
+/// Returns a text run
+fn text_run(text: &str, props: RunProps) -> String {
+    format!(
+        r#"<w:r>{}<w:t xml:space="preserve">{}</w:t></w:r>"#,
+        props.to_xml(),
+        escape_xml(text)
+    )
+}
+
+/// Checks if a URL points outside the document
+fn is_external_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://") || url.starts_with("mailto:")
+}
+
+/// Reads the pixel size of a PNG, GIF or JPEG image
+fn image_size(data: &[u8]) -> Option<(u32, u32)> {
+    // PNG
+    if data.len() >= 24 && data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+        let height = u32::from_be_bytes([data[20], data[21], data[22], data[23]]);
+        return Some((width, height));
+    }
+
+    // GIF
+    if data.len() >= 10 && data.starts_with(b"GIF8") {
+        let width = u16::from_le_bytes([data[6], data[7]]) as u32;
+        let height = u16::from_le_bytes([data[8], data[9]]) as u32;
+        return Some((width, height));
+    }
+
+    // JPEG (NB: the size is in the SOF segment)
+    if data.starts_with(&[0xFF, 0xD8]) {
+        let mut i = 2;
+        while i + 9 < data.len() {
+            if data[i] != 0xFF {
+                return None;
+            }
+            let marker = data[i + 1];
+            if marker == 0xFF {
+                i += 1;
+                continue;
+            }
+            let len = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+            if matches!(marker, 0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF) {
+                let height = u16::from_be_bytes([data[i + 5], data[i + 6]]) as u32;
+                let width = u16::from_be_bytes([data[i + 7], data[i + 8]]) as u32;
+                return Some((width, height));
+            }
+            i += 2 + len;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a PNG header with a pixel size
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        data.extend(width.to_be_bytes());
+        data.extend(height.to_be_bytes());
+        data
+    }
+
+    /// Reads a part of a .docx package
+    fn read_part(docx: &[u8], path: &str) -> String {
+        let mut archive = ZipArchive::new(Cursor::new(docx)).unwrap();
+        let mut part = String::new();
+        archive
+            .by_name(path)
+            .unwrap()
+            .read_to_string(&mut part)
+            .unwrap();
+        part
+    }
+
+    #[test]
+    fn test_document_structure() {
+        let assets = vec![SourceAsset {
+            path: PathBuf::from("src/_assets/wide.png"),
+            content: png(2000, 100),
+        }];
+        let mut page = SourceFile::new("src/01-intro.md");
+        page.content = b"# Intro\n\n- a\n- b\n\n3. c\n4. d\n\nText[^1] ![Wide](_assets/wide.png)\n\n[^1]: Note\n".to_vec();
+        let mut child = SourceFile::new("src/01-intro/01-details.md");
+        child.content = b"# Details\n".to_vec();
+        page.children.push(child);
+
+        let callouts = CalloutRenderer::new(&Config::default(), CalloutMarkup::Markdown);
+        let mut writer = DocxWriter::new(&assets, callouts, MathIndex::default());
+        let mut cfg = Config::default();
+        cfg.set_locale(Some("fr"));
+        writer
+            .body
+            .push_str(&toc_field(&UIStrings::from_config(&cfg)));
+        writer.write_pages_iter(&[page], 0).unwrap();
+        let docx = writer.finish(&Config::default(), DOCX_STYLES).unwrap();
+
+        let document = read_part(&docx, "word/document.xml");
+        assert!(document.contains(r#"<w:instrText xml:space="preserve"> TOC \o "1-3""#));
+        assert!(document.contains("<w:t>Table des matières</w:t>"));
+        // NB: the headings of the child pages are shifted by their depth
+        assert!(document.contains(
+            r#"<w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t xml:space="preserve">Intro"#
+        ));
+        assert!(document.contains(
+            r#"<w:pStyle w:val="Heading2"/></w:pPr><w:r><w:t xml:space="preserve">Details"#
+        ));
+        assert!(document.contains(r#"<w:numId w:val="1"/>"#));
+        assert!(document.contains(r#"<w:numId w:val="2"/>"#));
+        assert!(document.contains(r#"<w:footnoteReference w:id="1"/>"#));
+        // NB: the image is scaled down to the max width
+        assert!(document.contains(r#"<wp:extent cx="5486400" cy="274320"/>"#));
+        assert!(document.ends_with(&format!("{SECTION_PROPERTIES}</w:body></w:document>")));
+
+        let numbering = read_part(&docx, "word/numbering.xml");
+        assert!(numbering.contains(r#"<w:num w:numId="2"><w:abstractNumId w:val="2"/><w:lvlOverride w:ilvl="0"><w:startOverride w:val="3"/>"#));
+
+        let footnotes = read_part(&docx, "word/footnotes.xml");
+        assert!(footnotes.contains(r#"<w:footnote w:id="1">"#));
+        assert!(footnotes.contains("Note"));
+
+        let rels = read_part(&docx, "word/_rels/document.xml.rels");
+        assert!(rels.contains(r#"Target="media/image1.png""#));
+        let mut archive = ZipArchive::new(Cursor::new(&docx)).unwrap();
+        assert!(archive.by_name("word/media/image1.png").is_ok());
+    }
+}
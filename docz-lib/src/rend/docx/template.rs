@@ -0,0 +1,10 @@
+//! DOCX template
+
+/// Default styles (word/styles.xml)
+pub static DOCX_STYLES: &str = include_str!("template/styles.xml");
+
+/// Settings (word/settings.xml)
+pub static DOCX_SETTINGS: &str = include_str!("template/settings.xml");
+
+/// Path of the styles inside a .docx package
+pub const DOCX_STYLES_PATH: &str = "word/styles.xml";
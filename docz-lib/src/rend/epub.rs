@@ -1,29 +1,37 @@
 //! EPUB renderer
 
+mod opf;
 mod template;
 
 use std::{fs, path::PathBuf};
 
 use crate::{
     cfg::Config,
+    doctest::DocTester,
     src::{SourceData, SourceFile},
 };
 
+use self::opf::add_opf_metadata;
 use self::template::{
     CHAPTER_TEMPLATE, CHAPTER_TEMPLATE_ID, COVER_TEMPLATE, COVER_TEMPLATE_ID, EPUB_STYLE_CSS,
     FONT_NOTO_SERIF_REGULAR,
 };
 
-use super::{comrak_options, markdown_to_html, Renderer};
+use super::{
+    child_index, filter_raw_html, html_comrak_options, BookIndex, CalloutMarkup, CalloutRenderer,
+    CitationIndex, DiagramRenderer, FigureIndex, GlossaryIndex, MathIndex, Renderer,
+};
 use anyhow::{anyhow, bail, Context, Result};
+use comrak::ComrakOptions;
 use epub_builder::{EpubBuilder, EpubContent, ReferenceType, ZipLibrary};
 use handlebars::Handlebars;
 use log::trace;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use slug::slugify;
 
 /// EPUB output config (from doc.toml)
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Deserialize, JsonSchema, Default)]
 pub struct EPUBOutputConfig {
     /// Cover image (path inside the assets folder)
     pub cover_image: Option<PathBuf>,
@@ -48,9 +56,14 @@ struct EPUBData {
     title: String,
     summary: String,
     authors: Vec<String>,
+    /// Language (defaults to `en`)
+    lang: String,
+    /// Text direction (`ltr` or `rtl`)
+    dir: Option<&'static str>,
     cover_image: Option<PathBuf>,
-    /// Assets (src path, dst path, eg `assets/img.jpg`)
-    assets: Vec<(PathBuf, PathBuf)>,
+    /// Assets (dst path, eg `assets/img.jpg`, content)
+    #[serde(skip)]
+    assets: Vec<(PathBuf, Vec<u8>)>,
     sections: Vec<EPUBSection>,
 }
 
@@ -64,8 +77,19 @@ struct EPUBSection {
     sections: Vec<EPUBSection>,
 }
 
+/// EPUB chapter data
+#[derive(Debug, Serialize)]
+struct EPUBChapterData<'a> {
+    #[serde(flatten)]
+    section: &'a EPUBSection,
+    lang: &'a str,
+    dir: Option<&'static str>,
+}
+
 impl Renderer for EPUBRenderer {
-    fn register(&mut self, _cfg: &Config) -> Result<()> {
+    type Config = EPUBOutputConfig;
+
+    fn register(&mut self, _cfg: &Config, _out_cfg: &Self::Config) -> Result<()> {
         self.registry = Handlebars::new();
         self.registry
             .register_template_string(COVER_TEMPLATE_ID, COVER_TEMPLATE)?;
@@ -74,9 +98,14 @@ impl Renderer for EPUBRenderer {
         Ok(())
     }
 
-    fn render(&self, cfg: &Config, src_data: &SourceData) -> Result<()> {
+    fn render(
+        &self,
+        cfg: &Config,
+        epub_config: &Self::Config,
+        src_data: &SourceData,
+    ) -> Result<()> {
         // process the source files
-        let data = process_src(src_data, cfg)?;
+        let data = process_src(src_data, cfg, epub_config)?;
 
         // Create a new EpubBuilder using the zip library
         let zip = ZipLibrary::new().into_any()?;
@@ -87,22 +116,22 @@ impl Renderer for EPUBRenderer {
         builder.set_title(&data.title);
         builder.set_description(vec![data.summary.clone()]);
         builder.set_authors(data.authors.clone());
+        builder.set_lang(&data.lang);
 
         // resources
         builder
             .add_resource("fonts/NotoSerif.ttf", FONT_NOTO_SERIF_REGULAR, "font/ttf")
             .into_any()?;
-        for (asset_src, asset_dst) in &data.assets {
-            let file = fs::File::open(asset_src)?;
-            let mime = match mime_guess::from_path(asset_src).first() {
+        for (asset_dst, asset_content) in &data.assets {
+            let mime = match mime_guess::from_path(asset_dst).first() {
                 Some(mime) => mime,
                 None => {
-                    bail!("Failed to guess mime type for asset: {:?}", asset_src);
+                    bail!("Failed to guess mime type for asset: {:?}", asset_dst);
                 }
             };
             trace!("Added resource: {} ({})", asset_dst.display(), mime);
             builder
-                .add_resource(asset_dst, file, mime.to_string())
+                .add_resource(asset_dst, asset_content.as_slice(), mime.to_string())
                 .into_any()?;
         }
 
@@ -139,18 +168,26 @@ impl Renderer for EPUBRenderer {
 
         // sections
         for section in &data.sections {
-            self.render_section_iter(&mut builder, section, 1)?;
+            self.render_section_iter(&mut builder, &data, section, 1)?;
         }
 
         // write
         let build_dir = cfg.build_dir();
-        let epub_file = build_dir.join("doc.epub");
+        let epub_file = match cfg.locale() {
+            Some(locale) => build_dir.join(format!("doc.{locale}.epub")),
+            None => build_dir.join("doc.epub"),
+        };
         let mut buffer = Vec::<u8>::new();
         builder.generate(&mut buffer).into_any()?;
+        let buffer = add_opf_metadata(buffer, &cfg.file().doc)?;
         fs::write(epub_file, buffer)?;
 
         Ok(())
     }
+
+    fn localized(&self) -> bool {
+        true
+    }
 }
 
 impl EPUBRenderer {
@@ -158,10 +195,16 @@ impl EPUBRenderer {
     fn render_section_iter(
         &self,
         builder: &mut EpubBuilder<ZipLibrary>,
+        data: &EPUBData,
         section: &EPUBSection,
         level: i32,
     ) -> Result<()> {
-        let xhtml = self.registry.render(CHAPTER_TEMPLATE_ID, section)?;
+        let chapter = EPUBChapterData {
+            section,
+            lang: &data.lang,
+            dir: data.dir,
+        };
+        let xhtml = self.registry.render(CHAPTER_TEMPLATE_ID, &chapter)?;
 
         let href = section.path.to_str().unwrap();
         let title = &section.title;
@@ -172,7 +215,7 @@ impl EPUBRenderer {
         builder.add_content(content).into_any()?;
 
         for child in &section.sections {
-            self.render_section_iter(builder, child, level + 1)?;
+            self.render_section_iter(builder, data, child, level + 1)?;
         }
 
         Ok(())
@@ -180,24 +223,50 @@ impl EPUBRenderer {
 }
 
 /// Processes the source data
-fn process_src(data: &SourceData, cfg: &Config) -> Result<EPUBData> {
-    let epub_config = cfg.get_output_cfg::<EPUBOutputConfig>("epub")?.unwrap();
-
+fn process_src(
+    data: &SourceData,
+    cfg: &Config,
+    epub_config: &EPUBOutputConfig,
+) -> Result<EPUBData> {
     let title = cfg.file().doc.title.clone();
     let summary = cfg.file().doc.summary.clone();
     let authors = cfg.file().doc.authors.clone();
+    let lang = cfg.lang();
+    let dir = cfg.file().doc.direction.map(|dir| dir.as_str());
     let cover_image = epub_config.cover_image.clone();
 
     let src_dir = cfg.src_dir();
     let mut assets = vec![];
-    for asset_path in &data.assets {
-        let asset_path_stripped = asset_path.strip_prefix(&src_dir)?;
-        assets.push((asset_path.to_owned(), asset_path_stripped.to_owned()));
+    for asset in &data.assets {
+        let asset_path_stripped = asset.path.strip_prefix(&src_dir)?;
+        assets.push((asset_path_stripped.to_owned(), asset.content.clone()));
     }
 
+    let figures = FigureIndex::new(cfg, &data.files, &src_dir, "xhtml")?;
+    let citations = CitationIndex::new(cfg, &data.files, &src_dir, "xhtml")?;
+    let glossary = GlossaryIndex::new(cfg, &data.files, &src_dir, "xhtml")?;
+    let book_index = BookIndex::new(&data.files, &src_dir, "xhtml")?;
+    let mut files = data.files.clone();
+    files.extend(figures.list_pages(cfg));
+    files.extend(citations.list_pages(cfg));
+    files.extend(glossary.list_pages());
+    files.extend(book_index.list_pages(cfg));
+    let ctx = SectionContext {
+        comrak_opts: html_comrak_options(cfg),
+        math: MathIndex::new(&files, &src_dir, "xhtml")?,
+        diagrams: DiagramRenderer::new(cfg),
+        callouts: CalloutRenderer::new(cfg, CalloutMarkup::XHTML),
+        figures,
+        citations,
+        glossary,
+        book_index,
+        doctests: DocTester::new(cfg),
+        raw_html: cfg.file().src.raw_html,
+        src_dir,
+    };
     let mut chapters = vec![];
-    for src_file in &data.files {
-        let chapter = process_src_file(src_file, cfg)?;
+    for (i, src_file) in files.iter().enumerate() {
+        let chapter = process_src_file(src_file, &ctx, &child_index("", i), &mut assets)?;
         chapters.push(chapter);
     }
 
@@ -205,14 +274,50 @@ fn process_src(data: &SourceData, cfg: &Config) -> Result<EPUBData> {
         title,
         summary,
         authors,
+        lang,
+        dir,
         cover_image,
         assets,
         sections: chapters,
     })
 }
 
+/// Context of the processing of the sections
+struct SectionContext {
+    /// Markdown options
+    comrak_opts: ComrakOptions,
+    /// Equations
+    math: MathIndex,
+    /// Diagrams
+    diagrams: DiagramRenderer,
+    /// Callouts
+    callouts: CalloutRenderer,
+    /// Figures, tables and listings
+    figures: FigureIndex,
+    /// Citations
+    citations: CitationIndex,
+    /// Glossary
+    glossary: GlossaryIndex,
+    /// Index
+    book_index: BookIndex,
+    /// Doc tests (hidden lines)
+    doctests: DocTester,
+    /// Raw HTML kept in the Markdown pages (`[src] raw_html`)
+    raw_html: bool,
+    /// Source dir
+    src_dir: PathBuf,
+}
+
 /// Processes a source file
-fn process_src_file(src_file: &SourceFile, cfg: &Config) -> Result<EPUBSection> {
+///
+/// NB: `index` is the index of the section (eg `2.1`), and the SVG files of the diagrams
+/// are added to the assets
+fn process_src_file(
+    src_file: &SourceFile,
+    ctx: &SectionContext,
+    index: &str,
+    assets: &mut Vec<(PathBuf, Vec<u8>)>,
+) -> Result<EPUBSection> {
     let id = {
         let file_name = src_file
             .path
@@ -223,23 +328,45 @@ fn process_src_file(src_file: &SourceFile, cfg: &Config) -> Result<EPUBSection>
         slugify(file_name)
     };
 
-    let src_dir = cfg.src_dir();
     let path = src_file
         .path
-        .strip_prefix(src_dir)
+        .strip_prefix(&ctx.src_dir)
         .context("Source file path is not within the source dir")?
         .with_file_name(&id)
         .with_extension("xhtml");
 
-    let comrak_opts = comrak_options();
     let content_str = String::from_utf8(src_file.content.to_vec())?;
-    let (html, metadata) = markdown_to_html(&content_str, &comrak_opts)?;
+    let content_str = ctx.callouts.process(&content_str);
+    let mut svg_files = vec![];
+    let (mut html, metadata) =
+        ctx.math
+            .markdown_to_html(&content_str, &ctx.comrak_opts, &path, |arena, root| {
+                // NB: the index markers are removed first, and the entries link to the sections.
+                // The glossary terms are linked before the citations and captions are replaced
+                // with HTML.
+                filter_raw_html(root, ctx.raw_html, &src_file.path);
+                ctx.book_index.transform(root)?;
+                ctx.glossary.transform(arena, root, &path)?;
+                ctx.doctests.transform(root)?;
+                ctx.citations.transform(root, &path)?;
+                // NB: captioned code blocks are listings, not diagrams
+                ctx.figures
+                    .transform(root, &ctx.comrak_opts, index, &path)?;
+                svg_files = ctx.diagrams.svg_files(root, &path)?;
+                Ok(())
+            })?;
+    html.push_str(&ctx.citations.bibliography_html(&path));
+    for (file, svg) in svg_files {
+        if !assets.iter().any(|(asset, _)| *asset == file) {
+            assets.push((file, svg));
+        }
+    }
     let title = metadata.title.unwrap_or("Missing title".to_string());
 
     // sections
     let mut sections = vec![];
-    for child in &src_file.children {
-        let section = process_src_file(child, cfg)?;
+    for (i, child) in src_file.children.iter().enumerate() {
+        let section = process_src_file(child, ctx, &child_index(index, i), assets)?;
         sections.push(section);
     }
 
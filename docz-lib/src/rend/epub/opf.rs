@@ -0,0 +1,207 @@
+//! OPF metadata
+//!
+//! `epub-builder` only writes a subset of the Dublin Core metadata, and no manifest properties
+//! for the chapters, so the package document is completed after generation.
+
+use std::{
+    io::{Cursor, Read, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    cfg::{DocConfig, TextDirection},
+    rend::escape_xml,
+};
+
+/// Adds the doc metadata to the package document (.opf) of an EPUB
+///
+/// NB: the chapters with MathML are declared in the manifest (`properties="mathml"`)
+pub(super) fn add_opf_metadata(epub: Vec<u8>, doc: &DocConfig) -> Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(Cursor::new(epub))?;
+    let mut mathml_files = vec![];
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.name().ends_with(".xhtml") {
+            let mut xhtml = String::new();
+            file.read_to_string(&mut xhtml)?;
+            if xhtml.contains("<math") {
+                mathml_files.push(file.name().to_string());
+            }
+        }
+    }
+
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if !file.name().ends_with(".opf") {
+            // NB: raw copy, to keep the `mimetype` file stored first and uncompressed
+            writer.raw_copy_file(file)?;
+            continue;
+        }
+
+        let name = file.name().to_string();
+        let mut opf = String::new();
+        file.read_to_string(&mut opf)?;
+        let opf = opf_with_metadata(&opf, doc);
+        let opf = opf_with_mathml(&opf, &name, &mathml_files);
+
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        writer.start_file(name, options)?;
+        writer.write_all(opf.as_bytes())?;
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
+/// Returns the package document with the doc metadata
+fn opf_with_metadata(opf: &str, doc: &DocConfig) -> String {
+    let mut opf = opf.to_string();
+    let mut metadata = String::new();
+
+    // elements which may be written by epub-builder are replaced
+    let mut set_element = |opf: &mut String, name: &str, value: &str| {
+        let value = escape_xml(value);
+        if !replace_element_text(opf, name, &value) {
+            metadata.push_str(&format!("<{name}>{value}</{name}>\n"));
+        }
+    };
+    if let Some(identifier) = &doc.identifier {
+        set_element(&mut opf, "dc:identifier", identifier);
+    }
+    if let Some(date) = &doc.publication_date {
+        set_element(&mut opf, "dc:date", date);
+    }
+    if let Some(rights) = &doc.rights {
+        set_element(&mut opf, "dc:rights", rights);
+    }
+
+    if let Some(publisher) = &doc.publisher {
+        metadata.push_str(&format!(
+            "<dc:publisher>{}</dc:publisher>\n",
+            escape_xml(publisher)
+        ));
+    }
+    if let Some(license) = &doc.license {
+        metadata.push_str(&format!(
+            "<meta property=\"dcterms:license\">{}</meta>\n",
+            escape_xml(license)
+        ));
+    }
+    if !opf.contains("<dc:subject") {
+        for subject in &doc.subjects {
+            metadata.push_str(&format!(
+                "<dc:subject>{}</dc:subject>\n",
+                escape_xml(subject)
+            ));
+        }
+    }
+    for (i, contributor) in doc.contributors.iter().enumerate() {
+        let id = format!("contributor-{}", i + 1);
+        metadata.push_str(&format!(
+            "<dc:contributor id=\"{id}\">{}</dc:contributor>\n",
+            escape_xml(&contributor.name)
+        ));
+        if let Some(role) = &contributor.role {
+            metadata.push_str(&format!(
+                "<meta refines=\"#{id}\" property=\"role\" scheme=\"marc:relators\">{}</meta>\n",
+                escape_xml(role)
+            ));
+        }
+    }
+    if let Some(series) = &doc.series {
+        let name = escape_xml(&series.name);
+        metadata.push_str(&format!(
+            "<meta property=\"belongs-to-collection\" id=\"series\">{name}</meta>\n"
+        ));
+        metadata.push_str("<meta refines=\"#series\" property=\"collection-type\">series</meta>\n");
+        metadata.push_str(&format!(
+            "<meta name=\"calibre:series\" content=\"{name}\"/>\n"
+        ));
+        if let Some(position) = series.position {
+            metadata.push_str(&format!(
+                "<meta refines=\"#series\" property=\"group-position\">{position}</meta>\n"
+            ));
+            metadata.push_str(&format!(
+                "<meta name=\"calibre:series_index\" content=\"{position}\"/>\n"
+            ));
+        }
+    }
+
+    if let Some(i) = opf.find("</metadata>") {
+        opf.insert_str(i, &metadata);
+    }
+
+    // page progression
+    if doc.direction == Some(TextDirection::Rtl) && !opf.contains("page-progression-direction") {
+        opf = opf.replacen("<spine", "<spine page-progression-direction=\"rtl\"", 1);
+    }
+
+    opf
+}
+
+/// Returns the package document with the `mathml` property on the manifest items of some files
+///
+/// NB: the file paths are relative to the archive root, the manifest hrefs to the package document
+fn opf_with_mathml(opf: &str, opf_path: &str, mathml_files: &[String]) -> String {
+    let opf_dir = Path::new(opf_path).parent().unwrap_or(Path::new(""));
+    let mut opf = opf.to_string();
+    for file in mathml_files {
+        let href = match Path::new(file).strip_prefix(opf_dir) {
+            Ok(href) => href.to_string_lossy().replace('\\', "/"),
+            Err(_) => continue,
+        };
+        let item_start = opf
+            .find(&format!("href=\"{}\"", escape_xml(&href)))
+            .and_then(|i| opf[..i].rfind("<item"));
+        let Some(item_start) = item_start else {
+            continue;
+        };
+        let item_end = item_start + opf[item_start..].find('>').unwrap_or(0);
+        match opf[item_start..item_end].find("properties=\"") {
+            Some(i) => {
+                let i = item_start + i + "properties=\"".len();
+                opf.insert_str(i, "mathml ");
+            }
+            None => opf.insert_str(item_start + "<item".len(), " properties=\"mathml\""),
+        }
+    }
+    opf
+}
+
+/// Replaces the text of the first element with a given name (returns false if not found)
+fn replace_element_text(xml: &mut String, name: &str, text: &str) -> bool {
+    let open = format!("<{name}");
+    let close = format!("</{name}>");
+    let start = match xml.find(&open) {
+        Some(start) => start,
+        None => return false,
+    };
+    let (content_start, content_end) = match (xml[start..].find('>'), xml[start..].find(&close)) {
+        (Some(gt), Some(end)) if gt < end => (start + gt + 1, start + end),
+        _ => return false,
+    };
+    xml.replace_range(content_start..content_end, text);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opf_with_mathml() {
+        let opf = "<manifest>\n\
+            <item media-type=\"application/xhtml+xml\" id=\"a\" href=\"a.xhtml\"/>\n\
+            <item media-type=\"application/xhtml+xml\" id=\"b\" href=\"b.xhtml\"/>\n\
+            <item media-type=\"application/xhtml+xml\" properties=\"nav\" id=\"nav\" href=\"nav.xhtml\"/>\n\
+            </manifest>";
+        let mathml_files = ["OEBPS/b.xhtml".to_string(), "OEBPS/nav.xhtml".to_string()];
+        let opf = opf_with_mathml(opf, "OEBPS/content.opf", &mathml_files);
+        assert!(opf.contains("<item media-type=\"application/xhtml+xml\" id=\"a\""));
+        assert!(opf
+            .contains("<item properties=\"mathml\" media-type=\"application/xhtml+xml\" id=\"b\""));
+        assert!(opf.contains("properties=\"mathml nav\" id=\"nav\""));
+    }
+}
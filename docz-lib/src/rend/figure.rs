@@ -0,0 +1,515 @@
+//! Figures, tables and listings
+//!
+//! Captioned elements are numbered after the index of their page (eg `Figure 2.1.3`):
+//! - figures are images alone in a paragraph, with an id (eg `![Caption](arch.png){#fig:arch}`)
+//! - tables and listings have a caption paragraph, before or after them
+//!   (eg `Table: Caption {#tbl:limits}`, `Listing: Caption {#lst:main}`)
+//!
+//! They are referenced with `@fig:id`, `@tbl:id` or `@lst:id`, across the pages.
+//!
+//! NB: the caption markers are always in English, the rendered labels (eg `Tableau 2.1`) are
+//! in the language of the doc.
+
+use std::{
+    collections::BTreeMap,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use comrak::{
+    nodes::{AstNode, NodeHtmlBlock, NodeValue},
+    ComrakOptions,
+};
+use log::warn;
+
+use crate::{cfg::Config, src::SourceFile};
+
+use super::{comrak_options, escape_xml, node_text, page_path, relative_href, UIStrings};
+
+/// Kind of a numbered element
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FigureKind {
+    /// Image
+    Figure,
+    /// Table
+    Table,
+    /// Code block
+    Listing,
+}
+
+impl FigureKind {
+    /// Returns the prefix of the ids (eg `fig`)
+    fn prefix(&self) -> &'static str {
+        match self {
+            FigureKind::Figure => "fig",
+            FigureKind::Table => "tbl",
+            FigureKind::Listing => "lst",
+        }
+    }
+
+    /// Returns the marker of the captions (eg `Table` for `Table: Caption`)
+    fn marker(&self) -> &'static str {
+        match self {
+            FigureKind::Figure => "Figure",
+            FigureKind::Table => "Table",
+            FigureKind::Listing => "Listing",
+        }
+    }
+
+    /// Returns the UI string of the label (eg `figure`)
+    fn label_key(&self) -> &'static str {
+        match self {
+            FigureKind::Figure => "figure",
+            FigureKind::Table => "table",
+            FigureKind::Listing => "listing",
+        }
+    }
+
+    /// Returns the kind of a prefix
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        [FigureKind::Figure, FigureKind::Table, FigureKind::Listing]
+            .into_iter()
+            .find(|kind| kind.prefix() == prefix)
+    }
+}
+
+/// Numbered element
+#[derive(Debug, Clone)]
+pub struct Figure {
+    /// Kind
+    pub kind: FigureKind,
+    /// Id (eg `fig:arch`, or generated)
+    pub id: String,
+    /// Number (eg `2.1.3`)
+    pub number: String,
+    /// Caption (plain text)
+    pub caption: String,
+    /// Output path of the page
+    pub page: PathBuf,
+}
+
+/// Index of the figures, tables and listings of a doc
+#[derive(Debug, Default, Clone)]
+pub struct FigureIndex {
+    /// Figures, in order
+    figures: Vec<Figure>,
+    /// Figures by id
+    ids: BTreeMap<String, usize>,
+    /// UI strings (labels)
+    ui_strings: UIStrings,
+}
+
+/// Captioned element of a parsed page
+struct Captioned<'a> {
+    /// Kind
+    kind: FigureKind,
+    /// Element node (the paragraph of an image)
+    node: &'a AstNode<'a>,
+    /// Caption paragraph (tables and listings)
+    caption_node: Option<&'a AstNode<'a>>,
+    /// Caption
+    caption: String,
+    /// Id
+    id: Option<String>,
+}
+
+impl FigureIndex {
+    /// Collects the numbered elements of the source files
+    ///
+    /// NB: the pages are indexed like the HTML pages (eg `2.1`), and `ext` is the extension
+    /// of the output pages (eg `html`)
+    pub fn new(cfg: &Config, src_files: &[SourceFile], src_dir: &Path, ext: &str) -> Result<Self> {
+        let mut index = Self {
+            ui_strings: UIStrings::from_config(cfg),
+            ..Default::default()
+        };
+        index.collect_iter(src_files, src_dir, ext, "")?;
+        Ok(index)
+    }
+
+    /// Collects the numbered elements recursively
+    fn collect_iter(
+        &mut self,
+        src_files: &[SourceFile],
+        src_dir: &Path,
+        ext: &str,
+        parent_index: &str,
+    ) -> Result<()> {
+        let opts = comrak_options();
+        for (i, src_file) in src_files.iter().enumerate() {
+            let index = child_index(parent_index, i);
+            let page = page_path(&src_file.path, src_dir, ext)?;
+            let md = String::from_utf8(src_file.content.to_vec())?;
+            let arena = comrak::Arena::new();
+            let root = comrak::parse_document(&arena, &md, &opts);
+            for figure in numbered(&captioned(root), &index, &page) {
+                if self
+                    .ids
+                    .insert(figure.id.clone(), self.figures.len())
+                    .is_some()
+                {
+                    warn!("Duplicate id {} ({})", figure.id, page.display());
+                }
+                self.figures.push(figure);
+            }
+            self.collect_iter(&src_file.children, src_dir, ext, &index)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the label of a kind, in the language of the doc (eg `Figure`)
+    fn label(&self, kind: FigureKind) -> &str {
+        self.ui_strings
+            .get(kind.label_key())
+            .unwrap_or(kind.marker())
+    }
+
+    /// Returns the numbered elements of a kind
+    pub fn figures(&self, kind: FigureKind) -> impl Iterator<Item = &Figure> {
+        self.figures
+            .iter()
+            .filter(move |figure| figure.kind == kind)
+    }
+
+    /// Numbers the captioned elements of a parsed page, and links the references
+    ///
+    /// NB: `index` is the index of the page (eg `2.1`), and `page` its output path
+    pub fn transform<'a>(
+        &self,
+        root: &'a AstNode<'a>,
+        opts: &ComrakOptions,
+        index: &str,
+        page: &Path,
+    ) -> Result<()> {
+        // captions
+        let captioned = captioned(root);
+        let figures = numbered(&captioned, index, page);
+        for (element, figure) in captioned.iter().zip(&figures) {
+            let node_html = match figure.kind {
+                // NB: only the image of the paragraph
+                FigureKind::Figure => match element.node.first_child() {
+                    Some(image) => format_html(image, opts)?,
+                    None => continue,
+                },
+                _ => format_html(element.node, opts)?,
+            };
+            let caption = format!(
+                "<figcaption><span class=\"figure-number\">{} {}:</span> {}</figcaption>",
+                escape_xml(self.label(figure.kind)),
+                figure.number,
+                escape_xml(&figure.caption)
+            );
+            let (before, after) = match figure.kind {
+                FigureKind::Figure => (String::new(), caption),
+                _ => (caption, String::new()),
+            };
+            let html = format!(
+                "<figure class=\"{}\" id=\"{}\">{before}{}{after}</figure>\n",
+                figure.kind.marker().to_lowercase(),
+                escape_xml(&figure.id),
+                node_html.trim()
+            );
+
+            let children = element.node.children().collect::<Vec<_>>();
+            for child in children {
+                child.detach();
+            }
+            element.node.data.borrow_mut().value = NodeValue::HtmlBlock(NodeHtmlBlock {
+                block_type: 6,
+                literal: html,
+            });
+            if let Some(caption_node) = element.caption_node {
+                caption_node.detach();
+            }
+        }
+
+        // references
+        for node in root.descendants() {
+            let text = match &node.data.borrow().value {
+                NodeValue::Text(text) if text.contains('@') => text.to_string(),
+                _ => continue,
+            };
+            let in_link = node
+                .ancestors()
+                .any(|a| matches!(a.data.borrow().value, NodeValue::Link(..)));
+            if in_link {
+                continue;
+            }
+            if let Some(html) = self.link_refs(&text, page) {
+                node.data.borrow_mut().value = NodeValue::HtmlInline(html);
+            }
+        }
+        Ok(())
+    }
+
+    /// Links the references of a text (eg `@fig:arch` -> `Figure 2.1`), if any
+    fn link_refs(&self, text: &str, page: &Path) -> Option<String> {
+        let mut html = String::new();
+        let mut rest = text;
+        let mut found = false;
+        while let Some(at) = rest.find('@') {
+            let (before, after) = rest.split_at(at);
+            html.push_str(&escape_xml(before));
+            let reference = parse_ref(before, &after[1..]);
+            match reference.and_then(|(id, len)| self.ids.get(id).map(|i| (i, len))) {
+                Some((i, len)) => {
+                    let figure = &self.figures[*i];
+                    let href = format!("{}#{}", relative_href(page, &figure.page), figure.id);
+                    html.push_str(&format!(
+                        "<a class=\"figure-ref\" href=\"{}\">{} {}</a>",
+                        escape_xml(&href),
+                        escape_xml(self.label(figure.kind)),
+                        figure.number
+                    ));
+                    rest = &after[1 + len..];
+                    found = true;
+                }
+                None => {
+                    if let Some((id, _)) = reference {
+                        warn!("Unknown reference @{id} ({})", page.display());
+                    }
+                    html.push('@');
+                    rest = &after[1..];
+                }
+            }
+        }
+        html.push_str(&escape_xml(rest));
+        if found {
+            Some(html)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the generated list pages (`[figures]` section), at the root of the source dir
+    pub fn list_pages(&self, cfg: &Config) -> Vec<SourceFile> {
+        let figures_cfg = &cfg.file().figures;
+        let lists = [
+            (
+                figures_cfg.list_of_figures,
+                FigureKind::Figure,
+                "list_of_figures",
+            ),
+            (
+                figures_cfg.list_of_tables,
+                FigureKind::Table,
+                "list_of_tables",
+            ),
+        ];
+        let src_dir = cfg.src_dir();
+        let mut pages = vec![];
+        for (enabled, kind, title_key) in lists {
+            if !enabled {
+                continue;
+            }
+            let title = self.ui_strings.get(title_key).unwrap_or(title_key);
+            let mut md = format!(
+                "---\ntitle: {}\n---\n\n# {}\n\n",
+                serde_json::to_string(title).unwrap_or_default(),
+                escape_markdown(title)
+            );
+            for figure in self.figures(kind) {
+                md.push_str(&format!(
+                    "- [{} {}: {}](<{}#{}>)\n",
+                    escape_markdown(self.label(kind)),
+                    figure.number,
+                    escape_markdown(&figure.caption),
+                    figure.page.to_string_lossy(),
+                    figure.id
+                ));
+            }
+            // NB: the file name does not depend on the language (eg `list-of-figures.md`)
+            let file_name = format!("{}.md", title_key.replace('_', "-"));
+            let mut page = SourceFile::new(src_dir.join(file_name));
+            page.content = md.into_bytes();
+            pages.push(page);
+        }
+        pages
+    }
+}
+
+/// Returns the index of a child page (eg `2.1`)
+pub(crate) fn child_index(parent_index: &str, i: usize) -> String {
+    format!(
+        "{}{}{}",
+        parent_index,
+        if parent_index.is_empty() { "" } else { "." },
+        i + 1
+    )
+}
+
+/// Numbers the captioned elements of a page
+fn numbered(captioned: &[Captioned], index: &str, page: &Path) -> Vec<Figure> {
+    let mut counts = BTreeMap::<FigureKind, usize>::new();
+    captioned
+        .iter()
+        .map(|element| {
+            let count = counts.entry(element.kind).or_default();
+            *count += 1;
+            let number = format!("{index}.{count}");
+            let id = element.id.clone().unwrap_or_else(|| {
+                format!("{}-{}", element.kind.prefix(), number.replace('.', "-"))
+            });
+            Figure {
+                kind: element.kind,
+                id,
+                number,
+                caption: element.caption.clone(),
+                page: page.to_owned(),
+            }
+        })
+        .collect()
+}
+
+/// Returns the captioned elements of a parsed page, in order
+fn captioned<'a>(root: &'a AstNode<'a>) -> Vec<Captioned<'a>> {
+    let mut elements = vec![];
+    for node in root.descendants() {
+        let kind = match &node.data.borrow().value {
+            NodeValue::Paragraph => FigureKind::Figure,
+            NodeValue::Table(..) => FigureKind::Table,
+            NodeValue::CodeBlock(..) => FigureKind::Listing,
+            _ => continue,
+        };
+
+        // figure: an image followed by its id (eg `{#fig:arch}`)
+        if kind == FigureKind::Figure {
+            let image = match node.first_child() {
+                Some(image) if matches!(image.data.borrow().value, NodeValue::Image(..)) => image,
+                _ => continue,
+            };
+            let attrs = node
+                .children()
+                .skip(1)
+                .map(|child| match &child.data.borrow().value {
+                    NodeValue::Text(text) => Some(text.to_string()),
+                    _ => None,
+                })
+                .collect::<Option<String>>();
+            let id = attrs
+                .as_deref()
+                .and_then(|attrs| attribute_id(attrs.trim()));
+            if let Some(id) = id.filter(|id| id.starts_with("fig:")) {
+                elements.push(Captioned {
+                    kind,
+                    node,
+                    caption_node: None,
+                    caption: node_text(image),
+                    id: Some(id.to_string()),
+                });
+            }
+            continue;
+        }
+
+        // tables and listings: a caption paragraph after or before (eg `Table: Caption {#tbl:id}`)
+        let caption_prefix = format!("{}:", kind.marker());
+        let caption = [node.next_sibling(), node.previous_sibling()]
+            .into_iter()
+            .flatten()
+            .filter(|sibling| matches!(sibling.data.borrow().value, NodeValue::Paragraph))
+            .find_map(|sibling| {
+                let text = node_text(sibling);
+                let caption = text
+                    .trim()
+                    .strip_prefix(&caption_prefix)?
+                    .trim()
+                    .to_string();
+                Some((sibling, caption))
+            });
+        if let Some((caption_node, caption)) = caption {
+            let (caption, id) = match caption.rfind('{') {
+                Some(start) if caption.ends_with('}') => match attribute_id(&caption[start..]) {
+                    Some(id) => (caption[..start].trim().to_string(), Some(id.to_string())),
+                    None => (caption, None),
+                },
+                _ => (caption, None),
+            };
+            elements.push(Captioned {
+                kind,
+                node,
+                caption_node: Some(caption_node),
+                caption,
+                id,
+            });
+        }
+    }
+    elements
+}
+
+/// Returns the id of an attribute block (eg `{#fig:arch}` -> `fig:arch`)
+fn attribute_id(attrs: &str) -> Option<&str> {
+    let id = attrs.strip_prefix("{#")?.strip_suffix('}')?.trim();
+    if id.is_empty() || id.contains(char::is_whitespace) {
+        None
+    } else {
+        Some(id)
+    }
+}
+
+/// Parses a reference after a `@` (eg `fig:arch`), and returns the id and its length
+///
+/// NB: a `@` within a word is not a reference (eg an email)
+fn parse_ref<'a>(before: &str, after: &'a str) -> Option<(&'a str, usize)> {
+    if before.chars().last().is_some_and(|c| c.is_alphanumeric()) {
+        return None;
+    }
+    let (prefix, _) = after.split_once(':')?;
+    FigureKind::from_prefix(prefix)?;
+    let len = prefix.len()
+        + 1
+        + after[prefix.len() + 1..]
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(after.len() - prefix.len() - 1);
+    if len == prefix.len() + 1 {
+        return None;
+    }
+    Some((&after[..len], len))
+}
+
+/// Renders a node to HTML
+fn format_html<'a>(node: &'a AstNode<'a>, opts: &ComrakOptions) -> Result<String> {
+    let mut bw = BufWriter::new(Vec::new());
+    comrak::format_html(node, opts, &mut bw)?;
+    Ok(String::from_utf8(bw.into_inner()?)?)
+}
+
+/// Escapes the Markdown inline chars of a text
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(c, '\\' | '[' | ']' | '*' | '_' | '`' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translated_labels() {
+        let mut cfg = Config::default();
+        cfg.set_locale(Some("fr"));
+        let src_dir = cfg.src_dir();
+        let mut file = SourceFile::new(src_dir.join("01-intro.md"));
+        file.content =
+            b"| a |\n|---|\n| 1 |\n\nTable: Limits {#tbl:limits}\n\nSee @tbl:limits.\n".to_vec();
+        let index = FigureIndex::new(&cfg, &[file.clone()], &src_dir, "html").unwrap();
+
+        let mut opts = comrak_options();
+        opts.render.unsafe_ = true;
+        let arena = comrak::Arena::new();
+        let md = String::from_utf8(file.content).unwrap();
+        let root = comrak::parse_document(&arena, &md, &opts);
+        index
+            .transform(root, &opts, "1", Path::new("01-intro.html"))
+            .unwrap();
+        let html = format_html(root, &opts).unwrap();
+        assert!(html.contains("<figure class=\"table\" id=\"tbl:limits\"><figcaption><span class=\"figure-number\">Tableau 1.1:</span> Limits</figcaption>"));
+        assert!(html.contains(">Tableau 1.1</a>"));
+    }
+}
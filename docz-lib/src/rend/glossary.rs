@@ -0,0 +1,469 @@
+//! Glossary
+//!
+//! The glossary terms are loaded from the file of the `[glossary]` section (TOML or YAML, eg
+//! `API = "Application Programming Interface"`), or from the definition lists of the `glossary.md`
+//! page. The first occurrence of each term (or alias) in a page is linked to its definition.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use comrak::{
+    arena_tree::Node,
+    nodes::{Ast, AstNode, NodeValue},
+    Arena,
+};
+use serde::Deserialize;
+use slug::slugify;
+
+use crate::{cfg::Config, src::SourceFile};
+
+use super::{
+    comrak_options, escape_xml, node_text, page_path, relative_href, UIStrings, RAW_HTML_MARKER,
+};
+
+/// Default title of the generated glossary page
+const DEFAULT_TITLE: &str = "Glossary";
+
+/// File name (stem) of the glossary page of the source dir
+const GLOSSARY_PAGE: &str = "glossary";
+
+/// Glossary entry of the glossary file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum GlossaryEntry {
+    /// Definition (Markdown)
+    Definition(String),
+    /// Definition with aliases (eg `{ definition = "...", aliases = ["APIs"] }`)
+    Full {
+        definition: String,
+        #[serde(default)]
+        aliases: Vec<String>,
+    },
+}
+
+/// Glossary term
+#[derive(Debug, Clone)]
+pub struct Term {
+    /// Term
+    pub name: String,
+    /// Aliases (eg plural)
+    pub aliases: Vec<String>,
+    /// Definition (Markdown), for the generated page
+    pub definition: Option<String>,
+    /// Definition (plain text)
+    pub summary: String,
+}
+
+impl Term {
+    /// Returns the id of the term on the glossary page
+    fn id(&self) -> String {
+        format!("term-{}", slugify(&self.name))
+    }
+}
+
+/// Loads the terms of the glossary file (`[glossary]` section)
+///
+/// NB: the format is given by the extension of the file (`.toml`, `.yaml` or `.yml`)
+pub fn load_glossary(cfg: &Config) -> Result<Vec<Term>> {
+    let path = match cfg.glossary_path() {
+        Some(path) => path,
+        None => return Ok(vec![]),
+    };
+    let text = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read the glossary {}", path.display()))?;
+    let entries = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => {
+            toml::from_str::<BTreeMap<String, GlossaryEntry>>(&text).map_err(anyhow::Error::from)
+        }
+        Some("yaml") | Some("yml") => {
+            serde_yaml::from_str::<BTreeMap<String, GlossaryEntry>>(&text)
+                .map_err(anyhow::Error::from)
+        }
+        _ => bail!(
+            "Invalid glossary {} (expected a .toml or .yaml file)",
+            path.display()
+        ),
+    }
+    .with_context(|| format!("Invalid glossary {}", path.display()))?;
+
+    Ok(entries
+        .into_iter()
+        .map(|(name, entry)| {
+            let (definition, aliases) = match entry {
+                GlossaryEntry::Definition(definition) => (definition, vec![]),
+                GlossaryEntry::Full {
+                    definition,
+                    aliases,
+                } => (definition, aliases),
+            };
+            let arena = comrak::Arena::new();
+            let root = comrak::parse_document(&arena, &definition, &comrak_options());
+            Term {
+                name,
+                aliases,
+                summary: node_text(root).trim().to_string(),
+                definition: Some(definition),
+            }
+        })
+        .collect())
+}
+
+/// Index of the glossary terms of a doc
+#[derive(Debug, Default, Clone)]
+pub struct GlossaryIndex {
+    /// Terms
+    terms: Vec<Term>,
+    /// Output path of the glossary page
+    page: PathBuf,
+    /// Generated page (from the glossary file)
+    generated: Option<SourceFile>,
+    /// Adds the definitions to the links, for the tooltips
+    tooltips: bool,
+}
+
+impl GlossaryIndex {
+    /// Loads the glossary terms, from the glossary file or the glossary page
+    ///
+    /// NB: `ext` is the extension of the output pages (eg `html`)
+    pub fn new(cfg: &Config, src_files: &[SourceFile], src_dir: &Path, ext: &str) -> Result<Self> {
+        let glossary_cfg = &cfg.file().glossary;
+        if glossary_cfg.file.is_some() {
+            let terms = load_glossary(cfg)?;
+            // NB: without a title, the file name does not depend on the language (`glossary.md`)
+            let file_name = slugify(glossary_cfg.title.as_deref().unwrap_or(DEFAULT_TITLE));
+            let title = glossary_cfg
+                .title
+                .clone()
+                .or(UIStrings::from_config(cfg)
+                    .get("glossary")
+                    .map(String::from))
+                .unwrap_or(DEFAULT_TITLE.to_string());
+            let mut generated = SourceFile::new(src_dir.join(format!("{file_name}.md")));
+            generated.content = generate_page(&title, &terms).into_bytes();
+            return Ok(Self {
+                terms,
+                page: page_path(&generated.path, src_dir, ext)?,
+                generated: Some(generated),
+                tooltips: false,
+            });
+        }
+
+        let glossary_page = src_files.iter().find(|src_file| {
+            src_file.path.parent() == Some(src_dir)
+                && src_file
+                    .path
+                    .file_stem()
+                    .is_some_and(|stem| slugify(stem.to_string_lossy()) == GLOSSARY_PAGE)
+        });
+        match glossary_page {
+            Some(src_file) => {
+                let md = String::from_utf8(src_file.content.to_vec())?;
+                Ok(Self {
+                    terms: parse_definition_lists(&md),
+                    page: page_path(&src_file.path, src_dir, ext)?,
+                    generated: None,
+                    tooltips: false,
+                })
+            }
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Adds the definitions to the term links (`data-definition`), for the hover tooltips
+    pub fn with_tooltips(mut self) -> Self {
+        self.tooltips = true;
+        self
+    }
+
+    /// Returns the generated glossary page, if the terms are loaded from the glossary file
+    pub fn list_pages(&self) -> Vec<SourceFile> {
+        self.generated.iter().cloned().collect()
+    }
+
+    /// Links the first occurrence of each term of a parsed page to its definition
+    ///
+    /// NB: `page` is the output path of the page. On the glossary page, the terms are anchored
+    /// instead, and the texts of the headings, links and images are not linked. The links are
+    /// inserted as separate nodes, so that the rest of the texts can still be transformed (eg
+    /// the citations).
+    pub fn transform<'a>(
+        &self,
+        arena: &'a Arena<AstNode<'a>>,
+        root: &'a AstNode<'a>,
+        page: &Path,
+    ) -> Result<()> {
+        if self.terms.is_empty() {
+            return Ok(());
+        }
+        if page == self.page {
+            self.anchor_terms(root);
+            return Ok(());
+        }
+
+        let mut linked = HashSet::new();
+        for node in root.descendants().collect::<Vec<_>>() {
+            let text = match &node.data.borrow().value {
+                NodeValue::Text(text) => text.to_string(),
+                _ => continue,
+            };
+            let skipped = node.ancestors().any(|a| {
+                matches!(
+                    a.data.borrow().value,
+                    NodeValue::Heading(..) | NodeValue::Link(..) | NodeValue::Image(..)
+                )
+            });
+            if skipped {
+                continue;
+            }
+            let mut values = self.link_terms(&text, page, &mut linked).into_iter();
+            let first = match values.next() {
+                Some(first) => first,
+                None => continue,
+            };
+            let start = node.data.borrow().sourcepos.start;
+            node.data.borrow_mut().value = first;
+            let mut last = node;
+            for value in values {
+                let next = arena.alloc(Node::new(RefCell::new(Ast::new(value, start))));
+                last.insert_after(next);
+                last = next;
+            }
+        }
+        Ok(())
+    }
+
+    /// Links the terms of a text which are not linked yet, if any
+    ///
+    /// NB: the text is split into texts and links (HTML), and is empty without terms
+    fn link_terms(&self, text: &str, page: &Path, linked: &mut HashSet<usize>) -> Vec<NodeValue> {
+        let mut values = vec![];
+        let mut pos = 0;
+        loop {
+            // NB: the longest term is linked first (eg `source format` before `source`)
+            let next = self
+                .terms
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !linked.contains(i))
+                .flat_map(|(i, term)| {
+                    std::iter::once(&term.name)
+                        .chain(&term.aliases)
+                        .filter_map(move |name| {
+                            find_word(&text[pos..], name).map(|(start, len)| (pos + start, len, i))
+                        })
+                })
+                .min_by_key(|(start, len, _)| (*start, usize::MAX - len));
+            let (start, len, i) = match next {
+                Some(next) => next,
+                None => break,
+            };
+
+            let term = &self.terms[i];
+            let href = format!("{}#{}", relative_href(page, &self.page), term.id());
+            if start > pos {
+                values.push(NodeValue::Text(text[pos..start].to_string()));
+            }
+            let mut html = format!("<a class=\"glossary-term\" href=\"{}\"", escape_xml(&href));
+            if self.tooltips {
+                html.push_str(&format!(
+                    " data-definition=\"{}\"",
+                    escape_xml(&term.summary)
+                ));
+            }
+            html.push_str(&format!(">{}</a>", escape_xml(&text[start..start + len])));
+            values.push(NodeValue::HtmlInline(html));
+            linked.insert(i);
+            pos = start + len;
+        }
+        if !values.is_empty() && pos < text.len() {
+            values.push(NodeValue::Text(text[pos..].to_string()));
+        }
+        values
+    }
+
+    /// Anchors the terms of the definition lists of the glossary page (`id="term-..."`)
+    fn anchor_terms<'a>(&self, root: &'a AstNode<'a>) {
+        for node in root.descendants() {
+            if !matches!(node.data.borrow().value, NodeValue::DescriptionTerm) {
+                continue;
+            }
+            let id = format!("term-{}", slugify(node_text(node).trim()));
+            let first_text = node
+                .descendants()
+                .find(|desc| matches!(desc.data.borrow().value, NodeValue::Text(..)));
+            if let Some(first_text) = first_text {
+                let mut data = first_text.data.borrow_mut();
+                if let NodeValue::Text(text) = &data.value {
+                    let html = format!(
+                        "<span id=\"{}\"></span>{}",
+                        escape_xml(&id),
+                        escape_xml(text)
+                    );
+                    data.value = NodeValue::HtmlInline(html);
+                }
+            }
+        }
+    }
+}
+
+/// Parses the terms of the definition lists of a page
+fn parse_definition_lists(md: &str) -> Vec<Term> {
+    let arena = comrak::Arena::new();
+    let root = comrak::parse_document(&arena, md, &comrak_options());
+    let mut terms = vec![];
+    for item in root.descendants() {
+        if !matches!(item.data.borrow().value, NodeValue::DescriptionItem(..)) {
+            continue;
+        }
+        let mut name = None;
+        let mut summary = String::new();
+        for child in item.children() {
+            match child.data.borrow().value {
+                NodeValue::DescriptionTerm => name = Some(node_text(child).trim().to_string()),
+                NodeValue::DescriptionDetails => summary = node_text(child).trim().to_string(),
+                _ => {}
+            }
+        }
+        if let Some(name) = name.filter(|name| !name.is_empty()) {
+            terms.push(Term {
+                name,
+                aliases: vec![],
+                definition: None,
+                summary,
+            });
+        }
+    }
+    terms
+}
+
+/// Generates the glossary page (Markdown, with the definition list as raw HTML)
+fn generate_page(title: &str, terms: &[Term]) -> String {
+    let opts = comrak_options();
+    let mut terms = terms.iter().collect::<Vec<_>>();
+    terms.sort_by_key(|term| term.name.to_lowercase());
+
+    // NB: the list is a raw HTML block, so it must not contain blank lines
+    let mut md = format!(
+        "---\ntitle: {}\n---\n\n# {title}\n\n<dl class=\"glossary\">\n",
+        serde_json::to_string(title).unwrap_or_default()
+    );
+    for term in terms {
+        let definition = term.definition.as_deref().unwrap_or(&term.summary);
+        let definition = comrak::markdown_to_html(definition, &opts);
+        md.push_str(&format!(
+            "<dt id=\"{}\">{}</dt>\n<dd>{}</dd>\n",
+            escape_xml(&term.id()),
+            escape_xml(&term.name),
+            definition.trim().replace("\n\n", "\n")
+        ));
+    }
+    md.push_str(&format!("</dl>{RAW_HTML_MARKER}\n"));
+    md
+}
+
+/// Finds a word in a text (case-insensitive, at word boundaries), and returns its position and length
+fn find_word(text: &str, word: &str) -> Option<(usize, usize)> {
+    if word.is_empty() {
+        return None;
+    }
+    let lower_text = text.to_lowercase();
+    let lower_word = word.to_lowercase();
+    // NB: the lowercase text is only searched if its byte positions match the text
+    let (haystack, needle) = if lower_text.len() == text.len() {
+        (lower_text.as_str(), lower_word.as_str())
+    } else {
+        (text, word)
+    };
+    haystack.match_indices(needle).find_map(|(start, _)| {
+        let before = text.get(..start)?.chars().next_back();
+        let after = text.get(start + needle.len()..)?.chars().next();
+        let is_boundary = |c: Option<char>| !c.is_some_and(|c| c.is_alphanumeric());
+        if is_boundary(before) && is_boundary(after) {
+            Some((start, needle.len()))
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transform() {
+        let index = GlossaryIndex {
+            terms: vec![Term {
+                name: "Source format".to_string(),
+                aliases: vec!["source formats".to_string()],
+                definition: None,
+                summary: "A format".to_string(),
+            }],
+            page: PathBuf::from("glossary.html"),
+            generated: None,
+            tooltips: false,
+        };
+        let opts = comrak_options();
+        let arena = comrak::Arena::new();
+        let root = comrak::parse_document(
+            &arena,
+            "# Source formats\n\nThe source formats [@key] and the source format.\n",
+            &opts,
+        );
+        index
+            .transform(&arena, root, Path::new("a/b.html"))
+            .unwrap();
+
+        // NB: the rest of the text is a separate text node (the link is raw HTML), and only the
+        // first occurrence is linked
+        let paragraph = root.last_child().unwrap();
+        let values = paragraph
+            .children()
+            .map(|node| node.data.borrow().value.clone())
+            .collect::<Vec<_>>();
+        assert!(matches!(&values[0], NodeValue::Text(text) if text == "The "));
+        assert!(matches!(&values[1], NodeValue::HtmlInline(html)
+            if html == "<a class=\"glossary-term\" href=\"../glossary.html#term-source-format\">source formats</a>"));
+        assert!(values[2..]
+            .iter()
+            .all(|value| matches!(value, NodeValue::Text(..))));
+        assert_eq!(node_text(paragraph), "The  [@key] and the source format.");
+        assert!(matches!(
+            root.first_child()
+                .unwrap()
+                .first_child()
+                .unwrap()
+                .data
+                .borrow()
+                .value,
+            NodeValue::Text(..)
+        ));
+    }
+
+    #[test]
+    fn test_translated_title() {
+        let mut cfg = Config::default();
+        cfg.set_root_dir(Path::new("tests/build"));
+        cfg.load_file().unwrap();
+        cfg.set_locale(Some("fr"));
+        let src_dir = cfg.src_dir();
+        let index = GlossaryIndex::new(&cfg, &[], &src_dir, "html").unwrap();
+
+        // NB: the file name does not depend on the language
+        let page = &index.list_pages()[0];
+        assert_eq!(page.path, src_dir.join("glossary.md"));
+        let md = String::from_utf8(page.content.to_vec()).unwrap();
+        assert!(md.contains("\n# Glossaire\n"));
+    }
+
+    #[test]
+    fn test_generate_page() {
+        let md = generate_page("Terms: A-Z", &[]);
+        assert!(md.starts_with("---\ntitle: \"Terms: A-Z\"\n---\n\n# Terms: A-Z\n"));
+    }
+}
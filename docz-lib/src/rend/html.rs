@@ -1,30 +1,40 @@
 //! HTML renderer
 
+mod strings;
 mod templates;
 
 use std::{
+    collections::BTreeMap,
     ffi::OsStr,
     fs,
     io::BufWriter,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 use anyhow::{anyhow, Context, Result};
-use comrak::{ComrakExtensionOptions, ComrakOptions, ComrakParseOptions, ComrakRenderOptions};
-use fs_extra::dir::CopyOptions;
+use comrak::{
+    nodes::{AstNode, NodeValue},
+    Arena, ComrakExtensionOptions, ComrakOptions, ComrakParseOptions, ComrakRenderOptions,
+};
 use handlebars::Handlebars;
-use log::{debug, trace, warn};
+use log::{debug, trace};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use slug::slugify;
 
 use crate::{
-    cfg::Config,
-    src::{FileMetadata, SourceData, SourceFile},
+    cfg::{did_you_mean, Config, ConfigDiagnostic, DocConfig},
+    doctest::DocTester,
+    src::{FileMetadata, MarkdownFormat, SourceAsset, SourceData, SourceFile, SourceFormat},
 };
 
-use self::templates::HTMLTemplate;
+pub(crate) use self::strings::UIStrings;
+use self::{strings::TranslateHelper, templates::HTMLTemplate};
 
-use super::Renderer;
+use super::{
+    child_index, BookIndex, CalloutMarkup, CalloutRenderer, CitationIndex, DiagramRenderer,
+    FigureIndex, GlossaryIndex, MathIndex, Renderer,
+};
 
 /// Renderer for HTML docs
 #[derive(Debug, Default)]
@@ -49,8 +59,11 @@ const INDEX_TEMPLATE_ID: &str = "_INDEX_";
 /// {page}.html template ID
 const PAGE_TEMPLATE_ID: &str = "_PAGE_";
 
+/// Name of the version built from the working tree (in the version switcher)
+const DEV_VERSION_NAME: &str = "dev";
+
 /// HTML output config (from doc.toml)
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Deserialize, JsonSchema, Default)]
 pub struct HTMLOutputConfig {
     /// Builtin template ID
     pub template: Option<String>,
@@ -60,6 +73,9 @@ pub struct HTMLOutputConfig {
     pub page: Option<PathBuf>,
     /// Add static files to the build dir (or overwrite existing files)
     pub static_files: Option<Vec<(PathBuf, PathBuf)>>,
+    /// Overrides the UI strings of the templates, by language (eg `[output.html.strings.fr]`)
+    #[serde(default)]
+    pub strings: BTreeMap<String, BTreeMap<String, String>>,
 }
 
 /// HTML index.html data
@@ -71,10 +87,53 @@ struct HTMLDocData {
     authors: Vec<String>,
     /// Summary
     summary: String,
+    /// Language
+    lang: String,
+    /// Text direction (`ltr` or `rtl`)
+    dir: Option<&'static str>,
+    /// Meta tags
+    meta: Vec<HTMLMetaTag>,
+    /// JSON-LD metadata (schema.org)
+    json_ld: String,
+    /// Versions (for the version switcher, empty if the doc is not versioned)
+    versions: Vec<HTMLVersion>,
+    /// Languages (for the language switcher, empty if the doc is not translated)
+    languages: Vec<HTMLLanguage>,
     /// All pages
     pages: Vec<HTMLPageData>,
 }
 
+/// HTML version
+#[derive(Debug, Serialize)]
+struct HTMLVersion {
+    /// Name
+    name: String,
+    /// URL of the version index
+    url: String,
+    /// Version being rendered
+    current: bool,
+}
+
+/// HTML language
+#[derive(Debug, Serialize)]
+struct HTMLLanguage {
+    /// Name (eg `fr`)
+    name: String,
+    /// URL of the page (or index) in this language
+    url: String,
+    /// Language being rendered
+    current: bool,
+}
+
+/// HTML meta tag
+#[derive(Debug, Serialize)]
+struct HTMLMetaTag {
+    /// Name
+    name: &'static str,
+    /// Content
+    content: String,
+}
+
 /// HTML page data
 #[derive(Debug, Clone, Serialize)]
 struct HTMLPageData {
@@ -82,6 +141,9 @@ struct HTMLPageData {
     id: String,
     /// URL path
     path: PathBuf,
+    /// URL path, without the version and locale prefix
+    #[serde(skip)]
+    rel_path: PathBuf,
     /// Title
     title: String,
     /// Index (eg 1.2.4) - used for the table of contents
@@ -107,22 +169,21 @@ struct HTMLPageTemplateData<'a> {
     html: String,
     /// Subpages pages
     pages: Vec<HTMLPageData>,
+    /// Languages of the page
+    languages: Vec<HTMLLanguage>,
     /// DOcument
     doc: &'a HTMLDocData,
 }
 
 impl Renderer for HTMLRenderer {
-    fn register(&mut self, cfg: &Config) -> Result<()> {
+    type Config = HTMLOutputConfig;
+
+    fn register(&mut self, cfg: &Config, html_config: &Self::Config) -> Result<()> {
         // (re)init registry
         self.registry = Handlebars::new();
 
-        // get config
-        let html_config = cfg
-            .get_output_cfg::<HTMLOutputConfig>("html")?
-            .unwrap_or_default();
-
         // overwrite the default built-in template
-        if let Some(cfg_template_id) = html_config.template {
+        if let Some(cfg_template_id) = &html_config.template {
             if cfg_template_id.as_str() != self.template.id {
                 match cfg_template_id.as_str() {
                     "default" => {
@@ -140,7 +201,7 @@ impl Renderer for HTMLRenderer {
 
         // overwrite the index template
         let root_dir = cfg.root_dir();
-        if let Some(index_hbs_path) = html_config.index {
+        if let Some(index_hbs_path) = &html_config.index {
             let index_hbs_path = root_dir.join(index_hbs_path);
             trace!(
                 "HTML template, overwriting index template by: {}",
@@ -160,7 +221,7 @@ impl Renderer for HTMLRenderer {
         }
 
         // overwrite the page template
-        if let Some(page_hbs_path) = html_config.page {
+        if let Some(page_hbs_path) = &html_config.page {
             let page_hbs_path = root_dir.join(page_hbs_path);
             trace!(
                 "HTML template, overwriting page template by: {}",
@@ -182,9 +243,9 @@ impl Renderer for HTMLRenderer {
         // Add static assets to copy from the FS
         self.template.fs_static_files = html_config
             .static_files
-            .unwrap_or_default()
-            .into_iter()
-            .map(|(src, dest)| (root_dir.join(src), dest))
+            .iter()
+            .flatten()
+            .map(|(src, dest)| (root_dir.join(src), dest.to_owned()))
             .collect();
 
         // register templates, partials, helpers with Handlebars
@@ -200,76 +261,168 @@ impl Renderer for HTMLRenderer {
             "toc",
             include_str!("html/templates/_partials/toc_partial.hbs"),
         )?;
+        self.registry.register_partial(
+            "meta",
+            include_str!("html/templates/_partials/meta_partial.hbs"),
+        )?;
+        self.registry.register_partial(
+            "versions",
+            include_str!("html/templates/_partials/versions_partial.hbs"),
+        )?;
+        self.registry.register_partial(
+            "languages",
+            include_str!("html/templates/_partials/languages_partial.hbs"),
+        )?;
 
         Ok(())
     }
 
-    fn render(&self, cfg: &Config, src_data: &SourceData) -> Result<()> {
+    fn check_config(&self, _cfg: &Config, html_config: &Self::Config) -> Vec<ConfigDiagnostic> {
+        let mut diags = vec![];
+        if let Some(template_id) = &html_config.template {
+            if !HTMLTemplate::IDS.contains(&template_id.as_str()) {
+                let suggestion =
+                    did_you_mean(template_id, HTMLTemplate::IDS).map(|id| id.to_string());
+                diags.push(
+                    ConfigDiagnostic::error(
+                        Some("template".to_string()),
+                        format!("unknown template ID `{template_id}`"),
+                    )
+                    .suggest(suggestion),
+                );
+            }
+        }
+
+        let keys = UIStrings::keys();
+        let keys = keys.iter().map(|key| key.as_str()).collect::<Vec<_>>();
+        for (lang, strings) in &html_config.strings {
+            for key in strings.keys() {
+                if !keys.contains(&key.as_str()) {
+                    diags.push(ConfigDiagnostic::unknown_key(
+                        &["strings", lang, key],
+                        &keys,
+                    ));
+                }
+            }
+        }
+        diags
+    }
+
+    fn render(
+        &self,
+        cfg: &Config,
+        html_config: &Self::Config,
+        src_data: &SourceData,
+    ) -> Result<()> {
         // process the source files to template data
         let doc = process_src_data(cfg, src_data)?;
         debug!("HTML template data \n{doc:#?}");
 
+        // UI strings (NB: the language differs between the locales sharing the registry)
+        let ui_strings = UIStrings::load(&doc.lang, &html_config.strings)?;
+        let mut registry = self.registry.clone();
+        registry.register_helper("t", Box::new(TranslateHelper::new(ui_strings)));
+
         // create HTML dir inside build (NB: /build has been cleared before)
+        // NB: versions and locales are rendered in a sub dir, and share the static files
         let build_dir = cfg.build_dir().join("html");
-        fs::create_dir_all(&build_dir)?;
+        let version_dir = build_dir.join(path_prefix(cfg));
+        fs::create_dir_all(&version_dir)?;
 
         // render index.html
-        let index_file_str = self.registry.render(INDEX_TEMPLATE_ID, &doc)?;
-        let index_file = build_dir.join("index.html");
+        let index_file_str = registry.render(INDEX_TEMPLATE_ID, &doc)?;
+        let index_file = version_dir.join("index.html");
         fs::write(index_file, index_file_str)?;
 
-        // render {page}.html
+        // render {page}.html (NB: the page paths include the version and locale)
         for page in &doc.pages {
-            self.render_page_iter(page, &build_dir, &doc)?;
+            render_page_iter(&registry, cfg, page, &build_dir, &doc)?;
         }
 
         // write embedded static files
         for (file_name, file_data) in &self.template.embed_static_files {
-            fs::write(&build_dir.join(file_name), file_data)?;
+            fs::write(build_dir.join(file_name), file_data)?;
         }
 
         // write filesystem static files
         for (src, dest) in &self.template.fs_static_files {
-            fs::copy(src, &build_dir.join(dest))?;
+            fs::copy(src, build_dir.join(dest))?;
         }
 
-        // copy source assets
-        let build_assets_dir = build_dir.join(cfg.assets_dir_name());
+        // write source assets
+        let src_assets_dir = cfg.src_assets_dir();
+        let build_assets_dir = version_dir.join(cfg.assets_dir_name());
         fs::create_dir_all(&build_assets_dir)?;
-        fs_extra::copy_items(&src_data.assets, build_assets_dir, &CopyOptions::new())?;
+        for asset in &src_data.assets {
+            let asset_file = build_assets_dir.join(asset.path.strip_prefix(&src_assets_dir)?);
+            fs::create_dir_all(asset_file.parent().unwrap())?;
+            fs::write(asset_file, &asset.content)?;
+        }
 
         Ok(())
     }
+
+    fn versioned(&self) -> bool {
+        true
+    }
+
+    /// NB: the version dir is copied (with its locales), so the pages of the alias link to
+    /// the version
+    fn alias_version(&self, cfg: &Config, alias: &str) -> Result<()> {
+        let build_dir = cfg.build_dir().join("html");
+        let version_dir = build_dir.join(path_prefix(cfg));
+        copy_dir_iter(&version_dir, &build_dir.join(alias))
+    }
+
+    fn localized(&self) -> bool {
+        true
+    }
 }
 
-impl HTMLRenderer {
-    /// Renders the individual pages
-    fn render_page_iter(
-        &self,
-        page: &HTMLPageData,
-        build_dir: &Path,
-        doc: &HTMLDocData,
-    ) -> Result<()> {
-        let page = HTMLPageTemplateData {
-            id: page.id.clone(),
-            path: page.path.clone(),
-            title: page.title.clone(),
-            index: page.index.clone(),
-            html: page.html.clone(),
-            pages: page.pages.clone(),
-            doc,
-        };
-        let page_file_str = self.registry.render(PAGE_TEMPLATE_ID, &page)?;
-        let page_file = build_dir.join(&page.path);
-        let parent_dir = page_file.parent().unwrap();
-        fs::create_dir_all(parent_dir)?;
-        fs::write(page_file, page_file_str)?;
-
-        for page in &page.pages {
-            self.render_page_iter(page, build_dir, doc)?;
+/// Copies a dir recursively
+fn copy_dir_iter(src: &Path, dest: &Path) -> Result<()> {
+    fs::create_dir_all(dest)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let dest_path = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_iter(&entry.path(), &dest_path)?;
+        } else {
+            fs::copy(entry.path(), dest_path)?;
         }
-        Ok(())
     }
+    Ok(())
+}
+
+/// Renders the individual pages
+fn render_page_iter(
+    registry: &Handlebars,
+    cfg: &Config,
+    page: &HTMLPageData,
+    build_dir: &Path,
+    doc: &HTMLDocData,
+) -> Result<()> {
+    let languages = languages(cfg, &page.rel_path.to_string_lossy());
+    let page = HTMLPageTemplateData {
+        id: page.id.clone(),
+        path: page.path.clone(),
+        title: page.title.clone(),
+        index: page.index.clone(),
+        html: page.html.clone(),
+        pages: page.pages.clone(),
+        languages,
+        doc,
+    };
+    let page_file_str = registry.render(PAGE_TEMPLATE_ID, &page)?;
+    let page_file = build_dir.join(&page.path);
+    let parent_dir = page_file.parent().unwrap();
+    fs::create_dir_all(parent_dir)?;
+    fs::write(page_file, page_file_str)?;
+
+    for page in &page.pages {
+        render_page_iter(registry, cfg, page, build_dir, doc)?;
+    }
+    Ok(())
 }
 
 /// Returns the comrak options
@@ -305,39 +458,392 @@ pub fn comrak_options() -> ComrakOptions {
     }
 }
 
+/// Returns the comrak options of the HTML pages
+///
+/// NB: the raw HTML is rendered (eg callouts, HTML fragments, notebook outputs), without
+/// the unsafe tags unless `[src] raw_html` is set. The raw HTML of the Markdown pages is
+/// filtered before (see [filter_raw_html]).
+pub(crate) fn html_comrak_options(cfg: &Config) -> ComrakOptions {
+    let mut opts = comrak_options();
+    opts.extension.tagfilter = !cfg.file().src.raw_html;
+    opts.render.unsafe_ = true;
+    opts
+}
+
+/// Marker of the raw HTML generated in the Markdown of the pages (eg callouts), which is kept
+/// by [filter_raw_html]
+///
+/// NB: it is a private use char, removed from the sources when they are loaded
+pub(crate) const RAW_HTML_MARKER: char = '\u{E002}';
+
+/// Filters the raw HTML of a parsed page, and removes the markers of the generated raw HTML
+///
+/// NB: unless `raw_html` is set (`[src] raw_html`), the raw HTML written in a Markdown page
+/// is omitted and its unsafe URLs (eg `javascript:`) are removed, as with the safe rendering
+/// of comrak. The pages converted from other formats (eg HTML fragments) keep their raw HTML.
+pub(crate) fn filter_raw_html<'a>(root: &'a AstNode<'a>, raw_html: bool, path: &Path) {
+    let markdown = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            MarkdownFormat::default()
+                .extensions()
+                .contains(&ext.to_lowercase().as_str())
+        });
+    let filtered = markdown && !raw_html;
+
+    for node in root.descendants() {
+        let value = &mut node.data.borrow_mut().value;
+        match value {
+            NodeValue::HtmlBlock(block) => {
+                if block.literal.contains(RAW_HTML_MARKER) {
+                    block.literal = block.literal.replace(RAW_HTML_MARKER, "");
+                } else if filtered {
+                    block.literal = "<!-- raw HTML omitted -->\n".to_string();
+                }
+            }
+            NodeValue::HtmlInline(html) => {
+                if html.contains(RAW_HTML_MARKER) {
+                    *html = html.replace(RAW_HTML_MARKER, "");
+                } else if filtered {
+                    *html = "<!-- raw HTML omitted -->".to_string();
+                }
+            }
+            NodeValue::Text(text) if text.contains(RAW_HTML_MARKER) => {
+                *text = text.replace(RAW_HTML_MARKER, "");
+            }
+            NodeValue::Link(link) | NodeValue::Image(link)
+                if filtered && is_unsafe_url(&link.url) =>
+            {
+                link.url = String::new();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Checks if a URL is unsafe (scripts, local files and non-image data)
+fn is_unsafe_url(url: &str) -> bool {
+    let url = url.trim().to_lowercase();
+    if let Some(data) = url.strip_prefix("data:") {
+        return !["image/png", "image/gif", "image/jpeg", "image/webp"]
+            .iter()
+            .any(|mime| data.starts_with(mime));
+    }
+    ["javascript:", "vbscript:", "file:"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+}
+
 /// Extracts the HTML data from the source data
 fn process_src_data(cfg: &Config, src_data: &SourceData) -> Result<HTMLDocData> {
-    let comrak_opts = comrak_options();
-    let src_dir = cfg.src_dir();
-    let pages = process_src_files_iter(&src_data.files, &comrak_opts, &src_dir, "")?;
+    let (ctx, files) = PageContext::new(cfg, src_data)?;
+    let pages = process_src_files_iter(&files, &ctx, "")?;
 
+    let doc = &cfg.file().doc;
     Ok(HTMLDocData {
-        title: cfg.file().doc.title.to_string(),
-        authors: cfg.file().doc.authors.to_vec(),
-        summary: cfg.file().doc.summary.to_string(),
+        title: doc.title.to_string(),
+        authors: doc.authors.to_vec(),
+        summary: doc.summary.to_string(),
+        lang: cfg.lang(),
+        dir: doc.direction.map(|dir| dir.as_str()),
+        meta: meta_tags(doc),
+        // NB: `</` is escaped so that the JSON cannot close the script tag
+        json_ld: json_ld(doc).to_string().replace("</", "<\\/"),
+        versions: versions(cfg),
+        languages: languages(cfg, ""),
         pages,
     })
 }
 
+/// Returns the prefix of the page paths (version and locale sub dirs)
+fn path_prefix(cfg: &Config) -> PathBuf {
+    PathBuf::from(cfg.version().unwrap_or("")).join(cfg.locale().unwrap_or(""))
+}
+
+/// Returns the languages of a page (the default language first)
+///
+/// NB: `rel_path` is the path of the page without prefix (empty for the index)
+fn languages(cfg: &Config, rel_path: &str) -> Vec<HTMLLanguage> {
+    let locales = cfg.locales();
+    if locales.is_empty() {
+        return vec![];
+    }
+
+    // NB: the locales are built per version
+    let rel_path = rel_path.replace('\\', "/");
+    let version_prefix = match cfg.version() {
+        Some(version) => format!("/{version}"),
+        None => String::new(),
+    };
+    let mut languages = vec![HTMLLanguage {
+        name: cfg.default_lang(),
+        url: format!("{version_prefix}/{rel_path}"),
+        current: cfg.locale().is_none(),
+    }];
+    for locale in locales {
+        languages.push(HTMLLanguage {
+            url: format!("{version_prefix}/{locale}/{rel_path}"),
+            current: cfg.locale() == Some(locale.as_str()),
+            name: locale,
+        });
+    }
+    languages
+}
+
+/// Returns the versions of the doc (the working tree first)
+fn versions(cfg: &Config) -> Vec<HTMLVersion> {
+    if cfg.versions().is_empty() {
+        return vec![];
+    }
+
+    let mut versions = vec![HTMLVersion {
+        name: DEV_VERSION_NAME.to_string(),
+        url: "/".to_string(),
+        current: cfg.version().is_none(),
+    }];
+    for version in cfg.versions() {
+        versions.push(HTMLVersion {
+            name: version.to_string(),
+            url: format!("/{version}/"),
+            current: cfg.version() == Some(version.as_str()),
+        });
+    }
+    versions
+}
+
+/// Returns the meta tags of the doc
+fn meta_tags(doc: &DocConfig) -> Vec<HTMLMetaTag> {
+    let mut tags = vec![
+        HTMLMetaTag {
+            name: "description",
+            content: doc.summary.to_string(),
+        },
+        HTMLMetaTag {
+            name: "generator",
+            content: format!("docz {}", env!("CARGO_PKG_VERSION")),
+        },
+    ];
+    if !doc.authors.is_empty() {
+        tags.push(HTMLMetaTag {
+            name: "author",
+            content: doc.authors.join(", "),
+        });
+    }
+    if !doc.subjects.is_empty() {
+        tags.push(HTMLMetaTag {
+            name: "keywords",
+            content: doc.subjects.join(", "),
+        });
+    }
+    let optional_tags = [
+        ("dcterms.identifier", &doc.identifier),
+        ("dcterms.publisher", &doc.publisher),
+        ("dcterms.license", &doc.license),
+        ("dcterms.rights", &doc.rights),
+        ("dcterms.date", &doc.publication_date),
+    ];
+    for (name, content) in optional_tags {
+        if let Some(content) = content {
+            tags.push(HTMLMetaTag {
+                name,
+                content: content.to_string(),
+            });
+        }
+    }
+    tags
+}
+
+/// Returns the JSON-LD metadata of the doc (schema.org `Book`)
+fn json_ld(doc: &DocConfig) -> serde_json::Value {
+    let person = |name: &str| serde_json::json!({ "@type": "Person", "name": name });
+
+    let mut ld = serde_json::json!({
+        "@context": "https://schema.org",
+        "@type": "Book",
+        "name": doc.title,
+        "description": doc.summary,
+        "author": doc.authors.iter().map(|author| person(author.as_str())).collect::<Vec<_>>(),
+    });
+    let obj = ld.as_object_mut().unwrap();
+    if let Some(language) = &doc.language {
+        obj.insert("inLanguage".to_string(), language.as_str().into());
+    }
+    if let Some(identifier) = &doc.identifier {
+        match identifier.strip_prefix("urn:isbn:") {
+            Some(isbn) => obj.insert("isbn".to_string(), isbn.into()),
+            None => obj.insert("identifier".to_string(), identifier.as_str().into()),
+        };
+    }
+    if let Some(publisher) = &doc.publisher {
+        obj.insert(
+            "publisher".to_string(),
+            serde_json::json!({ "@type": "Organization", "name": publisher }),
+        );
+    }
+    if let Some(license) = &doc.license {
+        obj.insert("license".to_string(), license.as_str().into());
+    }
+    if let Some(rights) = &doc.rights {
+        obj.insert("copyrightNotice".to_string(), rights.as_str().into());
+    }
+    if let Some(date) = &doc.publication_date {
+        obj.insert("datePublished".to_string(), date.as_str().into());
+    }
+    if !doc.subjects.is_empty() {
+        obj.insert("keywords".to_string(), doc.subjects.join(", ").into());
+    }
+    for contributor in &doc.contributors {
+        // MARC relator codes with a schema.org property
+        let property = match contributor.role.as_deref() {
+            Some("edt") => "editor",
+            Some("trl") => "translator",
+            Some("ill") => "illustrator",
+            _ => "contributor",
+        };
+        let people = obj
+            .entry(property)
+            .or_insert_with(|| serde_json::Value::Array(vec![]));
+        if let Some(people) = people.as_array_mut() {
+            people.push(person(contributor.name.as_str()));
+        }
+    }
+    if let Some(series) = &doc.series {
+        obj.insert(
+            "isPartOf".to_string(),
+            serde_json::json!({ "@type": "BookSeries", "name": series.name }),
+        );
+        if let Some(position) = series.position {
+            obj.insert("position".to_string(), position.into());
+        }
+    }
+    ld
+}
+
+/// Context of the processing of the pages
+///
+/// NB: it is shared with the outputs embedding the HTML pages (JSON, slides)
+pub(crate) struct PageContext {
+    /// Markdown options
+    pub comrak_opts: ComrakOptions,
+    /// Equations
+    pub math: MathIndex,
+    /// Diagrams
+    diagrams: DiagramRenderer,
+    /// Callouts
+    pub callouts: CalloutRenderer,
+    /// Figures, tables and listings
+    figures: FigureIndex,
+    /// Citations
+    pub citations: CitationIndex,
+    /// Glossary
+    glossary: GlossaryIndex,
+    /// Index
+    book_index: BookIndex,
+    /// Doc tests (hidden lines)
+    doctests: DocTester,
+    /// Raw HTML kept in the Markdown pages (`[src] raw_html`)
+    pub raw_html: bool,
+    /// Source dir
+    pub src_dir: PathBuf,
+    /// Prefix of the page paths (version and locale sub dirs)
+    path_prefix: PathBuf,
+}
+
+impl PageContext {
+    /// Creates the context of the pages, and returns the source files along with the generated
+    /// pages (eg the list of figures, the references, the glossary and the index)
+    pub fn new(cfg: &Config, src_data: &SourceData) -> Result<(Self, Vec<SourceFile>)> {
+        let src_dir = cfg.src_dir();
+        let figures = FigureIndex::new(cfg, &src_data.files, &src_dir, "html")?;
+        let citations = CitationIndex::new(cfg, &src_data.files, &src_dir, "html")?;
+        let glossary = GlossaryIndex::new(cfg, &src_data.files, &src_dir, "html")?.with_tooltips();
+        let book_index = BookIndex::new(&src_data.files, &src_dir, "html")?.with_anchors();
+        let mut files = src_data.files.clone();
+        files.extend(figures.list_pages(cfg));
+        files.extend(citations.list_pages(cfg));
+        files.extend(glossary.list_pages());
+        files.extend(book_index.list_pages(cfg));
+        let ctx = Self {
+            comrak_opts: html_comrak_options(cfg),
+            math: MathIndex::new(&files, &src_dir, "html")?,
+            diagrams: DiagramRenderer::new(cfg),
+            callouts: CalloutRenderer::new(cfg, CalloutMarkup::HTML),
+            figures,
+            citations,
+            glossary,
+            book_index,
+            doctests: DocTester::new(cfg),
+            raw_html: cfg.file().src.raw_html,
+            src_dir,
+            path_prefix: path_prefix(cfg),
+        };
+        Ok((ctx, files))
+    }
+
+    /// Converts a page to HTML, with the transforms of the HTML output (see [Self::transform])
+    /// and its bibliography
+    ///
+    /// NB: `inspect` gets the parsed page before it is transformed (eg to extract the headings)
+    pub fn page_html(
+        &self,
+        src_file: &SourceFile,
+        index: &str,
+        rel_path: &Path,
+        inspect: impl for<'a> FnOnce(&'a AstNode<'a>) -> Result<()>,
+    ) -> Result<(String, FileMetadata)> {
+        // NB: the pages are converted to Markdown when they are loaded (source formats)
+        let content_str = String::from_utf8(src_file.content.to_vec())?;
+        let content_str = self.callouts.process(&content_str);
+        let (mut html, metadata) = self.math.markdown_to_html(
+            &content_str,
+            &self.comrak_opts,
+            rel_path,
+            |arena, root| {
+                filter_raw_html(root, self.raw_html, &src_file.path);
+                inspect(root)?;
+                self.transform(arena, root, index, rel_path)
+            },
+        )?;
+        html.push_str(&self.citations.bibliography_html(rel_path));
+        Ok((html, metadata))
+    }
+
+    /// Transforms a parsed page (index, glossary, doc tests, citations, figures and diagrams)
+    ///
+    /// NB: `index` is the index of the page (eg `2.1`) and `rel_path` its output path
+    /// (eg `02-chapter_2/sub.html`)
+    pub fn transform<'a>(
+        &self,
+        arena: &'a Arena<AstNode<'a>>,
+        root: &'a AstNode<'a>,
+        index: &str,
+        rel_path: &Path,
+    ) -> Result<()> {
+        // NB: the index markers are removed first, and the glossary terms are linked
+        // before the citations and captions are replaced with HTML
+        self.book_index.transform(root)?;
+        self.glossary.transform(arena, root, rel_path)?;
+        self.doctests.transform(root)?;
+        self.citations.transform(root, rel_path)?;
+        // NB: captioned code blocks are listings, not diagrams
+        self.figures
+            .transform(root, &self.comrak_opts, index, rel_path)?;
+        self.diagrams.inline_svgs(root)
+    }
+}
+
 /// Processes source files recursively
 fn process_src_files_iter(
     src_files: &[SourceFile],
-    comrak_opts: &ComrakOptions,
-    src_dir: &Path,
+    ctx: &PageContext,
     parent_index: &str,
 ) -> Result<Vec<HTMLPageData>> {
     let mut pages = vec![];
     for (i, src_file) in src_files.iter().enumerate() {
-        let index = format!(
-            "{}{}{}",
-            parent_index,
-            if parent_index.is_empty() { "" } else { "." },
-            i + 1
-        );
-
-        if let Some(page) = process_src_file_iter(src_file, comrak_opts, src_dir, &index)? {
-            pages.push(page);
-        };
+        let index = child_index(parent_index, i);
+        let page = process_src_file_iter(src_file, ctx, &index)?;
+        pages.push(page);
     }
     Ok(pages)
 }
@@ -345,26 +851,9 @@ fn process_src_files_iter(
 /// Processes a source file recursively
 fn process_src_file_iter(
     src_file: &SourceFile,
-    comrak_opts: &ComrakOptions,
-    src_dir: &Path,
+    ctx: &PageContext,
     index: &str,
-) -> Result<Option<HTMLPageData>> {
-    let (html, metadata) = match src_file
-        .path
-        .extension()
-        .map(|ext| ext.to_str().unwrap_or(""))
-        .unwrap_or("")
-    {
-        "md" | "markdown" => {
-            let content_str = String::from_utf8(src_file.content.to_vec())?;
-            markdown_to_html(&content_str, comrak_opts)?
-        }
-        _ => {
-            warn!("Skipped file {}", src_file.path.display());
-            return Ok(None);
-        }
-    };
-
+) -> Result<HTMLPageData> {
     let id = {
         let file_name = src_file
             .path
@@ -375,58 +864,153 @@ fn process_src_file_iter(
         slugify(file_name)
     };
 
-    let title = metadata.title.unwrap_or(id.clone());
-    let index = index.to_string();
-
-    let path = src_file
+    let rel_path = src_file
         .path
-        .strip_prefix(src_dir)
+        .strip_prefix(&ctx.src_dir)
         .context("Source file path is not within the source dir")?
         .with_file_name(&id)
         .with_extension("html");
+    let path = ctx.path_prefix.join(&rel_path);
 
-    let pages = process_src_files_iter(&src_file.children, comrak_opts, src_dir, &index)?;
+    let (html, metadata) = ctx.page_html(src_file, index, &rel_path, |_| Ok(()))?;
+    let title = metadata.title.unwrap_or(id.clone());
+    let index = index.to_string();
 
-    Ok(Some(HTMLPageData {
+    let pages = process_src_files_iter(&src_file.children, ctx, &index)?;
+
+    Ok(HTMLPageData {
         id,
         path,
+        rel_path,
         title,
         index,
         html,
         pages,
-    }))
+    })
 }
 
 /// Extracts the markdown content and converts to HTML
 pub fn markdown_to_html(md: &str, opts: &ComrakOptions) -> Result<(String, FileMetadata)> {
+    markdown_to_html_with(md, opts, |_, _| Ok(()))
+}
+
+/// Extracts the markdown content and converts to HTML, transforming the parsed document first
+/// (eg diagrams)
+///
+/// NB: the transform gets the arena of the document, to add nodes
+pub fn markdown_to_html_with(
+    md: &str,
+    opts: &ComrakOptions,
+    transform: impl for<'a> FnOnce(&'a Arena<AstNode<'a>>, &'a AstNode<'a>) -> Result<()>,
+) -> Result<(String, FileMetadata)> {
     // extract
     let arena = comrak::Arena::new();
     let root = comrak::parse_document(&arena, md, opts);
 
     // frontmatter > metadata
-    let metadata = match root.children().next() {
-        Some(node) => {
-            if let comrak::nodes::NodeValue::FrontMatter(ref fm) = node.data.borrow().value {
-                let fm = match fm.strip_prefix("---") {
-                    Some(fm) => fm.trim(),
-                    None => return Err(anyhow!("Invalid frontmatter, missing leading ---")),
-                };
-                let fm = match fm.strip_suffix("---") {
-                    Some(fm) => fm.trim(),
-                    None => return Err(anyhow!("Invalid frontmatter, missing trailing --- )")),
-                };
-                serde_yaml::from_str::<FileMetadata>(fm)?
-            } else {
-                FileMetadata::default()
-            }
-        }
+    let metadata = match frontmatter(root)? {
+        Some(fm) => serde_yaml::from_str::<FileMetadata>(&fm)?,
         None => FileMetadata::default(),
     };
 
     // > HTML
+    transform(&arena, root)?;
     let mut bw = BufWriter::new(Vec::new());
     comrak::format_html_with_plugins(root, opts, &mut bw, &comrak::ComrakPlugins::default())?;
     let html = String::from_utf8(bw.into_inner()?)?;
 
     Ok((html, metadata))
 }
+
+/// Extracts the raw frontmatter (without the --- delimiters) of a parsed document
+pub(crate) fn frontmatter<'a>(root: &'a AstNode<'a>) -> Result<Option<String>> {
+    let node = match root.children().next() {
+        Some(node) => node,
+        None => return Ok(None),
+    };
+    let fm = if let NodeValue::FrontMatter(ref fm) = node.data.borrow().value {
+        fm.clone()
+    } else {
+        return Ok(None);
+    };
+    let fm = match fm.strip_prefix("---") {
+        Some(fm) => fm.trim(),
+        None => return Err(anyhow!("Invalid frontmatter, missing leading ---")),
+    };
+    let fm = match fm.strip_suffix("---") {
+        Some(fm) => fm.trim(),
+        None => return Err(anyhow!("Invalid frontmatter, missing trailing --- )")),
+    };
+    Ok(Some(fm.to_string()))
+}
+
+/// Collects the plain text of a node (eg heading text, image alt text)
+pub(crate) fn node_text<'a>(node: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+    for desc in node.descendants() {
+        match &desc.data.borrow().value {
+            NodeValue::Text(t) => text.push_str(t),
+            NodeValue::Code(code) => text.push_str(&code.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => text.push(' '),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Finds the asset referenced by a URL
+pub(crate) fn find_asset<'a>(assets: &'a [SourceAsset], url: &str) -> Option<&'a SourceAsset> {
+    let rel_path = Path::new(url)
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect::<PathBuf>();
+    if rel_path.as_os_str().is_empty() {
+        return None;
+    }
+    assets.iter().find(|asset| asset.path.ends_with(&rel_path))
+}
+
+/// Escapes XML text
+pub(crate) fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_raw_html() {
+        let cfg = Config::default();
+        let src_dir = cfg.src_dir();
+        let md = "> [!NOTE]\n> <b onclick=\"alert(1)\">Hi</b> [link](javascript:alert(1))\n\n<div>Raw</div>\n";
+        let mut page = SourceFile::new(src_dir.join("01-page.md"));
+        page.content = md.as_bytes().to_vec();
+        let mut fragment = SourceFile::new(src_dir.join("02-fragment.html"));
+        fragment.content = md.as_bytes().to_vec();
+        let src_data = SourceData {
+            files: vec![page, fragment],
+            assets: vec![],
+        };
+        let (ctx, files) = PageContext::new(&cfg, &src_data).unwrap();
+
+        // Markdown page: only the generated raw HTML is kept
+        let (html, _) = ctx
+            .page_html(&files[0], "1", Path::new("01-page.html"), |_| Ok(()))
+            .unwrap();
+        assert!(html.contains("<aside class=\"callout callout-note\" role=\"note\">"));
+        assert!(!html.contains("onclick"));
+        assert!(!html.contains("javascript:"));
+        assert!(!html.contains("<div>Raw</div>"));
+        assert!(!html.contains(RAW_HTML_MARKER));
+
+        // converted page: the raw HTML is kept
+        let (html, _) = ctx
+            .page_html(&files[1], "2", Path::new("02-fragment.html"), |_| Ok(()))
+            .unwrap();
+        assert!(html.contains("<div>Raw</div>"));
+    }
+}
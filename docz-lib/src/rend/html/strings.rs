@@ -0,0 +1,140 @@
+//! UI strings
+//!
+//! The built-in templates get their UI text from a translation catalog, with the `t` helper
+//! (eg `{{t "table_of_contents"}}`). Catalogs are bundled for common languages,
+//! and can be overridden with `[output.html.strings.<lang>]`.
+//!
+//! The generated content of all the outputs (eg the callout titles) is translated with
+//! the same catalogs.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context as _, Result};
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext, RenderError,
+};
+
+use crate::cfg::Config;
+
+use super::HTMLOutputConfig;
+
+/// Bundled catalogs (language, TOML)
+const BUNDLED_STRINGS: &[(&str, &str)] = &[
+    ("en", include_str!("strings/en.toml")),
+    ("fr", include_str!("strings/fr.toml")),
+    ("de", include_str!("strings/de.toml")),
+    ("es", include_str!("strings/es.toml")),
+    ("it", include_str!("strings/it.toml")),
+    ("pt", include_str!("strings/pt.toml")),
+    ("ja", include_str!("strings/ja.toml")),
+    ("zh", include_str!("strings/zh.toml")),
+];
+
+/// Language of the fallback catalog
+const FALLBACK_LANG: &str = "en";
+
+/// UI strings of a language
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UIStrings {
+    strings: BTreeMap<String, String>,
+}
+
+impl UIStrings {
+    /// Loads the UI strings of a language (eg `fr-CA`)
+    ///
+    /// Strings are looked up in the overrides and the bundled catalogs, for the language,
+    /// then its primary subtag (eg `fr`), then English.
+    pub(crate) fn load(
+        lang: &str,
+        overrides: &BTreeMap<String, BTreeMap<String, String>>,
+    ) -> Result<Self> {
+        let primary = lang.split(['-', '_']).next().unwrap_or(lang);
+        let mut strings = BTreeMap::new();
+        for lang in [FALLBACK_LANG, primary, lang] {
+            if let Some((_, toml_str)) = BUNDLED_STRINGS.iter().find(|(l, _)| *l == lang) {
+                let bundled = toml::from_str::<BTreeMap<String, String>>(toml_str)
+                    .with_context(|| format!("Invalid bundled UI strings ({lang})"))?;
+                strings.extend(bundled);
+            }
+            if let Some(lang_overrides) = overrides.get(lang) {
+                strings.extend(lang_overrides.clone());
+            }
+        }
+        Ok(Self { strings })
+    }
+
+    /// Loads the UI strings of the language of a config (with the `[output.html.strings]` overrides)
+    ///
+    /// NB: an invalid override falls back to the bundled catalogs (it is reported by the config check)
+    pub(crate) fn from_config(cfg: &Config) -> Self {
+        let overrides = cfg
+            .get_output_cfg::<HTMLOutputConfig>("html")
+            .ok()
+            .flatten()
+            .map(|html_cfg| html_cfg.strings)
+            .unwrap_or_default();
+        Self::load(&cfg.lang(), &overrides)
+            .or_else(|_| Self::load(&cfg.lang(), &BTreeMap::new()))
+            .unwrap_or_default()
+    }
+
+    /// Returns a UI string
+    pub(crate) fn get(&self, key: &str) -> Option<&str> {
+        self.strings.get(key).map(|s| s.as_str())
+    }
+
+    /// Returns the keys of the UI strings (from the fallback catalog)
+    pub(crate) fn keys() -> Vec<String> {
+        Self::load(FALLBACK_LANG, &BTreeMap::new())
+            .map(|ui_strings| ui_strings.strings.into_keys().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Handlebars helper translating a UI string (eg `{{t "toc_item" index=index title=title}}`)
+///
+/// NB: `{name}` placeholders are replaced by the hash parameters, and unknown keys are
+/// rendered as is.
+pub(crate) struct TranslateHelper {
+    ui_strings: UIStrings,
+}
+
+impl TranslateHelper {
+    /// Creates a new helper
+    pub(crate) fn new(ui_strings: UIStrings) -> Self {
+        Self { ui_strings }
+    }
+}
+
+impl HelperDef for TranslateHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let key = h
+            .param(0)
+            .and_then(|param| param.value().as_str())
+            .ok_or(RenderError::new("Helper `t` expects a string key"))?;
+
+        let mut text = self
+            .ui_strings
+            .strings
+            .get(key)
+            .cloned()
+            .unwrap_or(key.to_string());
+        for (name, value) in h.hash() {
+            let value = match value.value() {
+                serde_json::Value::String(s) => s.to_string(),
+                value => value.to_string(),
+            };
+            text = text.replace(&format!("{{{name}}}"), &value);
+        }
+
+        out.write(&handlebars::html_escape(&text))?;
+        Ok(())
+    }
+}
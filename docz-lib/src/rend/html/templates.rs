@@ -36,6 +36,7 @@ static SHARED_STATIC_FILES: &[(&str, &[u8])] = &[
     ),
     ("theme.js", include_bytes!("templates/_shared/theme.js")),
     ("sidebar.js", include_bytes!("templates/_shared/sidebar.js")),
+    ("tabs.js", include_bytes!("templates/_shared/tabs.js")),
 ];
 
 impl Default for HTMLTemplate {
@@ -55,6 +56,9 @@ impl Default for HTMLTemplate {
 }
 
 impl HTMLTemplate {
+    /// Built-in template IDs
+    pub const IDS: &'static [&'static str] = &["default", "article"];
+
     /// Returns the template for article
     pub fn article() -> Self {
         let mut embed_static_files: Vec<(&'static str, &'static [u8])> =
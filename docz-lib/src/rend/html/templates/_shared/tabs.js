@@ -0,0 +1,52 @@
+// Tab groups (`:::tabs` and `:::code-group`)
+//
+// NB: the tabs with the same label are selected together, across the page
+
+function selectTab(key) {
+    document.querySelectorAll('.tabs').forEach((tabsElt) => {
+        const tabElts = Array.from(tabsElt.querySelectorAll(':scope > .tabs-list > [role="tab"]'));
+        const selectedElt = tabElts.find((tabElt) => tabElt.dataset.tab === key);
+        if (!selectedElt) {
+            return;
+        }
+        tabElts.forEach((tabElt) => {
+            const selected = tabElt === selectedElt;
+            tabElt.setAttribute('aria-selected', selected);
+            tabElt.tabIndex = selected ? 0 : -1;
+            document.getElementById(tabElt.getAttribute('aria-controls')).hidden = !selected;
+        });
+    });
+}
+
+window.addEventListener('load', () => {
+    document.querySelectorAll('.tabs [role="tab"]').forEach((tabElt) => {
+        // click handler
+        tabElt.addEventListener('click', () => selectTab(tabElt.dataset.tab));
+
+        // keyboard handler (arrows, home and end)
+        tabElt.addEventListener('keydown', (event) => {
+            const tabElts = Array.from(tabElt.parentElement.querySelectorAll('[role="tab"]'));
+            const i = tabElts.indexOf(tabElt);
+            let nextElt;
+            switch (event.key) {
+                case 'ArrowRight':
+                    nextElt = tabElts[(i + 1) % tabElts.length];
+                    break;
+                case 'ArrowLeft':
+                    nextElt = tabElts[(i - 1 + tabElts.length) % tabElts.length];
+                    break;
+                case 'Home':
+                    nextElt = tabElts[0];
+                    break;
+                case 'End':
+                    nextElt = tabElts[tabElts.length - 1];
+                    break;
+                default:
+                    return;
+            }
+            event.preventDefault();
+            selectTab(nextElt.dataset.tab);
+            nextElt.focus();
+        });
+    });
+});
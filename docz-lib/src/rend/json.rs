@@ -0,0 +1,323 @@
+//! JSON renderer
+//!
+//! Writes the whole document to `build/doc.json`, for downstream tools (search ingestion,
+//! translation, chatbots, ...).
+//!
+//! The output follows a versioned schema ([JSON_SCHEMA_VERSION]), written next to it as a JSON
+//! Schema (`build/doc.schema.json`, see [json_schema]). Fields are only added within a version,
+//! any breaking change bumps the version.
+//!
+//! NB: the HTML of the pages is the one of the HTML output (callouts, math, figures, citations, ...).
+
+use std::{fs, path::PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use comrak::nodes::{AstNode, NodeValue};
+use schemars::{
+    schema::{RootSchema, Schema},
+    schema_for, JsonSchema,
+};
+use serde::Serialize;
+use slug::slugify;
+
+use crate::{
+    cfg::Config,
+    src::{SourceData, SourceFile},
+};
+
+use super::{
+    child_index, frontmatter, node_text, strip_markers, EmptyOutputConfig, PageContext, Renderer,
+};
+
+/// Version of the JSON schema
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// File name of the JSON document (in the build dir)
+const JSON_FILE_NAME: &str = "doc.json";
+
+/// File name of the JSON Schema of the document (in the build dir)
+const JSON_SCHEMA_FILE_NAME: &str = "doc.schema.json";
+
+/// Renderer for JSON
+#[derive(Debug, Default)]
+pub struct JSONRenderer {}
+
+impl JSONRenderer {
+    /// Creates a new JSON renderer
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// JSON document (root of doc.json)
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct JSONDoc {
+    /// Schema version
+    pub schema_version: u32,
+    /// Generator (eg `docz 0.1.0`)
+    pub generator: String,
+    /// Document metadata (`[doc]` section of doc.toml)
+    pub doc: JSONDocMetadata,
+    /// Static assets (paths relative to the source dir)
+    pub assets: Vec<PathBuf>,
+    /// Pages, in outline order
+    pub pages: Vec<JSONPage>,
+}
+
+/// JSON document metadata
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct JSONDocMetadata {
+    /// Title
+    pub title: String,
+    /// Summary
+    pub summary: String,
+    /// Authors
+    pub authors: Vec<String>,
+}
+
+/// JSON page
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct JSONPage {
+    /// Page ID (slugified file stem)
+    pub id: String,
+    /// Index (eg 1.2.4)
+    pub index: String,
+    /// Title (from the frontmatter, or the ID)
+    pub title: String,
+    /// Source file (relative to the source dir)
+    pub source: PathBuf,
+    /// Path of the HTML page (relative to the HTML build dir)
+    pub path: PathBuf,
+    /// Frontmatter (`null` if none)
+    pub front_matter: serde_json::Value,
+    /// Headings
+    pub headings: Vec<JSONHeading>,
+    /// Links
+    pub links: Vec<JSONLink>,
+    /// Raw Markdown (including the frontmatter)
+    pub markdown: String,
+    /// Rendered HTML
+    pub html: String,
+    /// Subpages
+    pub pages: Vec<JSONPage>,
+}
+
+/// JSON heading
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct JSONHeading {
+    /// Level (1 to 6)
+    pub level: u8,
+    /// Text
+    pub text: String,
+}
+
+/// JSON link
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct JSONLink {
+    /// URL
+    pub url: String,
+    /// Title
+    pub title: String,
+    /// Text
+    pub text: String,
+    /// Image link
+    pub image: bool,
+}
+
+impl Renderer for JSONRenderer {
+    type Config = EmptyOutputConfig;
+
+    fn render(&self, cfg: &Config, _out_cfg: &Self::Config, src_data: &SourceData) -> Result<()> {
+        let doc = process_src_data(cfg, src_data)?;
+        let doc_str = serde_json::to_string_pretty(&doc)?;
+
+        let build_dir = cfg.build_dir();
+        fs::write(build_dir.join(JSON_FILE_NAME), doc_str)?;
+
+        let schema_str = serde_json::to_string_pretty(&json_schema())?;
+        fs::write(build_dir.join(JSON_SCHEMA_FILE_NAME), schema_str)?;
+
+        Ok(())
+    }
+}
+
+/// Returns the JSON Schema of the JSON document
+///
+/// NB: `schema_version` is a constant of the schema, so that a document is only valid
+/// against the schema of its version
+pub fn json_schema() -> RootSchema {
+    let mut root = schema_for!(JSONDoc);
+    let metadata = root.schema.metadata();
+    metadata.title = Some(JSON_FILE_NAME.to_string());
+    metadata.description = Some(format!(
+        "docz JSON document (schema version {JSON_SCHEMA_VERSION})"
+    ));
+    if let Some(Schema::Object(version)) = root.schema.object().properties.get_mut("schema_version")
+    {
+        version.const_value = Some(JSON_SCHEMA_VERSION.into());
+    }
+    root
+}
+
+/// Extracts the JSON document from the source data
+fn process_src_data(cfg: &Config, src_data: &SourceData) -> Result<JSONDoc> {
+    // NB: the generated pages (eg list of figures) are not part of the JSON document
+    let (ctx, _) = PageContext::new(cfg, src_data)?;
+
+    let mut assets = vec![];
+    for asset in &src_data.assets {
+        assets.push(asset.path.strip_prefix(&ctx.src_dir)?.to_owned());
+    }
+
+    let pages = process_src_files_iter(&src_data.files, &ctx, "")?;
+
+    Ok(JSONDoc {
+        schema_version: JSON_SCHEMA_VERSION,
+        generator: format!("docz {}", env!("CARGO_PKG_VERSION")),
+        doc: JSONDocMetadata {
+            title: cfg.file().doc.title.to_string(),
+            summary: cfg.file().doc.summary.to_string(),
+            authors: cfg.file().doc.authors.to_vec(),
+        },
+        assets,
+        pages,
+    })
+}
+
+/// Processes source files recursively
+fn process_src_files_iter(
+    src_files: &[SourceFile],
+    ctx: &PageContext,
+    parent_index: &str,
+) -> Result<Vec<JSONPage>> {
+    let mut pages = vec![];
+    for (i, src_file) in src_files.iter().enumerate() {
+        let index = child_index(parent_index, i);
+        let page = process_src_file_iter(src_file, ctx, &index)?;
+        pages.push(page);
+    }
+    Ok(pages)
+}
+
+/// Processes a source file recursively
+fn process_src_file_iter(
+    src_file: &SourceFile,
+    ctx: &PageContext,
+    index: &str,
+) -> Result<JSONPage> {
+    let id = {
+        let file_name = src_file
+            .path
+            .file_stem()
+            .ok_or(anyhow!("Invalid src file name"))?
+            .to_str()
+            .ok_or(anyhow!("Invalid src file name"))?;
+        slugify(file_name)
+    };
+
+    let source = src_file
+        .path
+        .strip_prefix(&ctx.src_dir)
+        .context("Source file path is not within the source dir")?
+        .to_owned();
+    let path = source.with_file_name(&id).with_extension("html");
+
+    // NB: the frontmatter, headings and links are extracted before the page is transformed
+    let mut front_matter = serde_json::Value::Null;
+    let mut headings = vec![];
+    let mut links = vec![];
+    let (html, metadata) = ctx.page_html(src_file, index, &path, |root| {
+        if let Some(fm) = frontmatter(root)? {
+            front_matter = serde_json::to_value(serde_yaml::from_str::<serde_yaml::Value>(&fm)?)?;
+        }
+        (headings, links) = extract_headings_and_links(root);
+        Ok(())
+    })?;
+    let title = metadata.title.unwrap_or(id.clone());
+    let markdown = String::from_utf8(src_file.content.to_vec())?;
+
+    let pages = process_src_files_iter(&src_file.children, ctx, index)?;
+
+    Ok(JSONPage {
+        id,
+        index: index.to_string(),
+        title,
+        source,
+        path,
+        front_matter,
+        headings,
+        links,
+        markdown,
+        html,
+        pages,
+    })
+}
+
+/// Extracts the headings and links of a parsed document
+///
+/// NB: the index markers are stripped from the texts
+fn extract_headings_and_links<'a>(root: &'a AstNode<'a>) -> (Vec<JSONHeading>, Vec<JSONLink>) {
+    let mut headings = vec![];
+    let mut links = vec![];
+    for node in root.descendants() {
+        match &node.data.borrow().value {
+            NodeValue::Heading(heading) => {
+                headings.push(JSONHeading {
+                    level: heading.level,
+                    text: strip_markers(&node_text(node)),
+                });
+            }
+            NodeValue::Link(link) | NodeValue::Image(link) => {
+                links.push(JSONLink {
+                    url: link.url.to_string(),
+                    title: link.title.to_string(),
+                    text: strip_markers(&node_text(node)),
+                    image: matches!(node.data.borrow().value, NodeValue::Image(_)),
+                });
+            }
+            _ => {}
+        }
+    }
+    (headings, links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_src_data() {
+        let cfg = Config::default();
+        let src_dir = cfg.src_dir();
+        let mut page = SourceFile::new(src_dir.join("01-intro.md"));
+        page.content = b"---\ntitle: Intro\n---\n# Intro {{index \"intro\"}}\n\n> [!NOTE]\n> See $x^2$ and [docs](https://docz.rs).\n".to_vec();
+        let mut child = SourceFile::new(src_dir.join("01-intro/01-details.md"));
+        child.content = b"# Details\n".to_vec();
+        page.children.push(child);
+        let src_data = SourceData {
+            files: vec![page],
+            assets: vec![],
+        };
+
+        let doc = process_src_data(&cfg, &src_data).unwrap();
+        let page = &doc.pages[0];
+        assert_eq!(page.title, "Intro");
+        assert_eq!(page.front_matter["title"], "Intro");
+        assert_eq!(page.headings[0].text.trim(), "Intro");
+        assert_eq!(page.links[0].url, "https://docz.rs");
+        assert!(page.html.contains("class=\"callout callout-note\""));
+        assert!(page.html.contains("<math"));
+        assert!(!page.html.contains("{{index"));
+        assert_eq!(page.pages[0].index, "1.1");
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let schema = serde_json::to_value(json_schema()).unwrap();
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            JSON_SCHEMA_VERSION
+        );
+        assert!(schema["definitions"]["JSONPage"]["properties"]["html"].is_object());
+    }
+}
@@ -0,0 +1,456 @@
+//! Math
+//!
+//! Inline (`$...$`) and display (`$$...$$`) math is rendered to MathML at build time,
+//! so no client-side script is needed. Display equations with a `\label{...}` are numbered
+//! across the doc (in the order of the pages), and referenced with `\ref{...}` or `\eqref{...}`.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use comrak::{nodes::AstNode, Arena, ComrakOptions};
+use latex2mathml::{latex_to_mathml, DisplayStyle};
+use log::warn;
+use slug::slugify;
+
+use crate::src::{frontmatter_str, FileMetadata, SourceFile};
+
+use super::{escape_xml, markdown_to_html_with};
+
+/// Placeholder delimiters of the math fragments (private use chars, kept as is by comrak)
+const PLACEHOLDER_START: char = '\u{E000}';
+const PLACEHOLDER_END: char = '\u{E001}';
+
+/// Index of the labelled equations of a doc
+#[derive(Debug, Default, Clone)]
+pub struct MathIndex {
+    /// Equations by label
+    equations: HashMap<String, Equation>,
+}
+
+/// MathML fragments of a page, replaced by placeholders (see [MathIndex::replace_math])
+#[derive(Debug, Default)]
+pub(crate) struct MathFragments(Vec<String>);
+
+impl MathFragments {
+    /// Restores the fragments in the rendered HTML (of a page, or a part of it)
+    pub fn restore(&self, html: &str) -> String {
+        let mut html = html.to_string();
+        for (i, fragment) in self.0.iter().enumerate() {
+            let placeholder = format!("{PLACEHOLDER_START}{i}{PLACEHOLDER_END}");
+            // NB: a display equation is a paragraph by itself
+            html = html
+                .replace(&format!("<p>{placeholder}</p>"), fragment)
+                .replace(&placeholder, fragment);
+        }
+        html
+    }
+}
+
+/// Labelled equation
+#[derive(Debug, Clone)]
+struct Equation {
+    /// Number
+    number: usize,
+    /// Output path of the page (eg `02-chapter_2/sub.html`)
+    page: PathBuf,
+}
+
+impl MathIndex {
+    /// Collects the labelled equations of the source files
+    ///
+    /// NB: `ext` is the extension of the output pages (eg `html`)
+    pub fn new(src_files: &[SourceFile], src_dir: &Path, ext: &str) -> Result<Self> {
+        let mut index = Self::default();
+        index.collect_iter(src_files, src_dir, ext)?;
+        Ok(index)
+    }
+
+    /// Collects the labelled equations recursively
+    fn collect_iter(&mut self, src_files: &[SourceFile], src_dir: &Path, ext: &str) -> Result<()> {
+        for src_file in src_files {
+            let page = page_path(&src_file.path, src_dir, ext)?;
+            let md = String::from_utf8(src_file.content.to_vec())?;
+            for segment in segments(&md) {
+                if let Segment::Display(latex) = segment {
+                    if let Some(label) = equation_label(latex) {
+                        let number = self.equations.len() + 1;
+                        let equation = Equation {
+                            number,
+                            page: page.clone(),
+                        };
+                        if self.equations.insert(label.to_string(), equation).is_some() {
+                            warn!("Duplicate equation label {label} ({})", page.display());
+                        }
+                    }
+                }
+            }
+            self.collect_iter(&src_file.children, src_dir, ext)?;
+        }
+        Ok(())
+    }
+
+    /// Converts a page to HTML, with its math rendered to MathML
+    ///
+    /// NB: `page` is the output path of the page (eg `02-chapter_2/sub.html`), to link the references,
+    /// and the parsed document is transformed before rendering (see [markdown_to_html_with]).
+    pub fn markdown_to_html(
+        &self,
+        md: &str,
+        opts: &ComrakOptions,
+        page: &Path,
+        transform: impl for<'a> FnOnce(&'a Arena<AstNode<'a>>, &'a AstNode<'a>) -> Result<()>,
+    ) -> Result<(String, FileMetadata)> {
+        let (md, fragments) = self.replace_math(md, page);
+        let (html, metadata) = markdown_to_html_with(&md, opts, transform)?;
+        Ok((fragments.restore(&html), metadata))
+    }
+
+    /// Replaces the math of a page with placeholders, and returns the page and the MathML
+    /// fragments to restore in the rendered HTML (see [MathFragments::restore])
+    ///
+    /// NB: math is replaced by placeholders, so that it is not parsed as Markdown
+    pub(crate) fn replace_math(&self, md: &str, page: &Path) -> (String, MathFragments) {
+        let mut fragments = vec![];
+        let mut md_out = String::new();
+        for segment in segments(md) {
+            let fragment = match segment {
+                Segment::Text(text) => {
+                    md_out.push_str(text);
+                    continue;
+                }
+                Segment::Inline(latex) => render_latex(latex, DisplayStyle::Inline, page),
+                Segment::Display(latex) => self.render_equation(latex, page),
+                Segment::Ref(label, parens) => self.render_ref(label, parens, page),
+            };
+            md_out.push(PLACEHOLDER_START);
+            md_out.push_str(&fragments.len().to_string());
+            md_out.push(PLACEHOLDER_END);
+            fragments.push(fragment);
+        }
+        (md_out, MathFragments(fragments))
+    }
+
+    /// Converts the math of a page to code, for the print outputs (eg text, DOCX)
+    ///
+    /// NB: the display equations are LaTeX code blocks (with their number), and the references
+    /// are the numbers of the equations.
+    pub fn markdown_to_code(&self, md: &str, page: &Path) -> String {
+        let mut out = String::new();
+        for segment in segments(md) {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::Inline(latex) => out.push_str(&code_span(latex.trim())),
+                Segment::Display(latex) => {
+                    let (mut code, label) = split_label(latex);
+                    let code_len = code.trim_end().len();
+                    code.truncate(code_len);
+                    if let Some(equation) = label.and_then(|label| self.equations.get(label)) {
+                        code.push_str(&format!("  ({})", equation.number));
+                    }
+                    // NB: a code block starts a line
+                    if !out.is_empty() && !out.ends_with('\n') {
+                        out.push('\n');
+                    }
+                    out.push_str(&format!("```latex\n{}\n```", code.trim_start_matches('\n')));
+                }
+                Segment::Ref(label, parens) => match self.equations.get(label) {
+                    Some(equation) if parens => out.push_str(&format!("({})", equation.number)),
+                    Some(equation) => out.push_str(&equation.number.to_string()),
+                    None => {
+                        warn!("Unknown equation label {label} ({})", page.display());
+                        out.push_str("??");
+                    }
+                },
+            }
+        }
+        out
+    }
+
+    /// Renders a display equation (numbered if it has a label)
+    fn render_equation(&self, latex: &str, page: &Path) -> String {
+        let (latex, label) = split_label(latex);
+        let mathml = render_latex(&latex, DisplayStyle::Block, page);
+        match label.and_then(|label| self.equations.get(label).map(|eq| (label, eq))) {
+            Some((label, equation)) => format!(
+                "<div class=\"math-display\" id=\"{}\">{mathml}<span class=\"math-number\">({})</span></div>",
+                escape_xml(label),
+                equation.number
+            ),
+            None => format!("<div class=\"math-display\">{mathml}</div>"),
+        }
+    }
+
+    /// Renders a reference to an equation (eg `\eqref{eq:energy}` -> `(1)`)
+    fn render_ref(&self, label: &str, parens: bool, page: &Path) -> String {
+        let equation = match self.equations.get(label) {
+            Some(equation) => equation,
+            None => {
+                warn!("Unknown equation label {label} ({})", page.display());
+                return "??".to_string();
+            }
+        };
+        let href = format!("{}#{}", relative_href(page, &equation.page), label);
+        let link = format!(
+            "<a class=\"math-ref\" href=\"{}\">{}</a>",
+            escape_xml(&href),
+            equation.number
+        );
+        if parens {
+            format!("({link})")
+        } else {
+            link
+        }
+    }
+}
+
+/// Renders LaTeX to MathML
+///
+/// NB: invalid math is reported, and rendered as code
+fn render_latex(latex: &str, style: DisplayStyle, page: &Path) -> String {
+    match latex_to_mathml(latex.trim(), style) {
+        Ok(mathml) => mathml,
+        Err(err) => {
+            warn!("Invalid math ({}): {err}", page.display());
+            format!("<code class=\"math-error\">{}</code>", escape_xml(latex))
+        }
+    }
+}
+
+/// Returns the output path of a page (relative to the output dir)
+pub(crate) fn page_path(path: &Path, src_dir: &Path, ext: &str) -> Result<PathBuf> {
+    let id = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or(anyhow!("Invalid src file name"))?;
+    Ok(path
+        .strip_prefix(src_dir)?
+        .with_file_name(slugify(id))
+        .with_extension(ext))
+}
+
+/// Returns the link from a page to another page
+pub(crate) fn relative_href(from: &Path, to: &Path) -> String {
+    if from == to {
+        return String::new();
+    }
+    let depth = from.components().count().saturating_sub(1);
+    format!("{}{}", "../".repeat(depth), to.to_string_lossy())
+}
+
+/// Returns the label of a display equation (`\label{...}`)
+fn equation_label(latex: &str) -> Option<&str> {
+    let start = latex.find("\\label{")? + "\\label{".len();
+    let end = start + latex[start..].find('}')?;
+    Some(&latex[start..end])
+}
+
+/// Returns the LaTeX of a display equation without its label, and the label
+fn split_label(latex: &str) -> (String, Option<&str>) {
+    let label = equation_label(latex);
+    let latex = match label {
+        Some(label) => latex.replace(&format!("\\label{{{label}}}"), ""),
+        None => latex.to_string(),
+    };
+    (latex, label)
+}
+
+/// Returns a Markdown code span (eg `` `a` ``)
+///
+/// NB: the delimiter is longer than the backtick runs of the code
+fn code_span(code: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in code.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    let fence = "`".repeat(longest + 1);
+    if code.starts_with('`') || code.ends_with('`') {
+        format!("{fence} {code} {fence}")
+    } else {
+        format!("{fence}{code}{fence}")
+    }
+}
+
+/// Segment of a Markdown page
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    /// Markdown
+    Text(&'a str),
+    /// Inline math (`$...$`)
+    Inline(&'a str),
+    /// Display math (`$$...$$`)
+    Display(&'a str),
+    /// Reference to an equation (`\ref{...}`, or `\eqref{...}` with parentheses)
+    Ref(&'a str, bool),
+}
+
+/// Splits a Markdown page into text and math segments
+///
+/// NB: the frontmatter, code blocks and code spans are skipped, and `\$` is a dollar sign.
+fn segments(md: &str) -> Vec<Segment<'_>> {
+    let bytes = md.as_bytes();
+    let mut segments = vec![];
+    let mut text_start = 0;
+    let mut i = match frontmatter_str(md) {
+        // NB: the frontmatter ends with a `---` line
+        Some(fm) => md.find('\n').map_or(0, |start| start + 1) + fm.len() + "\n---".len(),
+        None => 0,
+    };
+    let mut line_start = true;
+    let mut fence: Option<(u8, usize)> = None;
+
+    while i < bytes.len() {
+        // fenced code blocks
+        if line_start {
+            let line_end = md[i..].find('\n').map_or(md.len(), |end| i + end + 1);
+            let line = md[i..line_end].trim_start();
+            let c = line.bytes().next().unwrap_or(b' ');
+            let run = line.bytes().take_while(|b| *b == c).count();
+            match fence {
+                Some((fence_c, fence_run)) => {
+                    if c == fence_c && run >= fence_run && line[run..].trim().is_empty() {
+                        fence = None;
+                    }
+                    i = line_end;
+                    continue;
+                }
+                None if (c == b'`' || c == b'~') && run >= 3 => {
+                    fence = Some((c, run));
+                    i = line_end;
+                    continue;
+                }
+                None => {}
+            }
+            line_start = false;
+        }
+
+        let mut math = None;
+        match bytes[i] {
+            b'\n' => {
+                line_start = true;
+                i += 1;
+            }
+            b'\\' => {
+                let rest = &md[i..];
+                let reference = [("\\ref{", false), ("\\eqref{", true)]
+                    .into_iter()
+                    .find(|(prefix, _)| rest.starts_with(prefix));
+                match reference.and_then(|(prefix, parens)| {
+                    rest.find('}').map(|end| (prefix.len(), end, parens))
+                }) {
+                    Some((start, end, parens)) => {
+                        math = Some((Segment::Ref(rest[start..end].trim(), parens), i + end + 1));
+                    }
+                    None if rest.starts_with("\\\n") => i += 1,
+                    None => i += 2,
+                }
+            }
+            b'`' => {
+                let run = md[i..].bytes().take_while(|b| *b == b'`').count();
+                let fence = "`".repeat(run);
+                let mut end = i + run;
+                // NB: the closing run has the same length
+                loop {
+                    match md[end..].find(&fence) {
+                        Some(pos) => {
+                            let close = end + pos;
+                            let close_run = md[close..].bytes().take_while(|b| *b == b'`').count();
+                            if close_run == run {
+                                i = close + run;
+                                break;
+                            }
+                            end = close + close_run;
+                        }
+                        None => {
+                            i += run;
+                            break;
+                        }
+                    }
+                }
+            }
+            b'$' if md[i..].starts_with("$$") => match md[i + 2..].find("$$") {
+                Some(end) => {
+                    let latex = &md[i + 2..i + 2 + end];
+                    math = Some((Segment::Display(latex), i + 2 + end + 2));
+                }
+                None => i += 2,
+            },
+            b'$' => match inline_math_end(md, i) {
+                Some(end) => math = Some((Segment::Inline(&md[i + 1..end]), end + 1)),
+                None => i += 1,
+            },
+            _ => i += 1,
+        }
+
+        if let Some((segment, end)) = math {
+            if text_start < i {
+                segments.push(Segment::Text(&md[text_start..i]));
+            }
+            segments.push(segment);
+            i = end;
+            text_start = end;
+        }
+    }
+    if text_start < md.len() {
+        segments.push(Segment::Text(&md[text_start..]));
+    }
+    segments
+}
+
+/// Returns the position of the closing `$` of inline math
+///
+/// NB: the math starts and ends with a non-space char, is not followed by a digit (eg `$5 and $10`),
+/// and does not span paragraphs.
+fn inline_math_end(md: &str, start: usize) -> Option<usize> {
+    let bytes = md.as_bytes();
+    if bytes.get(start + 1).is_none_or(|b| b.is_ascii_whitespace()) {
+        return None;
+    }
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'\n'
+                if md[i + 1..]
+                    .trim_start_matches([' ', '\t'])
+                    .starts_with('\n') =>
+            {
+                return None
+            }
+            b'$' => {
+                let valid = !bytes[i - 1].is_ascii_whitespace()
+                    && !bytes.get(i + 1).is_some_and(|b| b.is_ascii_digit());
+                return if valid && i > start + 1 {
+                    Some(i)
+                } else {
+                    None
+                };
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_to_code() {
+        let mut file = SourceFile::new("src/01-intro.md");
+        file.content = b"$$\nE = mc^2 \\label{eq:energy}\n$$\n".to_vec();
+        let index = MathIndex::new(&[file], Path::new("src"), "md").unwrap();
+
+        let md = "Energy $E = mc^2$ in \\eqref{eq:energy} and `$a$`:\n\n$$\nE = mc^2 \\label{eq:energy}\n$$\n\nSee \\ref{eq:energy}, $x` + y$ and $5 or $10.\n";
+        assert_eq!(
+            index.markdown_to_code(md, Path::new("src/01-intro.md")),
+            "Energy `E = mc^2` in (1) and `$a$`:\n\n```latex\nE = mc^2  (1)\n```\n\nSee 1, ``x` + y`` and $5 or $10.\n"
+        );
+        assert_eq!(code_span("`a"), "`` `a ``");
+    }
+}
@@ -0,0 +1,341 @@
+//! Slides renderer
+
+mod template;
+
+use std::{fs, io::BufWriter, path::PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use comrak::{
+    nodes::{AstNode, NodeValue},
+    ComrakOptions,
+};
+use handlebars::Handlebars;
+use log::trace;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slug::slugify;
+
+use crate::{
+    cfg::Config,
+    src::{FileMetadata, SourceAsset, SourceData, SourceFile},
+};
+
+use self::template::{
+    DECK_SCRIPT_JS, DECK_STYLE_CSS, DECK_TABS_JS, DECK_TEMPLATE, DECK_TEMPLATE_ID,
+};
+
+use super::{
+    child_index, filter_raw_html, find_asset, frontmatter, MathFragments, PageContext, Renderer,
+};
+
+/// Layout (frontmatter) of the pages rendered as slides
+const SLIDES_LAYOUT: &str = "slides";
+
+/// Info string of the fenced blocks holding the speaker notes
+const NOTES_INFO: &str = "notes";
+
+/// Slides output config (from doc.toml)
+#[derive(Debug, Deserialize, JsonSchema, Default)]
+pub struct SlidesOutputConfig {
+    /// Source files rendered as slides (relative to the source dir)
+    ///
+    /// NB: pages with `layout: slides` in their frontmatter are always included
+    pub include: Option<Vec<PathBuf>>,
+}
+
+/// Renderer for slides
+///
+/// Each page is split into slides on thematic breaks (`---`) and level-2 headings,
+/// and rendered as a self-contained HTML deck: the styles, scripts and images (as data URIs)
+/// are inlined.
+///
+/// NB: the pages get the transforms of the HTML output (callouts, tabs, math, figures,
+/// citations, ...), and the links to other pages are the relative links of the HTML pages
+#[derive(Debug, Default)]
+pub struct SlidesRenderer {
+    registry: Handlebars<'static>,
+    /// Included source files
+    include: Vec<PathBuf>,
+}
+
+impl SlidesRenderer {
+    /// Creates a new slides renderer
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Deck data
+#[derive(Debug, Serialize)]
+struct SlidesDeckData<'a> {
+    /// Doc title
+    doc_title: &'a str,
+    /// Page title
+    title: String,
+    /// Inlined CSS
+    style: &'a str,
+    /// Inlined JS
+    script: String,
+    /// Slides
+    slides: Vec<SlideData>,
+}
+
+/// Slide data
+#[derive(Debug, Serialize)]
+struct SlideData {
+    /// HTML content
+    html: String,
+    /// Speaker notes (HTML)
+    notes: String,
+}
+
+impl Renderer for SlidesRenderer {
+    type Config = SlidesOutputConfig;
+
+    fn register(&mut self, cfg: &Config, slides_config: &Self::Config) -> Result<()> {
+        self.registry = Handlebars::new();
+        self.registry
+            .register_template_string(DECK_TEMPLATE_ID, DECK_TEMPLATE)?;
+
+        let src_dir = cfg.src_dir();
+        self.include = slides_config
+            .include
+            .iter()
+            .flatten()
+            .map(|path| src_dir.join(path))
+            .collect();
+
+        Ok(())
+    }
+
+    fn render(&self, cfg: &Config, _out_cfg: &Self::Config, src_data: &SourceData) -> Result<()> {
+        // NB: the generated pages (eg list of figures) are not rendered as slides
+        let (ctx, _) = PageContext::new(cfg, src_data)?;
+        let deck_ctx = DeckContext {
+            cfg,
+            page_ctx: &ctx,
+            assets: &src_data.assets,
+            build_dir: cfg.build_dir().join("slides"),
+        };
+        self.render_src_files_iter(&deck_ctx, &src_data.files, "")
+    }
+}
+
+/// Context of the rendering of the decks
+struct DeckContext<'a> {
+    /// Config
+    cfg: &'a Config,
+    /// Context of the HTML pages
+    page_ctx: &'a PageContext,
+    /// Source assets (inlined)
+    assets: &'a [SourceAsset],
+    /// Build dir
+    build_dir: PathBuf,
+}
+
+impl SlidesRenderer {
+    /// Renders the decks of the source files recursively
+    fn render_src_files_iter(
+        &self,
+        ctx: &DeckContext,
+        src_files: &[SourceFile],
+        parent_index: &str,
+    ) -> Result<()> {
+        for (i, src_file) in src_files.iter().enumerate() {
+            let index = child_index(parent_index, i);
+            self.render_src_file(ctx, src_file, &index)?;
+            self.render_src_files_iter(ctx, &src_file.children, &index)?;
+        }
+        Ok(())
+    }
+
+    /// Renders the deck of a source file (if it is included)
+    fn render_src_file(&self, ctx: &DeckContext, src_file: &SourceFile, index: &str) -> Result<()> {
+        let page_ctx = ctx.page_ctx;
+        let comrak_opts = &page_ctx.comrak_opts;
+        let content_str = String::from_utf8(src_file.content.to_vec())?;
+        let content_str = page_ctx.callouts.process(&content_str);
+
+        let id = {
+            let file_name = src_file
+                .path
+                .file_stem()
+                .ok_or(anyhow!("Invalid src file name"))?
+                .to_str()
+                .ok_or(anyhow!("Invalid src file name"))?;
+            slugify(file_name)
+        };
+        let path = src_file
+            .path
+            .strip_prefix(&page_ctx.src_dir)
+            .context("Source file path is not within the source dir")?
+            .with_file_name(&id)
+            .with_extension("html");
+
+        // NB: the math is restored in each slide
+        let (content_str, math) = page_ctx.math.replace_math(&content_str, &path);
+        let arena = comrak::Arena::new();
+        let root = comrak::parse_document(&arena, &content_str, comrak_opts);
+
+        let metadata = match frontmatter(root)? {
+            Some(fm) => serde_yaml::from_str::<FileMetadata>(&fm)?,
+            None => FileMetadata::default(),
+        };
+        let is_slides = metadata.layout.as_deref() == Some(SLIDES_LAYOUT)
+            || self.include.contains(&src_file.path);
+        if !is_slides {
+            return Ok(());
+        }
+        trace!("Rendering slides: {}", path.display());
+
+        filter_raw_html(root, page_ctx.raw_html, &src_file.path);
+        page_ctx.transform(&arena, root, index, &path)?;
+        inline_images(root, ctx.assets);
+        let notes_html = |md: &str| {
+            let (html, _) =
+                page_ctx
+                    .math
+                    .markdown_to_html(md, comrak_opts, &path, |_, _| Ok(()))?;
+            Ok(html)
+        };
+        let mut slides = split_slides(root, comrak_opts, &math, notes_html)?;
+
+        // NB: the cited references are the last slide
+        let bibliography = page_ctx.citations.bibliography_html(&path);
+        if !bibliography.is_empty() {
+            slides.push(SlideData {
+                html: bibliography,
+                notes: String::new(),
+            });
+        }
+
+        let deck = SlidesDeckData {
+            doc_title: &ctx.cfg.file().doc.title,
+            title: metadata.title.unwrap_or(id),
+            style: DECK_STYLE_CSS,
+            script: format!("{DECK_SCRIPT_JS}\n{DECK_TABS_JS}"),
+            slides,
+        };
+        let deck_str = self.registry.render(DECK_TEMPLATE_ID, &deck)?;
+
+        let deck_file = ctx.build_dir.join(path);
+        let parent_dir = deck_file.parent().unwrap();
+        fs::create_dir_all(parent_dir)?;
+        fs::write(deck_file, deck_str)?;
+
+        Ok(())
+    }
+}
+
+/// Replaces the URLs of the images referencing the source assets with data URIs
+fn inline_images<'a>(root: &'a AstNode<'a>, assets: &[SourceAsset]) {
+    for node in root.descendants() {
+        if let NodeValue::Image(link) = &mut node.data.borrow_mut().value {
+            if link.url.contains(':') {
+                continue;
+            }
+            if let Some(asset) = find_asset(assets, &link.url) {
+                let mime = mime_guess::from_path(&asset.path).first_or_octet_stream();
+                link.url = format!("data:{mime};base64,{}", BASE64.encode(&asset.content));
+            }
+        }
+    }
+}
+
+/// Splits a document into slides
+///
+/// NB: the math placeholders are restored in the HTML of the slides, and the speaker notes
+/// are converted with `notes_html`
+fn split_slides<'a>(
+    root: &'a AstNode<'a>,
+    opts: &ComrakOptions,
+    math: &MathFragments,
+    notes_html: impl Fn(&str) -> Result<String>,
+) -> Result<Vec<SlideData>> {
+    let mut slides = vec![];
+    let mut nodes = vec![];
+    let mut notes = String::new();
+    for node in root.children() {
+        match &node.data.borrow().value {
+            NodeValue::FrontMatter(_) => {}
+            NodeValue::ThematicBreak => {
+                push_slide(&mut slides, &mut nodes, &mut notes, opts, math)?;
+            }
+            NodeValue::Heading(heading) if heading.level == 2 => {
+                push_slide(&mut slides, &mut nodes, &mut notes, opts, math)?;
+                nodes.push(node);
+            }
+            NodeValue::CodeBlock(code_block)
+                if code_block.info.split_whitespace().next() == Some(NOTES_INFO) =>
+            {
+                notes.push_str(&notes_html(&code_block.literal)?);
+            }
+            _ => nodes.push(node),
+        }
+    }
+    push_slide(&mut slides, &mut nodes, &mut notes, opts, math)?;
+    Ok(slides)
+}
+
+/// Renders the pending nodes and notes as a new slide
+fn push_slide<'a>(
+    slides: &mut Vec<SlideData>,
+    nodes: &mut Vec<&'a AstNode<'a>>,
+    notes: &mut String,
+    opts: &ComrakOptions,
+    math: &MathFragments,
+) -> Result<()> {
+    if nodes.is_empty() && notes.is_empty() {
+        return Ok(());
+    }
+
+    let mut bw = BufWriter::new(Vec::new());
+    for node in nodes.drain(..) {
+        comrak::format_html_with_plugins(node, opts, &mut bw, &comrak::ComrakPlugins::default())?;
+    }
+    let html = String::from_utf8(bw.into_inner()?)?;
+
+    slides.push(SlideData {
+        html: math.restore(&html),
+        notes: std::mem::take(notes),
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use crate::rend::{comrak_options, MathIndex};
+
+    #[test]
+    fn test_split_slides() {
+        let assets = vec![SourceAsset {
+            path: PathBuf::from("src/_assets/logo.png"),
+            content: b"PNG".to_vec(),
+        }];
+        let md = "# Deck\n\n![Logo](_assets/logo.png)\n\n---\n\n$x^2$\n\n```notes\nSay $y$\n```\n";
+        let math_index = MathIndex::default();
+        let page = Path::new("deck.html");
+        let (md, math) = math_index.replace_math(md, page);
+        let opts = comrak_options();
+        let arena = comrak::Arena::new();
+        let root = comrak::parse_document(&arena, &md, &opts);
+        inline_images(root, &assets);
+        let notes_html = |md: &str| {
+            let (html, _) = math_index.markdown_to_html(md, &opts, page, |_, _| Ok(()))?;
+            Ok(html)
+        };
+        let slides = split_slides(root, &opts, &math, notes_html).unwrap();
+
+        assert_eq!(slides.len(), 2);
+        assert!(slides[0]
+            .html
+            .contains("src=\"data:image/png;base64,UE5H\""));
+        assert!(slides[1].html.contains("<math"));
+        assert!(slides[1].notes.contains("<math"));
+    }
+}
@@ -0,0 +1,16 @@
+//! Slides template
+
+/// Deck template ID
+pub const DECK_TEMPLATE_ID: &str = "_deck_";
+
+/// Deck template
+pub static DECK_TEMPLATE: &str = include_str!("template/deck.hbs");
+
+/// Deck style (inlined in the deck)
+pub static DECK_STYLE_CSS: &str = include_str!("template/style.css");
+
+/// Deck script (inlined in the deck)
+pub static DECK_SCRIPT_JS: &str = include_str!("template/deck.js");
+
+/// Script of the tab groups (inlined in the deck, shared with the HTML templates)
+pub static DECK_TABS_JS: &str = include_str!("../html/templates/_shared/tabs.js");
@@ -0,0 +1,62 @@
+(function () {
+    const slides = Array.from(document.querySelectorAll(".slide"));
+    const progress = document.getElementById("progress");
+    let current = 0;
+
+    function show(index) {
+        if (slides.length === 0) {
+            return;
+        }
+        current = Math.max(0, Math.min(index, slides.length - 1));
+        slides.forEach((slide, i) => slide.classList.toggle("active", i === current));
+        progress.style.width = ((current + 1) / slides.length) * 100 + "%";
+        history.replaceState(null, "", "#" + (current + 1));
+    }
+
+    function fromHash() {
+        const index = parseInt(window.location.hash.substring(1), 10);
+        return isNaN(index) ? 0 : index - 1;
+    }
+
+    document.addEventListener("keydown", (event) => {
+        // NB: the tabs handle their own keys
+        if (event.target.closest && event.target.closest('[role="tab"]')) {
+            return;
+        }
+        switch (event.key) {
+            case "ArrowRight":
+            case "ArrowDown":
+            case "PageDown":
+            case " ":
+                show(current + 1);
+                break;
+            case "ArrowLeft":
+            case "ArrowUp":
+            case "PageUp":
+                show(current - 1);
+                break;
+            case "Home":
+                show(0);
+                break;
+            case "End":
+                show(slides.length - 1);
+                break;
+            case "n":
+                document.body.classList.toggle("show-notes");
+                break;
+            default:
+                return;
+        }
+        event.preventDefault();
+    });
+
+    document.addEventListener("click", (event) => {
+        if (event.target.closest("a, button, summary, .notes")) {
+            return;
+        }
+        show(event.clientX < window.innerWidth / 3 ? current - 1 : current + 1);
+    });
+
+    window.addEventListener("hashchange", () => show(fromHash()));
+    show(fromHash());
+})();
@@ -0,0 +1,239 @@
+//! Tabs
+//!
+//! Tab groups are fenced containers, like the callouts: `:::code-group` makes a tab of each code
+//! block (labelled with its language, or a `[Label]` in the info string), and `:::tabs` a tab of
+//! each `@tab Label` section. The tabs with the same label are selected together (see `tabs.js`),
+//! and the EPUB and print outputs show the tabs one after the other.
+//!
+//! NB: the tabs are raw HTML around their Markdown content, like the callouts (marked as generated,
+//! see [RAW_HTML_MARKER]).
+
+use log::warn;
+
+use super::{
+    callout::{capitalize, code_fence, push_line},
+    escape_xml, CalloutMarkup, RAW_HTML_MARKER,
+};
+
+/// Container types of the tab groups
+pub(crate) const TAB_KINDS: &[&str] = &["tabs", "code-group"];
+
+/// Line starting a tab of a `:::tabs` container (eg `@tab Rust`)
+const TAB_MARKER: &str = "@tab ";
+
+/// Renders a tab group (the lines between the container markers)
+///
+/// NB: `process` converts the callouts of the content of a tab (with the id prefix of the tab)
+pub(crate) fn render_tabs(
+    kind: &str,
+    lines: &[&str],
+    id_prefix: &str,
+    markup: CalloutMarkup,
+    process: impl Fn(&str, &str) -> String,
+) -> String {
+    let tabs = match kind {
+        "code-group" => code_group_tabs(lines),
+        _ => section_tabs(lines),
+    };
+    if tabs.is_empty() {
+        warn!("Empty tab group ({kind})");
+        return String::new();
+    }
+    let id = format!("tabs{id_prefix}");
+    let kind = escape_xml(kind);
+
+    if markup == CalloutMarkup::Markdown {
+        let mut md = String::new();
+        for (n, (label, content)) in tabs.iter().enumerate() {
+            let content = process(content, &format!("{id_prefix}-{n}"));
+            md.push_str(&format!(
+                "**{label}**\n\n{}\n\n",
+                content.trim_matches('\n')
+            ));
+        }
+        return md;
+    }
+
+    let mut html = format!("<div class=\"tabs tabs-{kind}\">{RAW_HTML_MARKER}\n");
+    if markup == CalloutMarkup::XHTML {
+        for (n, (label, content)) in tabs.iter().enumerate() {
+            let content = process(content, &format!("{id_prefix}-{n}"));
+            html.push_str(&format!(
+                "<p class=\"tab-title\">{}</p>{RAW_HTML_MARKER}\n\n{}\n\n",
+                escape_xml(label),
+                content.trim_matches('\n')
+            ));
+        }
+        html.push_str(&format!("</div>{RAW_HTML_MARKER}\n\n"));
+        return html;
+    }
+
+    html.push_str("<div class=\"tabs-list\" role=\"tablist\">\n");
+    for (n, (label, _)) in tabs.iter().enumerate() {
+        html.push_str(&format!(
+            "<button type=\"button\" role=\"tab\" id=\"{id}-{n}\" aria-controls=\"{id}-{n}-panel\" aria-selected=\"{}\" tabindex=\"{}\" data-tab=\"{}\">{}</button>\n",
+            n == 0,
+            if n == 0 { 0 } else { -1 },
+            escape_xml(&label.to_lowercase()),
+            escape_xml(label)
+        ));
+    }
+    html.push_str("</div>\n");
+    for (n, (label, content)) in tabs.iter().enumerate() {
+        let content = process(content, &format!("{id_prefix}-{n}"));
+        html.push_str(&format!(
+            "<div class=\"tab-panel\" role=\"tabpanel\" id=\"{id}-{n}-panel\" aria-labelledby=\"{id}-{n}\" data-tab=\"{}\"{}>\n\n{}\n\n</div>{RAW_HTML_MARKER}\n",
+            escape_xml(&label.to_lowercase()),
+            if n == 0 { "" } else { " hidden" },
+            content.trim_matches('\n')
+        ));
+    }
+    html.push_str("</div>\n\n");
+    html
+}
+
+/// Splits the lines of a `:::code-group` container into tabs (label, content), one per code block
+///
+/// NB: the lines between the code blocks belong to the previous tab
+fn code_group_tabs(lines: &[&str]) -> Vec<(String, String)> {
+    let mut tabs: Vec<(String, String)> = vec![];
+    let mut fence: Option<(char, usize)> = None;
+    for line in lines {
+        let mut line = line.to_string();
+        if let Some((c, run, has_info)) = code_fence(&line) {
+            match fence {
+                Some((fence_c, fence_run)) if c == fence_c && run >= fence_run && !has_info => {
+                    fence = None;
+                }
+                Some(_) => {}
+                None => {
+                    fence = Some((c, run));
+                    let (label, fence_line) = code_block_label(&line);
+                    tabs.push((label, String::new()));
+                    line = fence_line;
+                }
+            }
+        }
+        match tabs.last_mut() {
+            Some((_, content)) => push_line(content, &line),
+            None if line.trim().is_empty() => {}
+            None => warn!(
+                "Text before the first code block of a code group ({})",
+                line.trim()
+            ),
+        }
+    }
+    tabs
+}
+
+/// Splits the lines of a `:::tabs` container into tabs (label, content), at the `@tab Label` lines
+fn section_tabs(lines: &[&str]) -> Vec<(String, String)> {
+    let mut tabs: Vec<(String, String)> = vec![];
+    let mut fence: Option<(char, usize)> = None;
+    for line in lines {
+        if let Some((c, run, has_info)) = code_fence(line) {
+            fence = match fence {
+                Some((fence_c, fence_run)) if c == fence_c && run >= fence_run && !has_info => None,
+                Some(fence) => Some(fence),
+                None => Some((c, run)),
+            };
+        } else if fence.is_none() {
+            if let Some(label) = line.trim().strip_prefix(TAB_MARKER) {
+                tabs.push((label.trim().to_string(), String::new()));
+                continue;
+            }
+        }
+        match tabs.last_mut() {
+            Some((_, content)) => push_line(content, line),
+            None if line.trim().is_empty() => {}
+            None => warn!(
+                "Text before the first @tab of a tab group ({})",
+                line.trim()
+            ),
+        }
+    }
+    tabs
+}
+
+/// Returns the label of a code block (`[Label]` in the info string, or the language),
+/// and its fence line without the label
+fn code_block_label(line: &str) -> (String, String) {
+    let info_start = line.len() - line.trim_start_matches([' ', '`', '~']).len();
+    let info = &line[info_start..];
+    if let Some((start, end)) = info.find('[').zip(info.find(']')).filter(|(s, e)| s < e) {
+        let label = info[start + 1..end].trim().to_string();
+        let fence_line = format!(
+            "{}{}{}",
+            &line[..info_start],
+            info[..start].trim_end(),
+            &info[end + 1..]
+        );
+        return (label, fence_line);
+    }
+    let label = match info.split_whitespace().next() {
+        Some(lang) => capitalize(lang),
+        None => "Code".to_string(),
+    };
+    (label, line.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders the tabs without nested callouts (and without the markers of the raw HTML)
+    fn render(kind: &str, md: &str, markup: CalloutMarkup) -> String {
+        let lines = md.lines().collect::<Vec<_>>();
+        render_tabs(kind, &lines, "-1", markup, |content, _| content.to_string())
+            .replace(RAW_HTML_MARKER, "")
+    }
+
+    #[test]
+    fn test_code_group() {
+        let md = "```rust [Cargo]\ncargo run\n```\n\n```sh\nmake\n```\n";
+        let html = render("code-group", md, CalloutMarkup::HTML);
+        assert!(html.starts_with(
+            "<div class=\"tabs tabs-code-group\">\n<div class=\"tabs-list\" role=\"tablist\">\n"
+        ));
+        assert!(html
+            .contains("aria-selected=\"true\" tabindex=\"0\" data-tab=\"cargo\">Cargo</button>"));
+        assert!(html.contains("data-tab=\"sh\" hidden>\n\n```sh\nmake\n```\n\n</div>"));
+        assert!(html.contains("\n\n```rust\ncargo run\n```\n"));
+
+        let md = render("code-group", md, CalloutMarkup::Markdown);
+        assert_eq!(
+            md,
+            "**Cargo**\n\n```rust\ncargo run\n```\n\n**Sh**\n\n```sh\nmake\n```\n\n"
+        );
+    }
+
+    #[test]
+    fn test_section_tabs() {
+        let md =
+            "@tab macOS\nbrew install\n\n```md\n@tab Not a tab\n```\n@tab Linux\napt install\n";
+        let xhtml = render("tabs", md, CalloutMarkup::XHTML);
+        assert_eq!(
+            xhtml,
+            "<div class=\"tabs tabs-tabs\">\n\
+            <p class=\"tab-title\">macOS</p>\n\nbrew install\n\n```md\n@tab Not a tab\n```\n\n\
+            <p class=\"tab-title\">Linux</p>\n\napt install\n\n</div>\n\n"
+        );
+        assert_eq!(render("tabs", "", CalloutMarkup::HTML), "");
+    }
+
+    #[test]
+    fn test_code_block_label() {
+        assert_eq!(
+            code_block_label("```js [Node] title"),
+            ("Node".to_string(), "```js title".to_string())
+        );
+        assert_eq!(
+            code_block_label("~~~python"),
+            ("Python".to_string(), "~~~python".to_string())
+        );
+        assert_eq!(
+            code_block_label("```"),
+            ("Code".to_string(), "```".to_string())
+        );
+    }
+}
@@ -0,0 +1,401 @@
+//! Text renderer
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use comrak::{
+    arena_tree::Node,
+    nodes::{Ast, AstNode, LineColumn, ListType, NodeValue},
+    ComrakOptions,
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use slug::slugify;
+
+use crate::{
+    cfg::Config,
+    src::{SourceData, SourceFile},
+};
+
+use super::{
+    comrak_options, node_text, strip_index_markers, CalloutMarkup, CalloutRenderer, MathIndex,
+    Renderer,
+};
+
+/// Text output config (from doc.toml)
+#[derive(Debug, Deserialize, JsonSchema, Default)]
+pub struct TextOutputConfig {
+    /// Hard wraps the text at N columns
+    pub wrap: Option<usize>,
+}
+
+/// Renderer for the whole doc as a single Markdown file (`book.md`) and plain text file (`book.txt`)
+///
+/// Pages are concatenated in outline order, their headings are shifted by their depth,
+/// and the links between pages point to in-document anchors.
+#[derive(Debug, Default)]
+pub struct TextRenderer {}
+
+impl TextRenderer {
+    /// Creates a new text renderer
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Renderer for TextRenderer {
+    type Config = TextOutputConfig;
+
+    fn render(
+        &self,
+        cfg: &Config,
+        text_config: &Self::Config,
+        src_data: &SourceData,
+    ) -> Result<()> {
+        let mut comrak_opts = comrak_options();
+        comrak_opts.render.width = text_config.wrap.unwrap_or(0);
+
+        // anchors of the pages
+        let mut anchors = HashMap::new();
+        collect_anchors_iter(&src_data.files, &cfg.src_dir(), &mut anchors)?;
+
+        let mut book = TextBook {
+            opts: comrak_opts,
+            wrap: text_config.wrap,
+            anchors,
+            callouts: CalloutRenderer::new(cfg, CalloutMarkup::Markdown),
+            math: MathIndex::new(&src_data.files, &cfg.src_dir(), "md")?,
+            md: String::new(),
+            txt: String::new(),
+        };
+        book.md.push_str(&format!("# {}\n\n", cfg.file().doc.title));
+        book.txt.push_str(&underline(&cfg.file().doc.title, '='));
+        book.process_src_files_iter(&src_data.files, 0)?;
+
+        let build_dir = cfg.build_dir();
+        fs::write(build_dir.join("book.md"), book.md)?;
+        fs::write(build_dir.join("book.txt"), book.txt)?;
+
+        Ok(())
+    }
+}
+
+/// Book being written
+struct TextBook {
+    /// Comrak options
+    opts: ComrakOptions,
+    /// Wrap width
+    wrap: Option<usize>,
+    /// Anchors of the source files
+    anchors: HashMap<PathBuf, String>,
+    /// Callouts and tabs (sequential)
+    callouts: CalloutRenderer,
+    /// Math (as code)
+    math: MathIndex,
+    /// Markdown output
+    md: String,
+    /// Text output
+    txt: String,
+}
+
+impl TextBook {
+    /// Processes source files recursively
+    ///
+    /// NB: the doc title is the only level-1 heading, so pages start at depth 1
+    fn process_src_files_iter(&mut self, src_files: &[SourceFile], depth: usize) -> Result<()> {
+        for src_file in src_files {
+            self.process_src_file(src_file, depth + 1)?;
+            self.process_src_files_iter(&src_file.children, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Processes a source file
+    fn process_src_file(&mut self, src_file: &SourceFile, depth: usize) -> Result<()> {
+        // NB: the callouts, tabs and math are converted to plain Markdown
+        let content_str = String::from_utf8(src_file.content.to_vec())?;
+        let content_str = self.callouts.process(&content_str);
+        let content_str = self.math.markdown_to_code(&content_str, &src_file.path);
+        let arena = comrak::Arena::new();
+        let root = comrak::parse_document(&arena, &content_str, &self.opts);
+        strip_index_markers(root);
+
+        // strip the frontmatter, shift and anchor headings, rewrite links
+        let anchor = self
+            .anchors
+            .get(&normalize_path(&src_file.path))
+            .cloned()
+            .unwrap_or_default();
+        let nodes = root.descendants().collect::<Vec<_>>();
+        for node in nodes {
+            if matches!(node.data.borrow().value, NodeValue::FrontMatter(_)) {
+                node.detach();
+                continue;
+            }
+            if matches!(node.data.borrow().value, NodeValue::Heading(_)) {
+                let id = heading_anchor(&anchor, &node_text(node));
+                let html = NodeValue::HtmlInline(format!("<a id=\"{id}\"></a>"));
+                let start = LineColumn { line: 0, column: 0 };
+                node.prepend(arena.alloc(Node::new(RefCell::new(Ast::new(html, start)))));
+            }
+            match node.data.borrow_mut().value {
+                NodeValue::Heading(ref mut heading) => {
+                    heading.level = (heading.level as usize + depth).min(6) as u8;
+                }
+                NodeValue::Link(ref mut link) => {
+                    if let Some(url) = self.rewrite_link(&src_file.path, &link.url) {
+                        link.url = url;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // markdown
+        let mut md = Vec::new();
+        comrak::format_commonmark(root, &self.opts, &mut md)?;
+        self.md.push_str(&format!("<a id=\"{anchor}\"></a>\n\n"));
+        self.md.push_str(&String::from_utf8(md)?);
+        self.md.push('\n');
+
+        // text
+        blocks_to_text(root, self.wrap, "", &mut self.txt);
+
+        Ok(())
+    }
+
+    /// Rewrites a link to a source file (or a heading) to an in-document anchor
+    fn rewrite_link(&self, src_path: &Path, url: &str) -> Option<String> {
+        if url.contains("://") || url.starts_with("mailto:") {
+            return None;
+        }
+        let (path, fragment) = match url.split_once('#') {
+            Some((path, fragment)) => (path, Some(fragment)),
+            None => (url, None),
+        };
+        let target = if path.is_empty() {
+            normalize_path(src_path)
+        } else {
+            normalize_path(&src_path.parent()?.join(path))
+        };
+        let anchor = self.anchors.get(&target)?;
+        match fragment {
+            Some(fragment) => Some(format!("#{}", heading_anchor(anchor, fragment))),
+            None => Some(format!("#{anchor}")),
+        }
+    }
+}
+
+/// Collects the anchors of the source files recursively
+///
+/// NB: the anchors are given by the paths (eg `02-chapter-2-01-intro`), as nested pages
+/// can have the same name
+fn collect_anchors_iter(
+    src_files: &[SourceFile],
+    src_dir: &Path,
+    anchors: &mut HashMap<PathBuf, String>,
+) -> Result<()> {
+    for src_file in src_files {
+        let rel_path = src_file
+            .path
+            .strip_prefix(src_dir)
+            .unwrap_or(&src_file.path)
+            .with_extension("");
+        let rel_path = rel_path.to_str().ok_or(anyhow!("Invalid src file name"))?;
+        anchors.insert(normalize_path(&src_file.path), slugify(rel_path));
+        collect_anchors_iter(&src_file.children, src_dir, anchors)?;
+    }
+    Ok(())
+}
+
+/// Returns the anchor of a heading of a page (eg `02-chapter-2--setup`)
+fn heading_anchor(page_anchor: &str, heading: &str) -> String {
+    format!("{page_anchor}--{}", slugify(heading))
+}
+
+/// Resolves the `.` and `..` components of a path (without touching the filesystem)
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Converts blocks to plain text
+fn blocks_to_text<'a>(node: &'a AstNode<'a>, wrap: Option<usize>, indent: &str, out: &mut String) {
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::FrontMatter(_) | NodeValue::HtmlBlock(_) => {}
+            NodeValue::Paragraph => {
+                out.push_str(&wrap_text(&inlines_to_text(child), wrap, indent));
+                out.push('\n');
+            }
+            NodeValue::Heading(heading) => {
+                let text = inlines_to_text(child);
+                out.push_str(&underline(
+                    &text,
+                    if heading.level <= 1 { '=' } else { '-' },
+                ));
+            }
+            NodeValue::CodeBlock(code_block) => {
+                for line in code_block.literal.lines() {
+                    out.push_str(&format!("{indent}    {line}\n"));
+                }
+                out.push('\n');
+            }
+            NodeValue::ThematicBreak => {
+                out.push_str(&format!("{indent}* * *\n\n"));
+            }
+            NodeValue::BlockQuote => {
+                blocks_to_text(child, wrap, &format!("{indent}> "), out);
+            }
+            NodeValue::List(list) => {
+                for (i, item) in child.children().enumerate() {
+                    let mut marker = match list.list_type {
+                        ListType::Bullet => "- ".to_string(),
+                        ListType::Ordered => format!("{}. ", list.start + i),
+                    };
+                    match item.data.borrow().value {
+                        NodeValue::TaskItem(Some(_)) => marker.push_str("[x] "),
+                        NodeValue::TaskItem(None) => marker.push_str("[ ] "),
+                        _ => {}
+                    }
+                    let item_indent = format!("{indent}{}", " ".repeat(marker.len()));
+                    let mut item_out = String::new();
+                    blocks_to_text(item, wrap, &item_indent, &mut item_out);
+                    let item_out = item_out
+                        .strip_prefix(&item_indent)
+                        .unwrap_or(&item_out)
+                        .trim_end();
+                    out.push_str(&format!("{indent}{marker}{item_out}\n"));
+                }
+                out.push('\n');
+            }
+            NodeValue::Table(..) => {
+                for row in child.children() {
+                    let cells = row.children().map(inlines_to_text).collect::<Vec<_>>();
+                    out.push_str(&format!("{indent}{}\n", cells.join(" | ")));
+                }
+                out.push('\n');
+            }
+            NodeValue::FootnoteDefinition(name) => {
+                let mut def_out = String::new();
+                blocks_to_text(child, wrap, indent, &mut def_out);
+                out.push_str(&format!("{indent}[^{name}]: {}\n\n", def_out.trim()));
+            }
+            _ => blocks_to_text(child, wrap, indent, out),
+        }
+    }
+}
+
+/// Converts inlines to plain text
+fn inlines_to_text<'a>(node: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::Text(t) => text.push_str(t),
+            NodeValue::Code(code) => text.push_str(&format!("`{}`", code.literal)),
+            NodeValue::SoftBreak => text.push(' '),
+            NodeValue::LineBreak => text.push('\n'),
+            NodeValue::HtmlInline(_) => {}
+            NodeValue::Link(link) => {
+                text.push_str(&inlines_to_text(child));
+                if !link.url.starts_with('#') {
+                    text.push_str(&format!(" ({})", link.url));
+                }
+            }
+            NodeValue::Image(link) => {
+                text.push_str(&format!("[image: {} ({})]", node_text(child), link.url));
+            }
+            NodeValue::FootnoteReference(name) => text.push_str(&format!("[^{name}]")),
+            _ => text.push_str(&inlines_to_text(child)),
+        }
+    }
+    text
+}
+
+/// Returns a heading underlined with a character
+fn underline(text: &str, c: char) -> String {
+    format!("{text}\n{}\n\n", c.to_string().repeat(text.chars().count()))
+}
+
+/// Wraps text at a given width (including the indent)
+fn wrap_text(text: &str, wrap: Option<usize>, indent: &str) -> String {
+    let mut out = String::new();
+    for line in text.lines() {
+        let width = match wrap {
+            Some(width) => width.saturating_sub(indent.chars().count()).max(1),
+            None => {
+                out.push_str(&format!("{indent}{line}\n"));
+                continue;
+            }
+        };
+        let mut current = String::new();
+        for word in line.split_whitespace() {
+            if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+                out.push_str(&format!("{indent}{current}\n"));
+                current.clear();
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+        out.push_str(&format!("{indent}{current}\n"));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchors_and_links() {
+        let mut page = SourceFile::new("src/01-intro.md");
+        page.content =
+            b"# Intro\n\nSee [setup](02-guide/01-intro.md#Setup) and [below](#intro).\n".to_vec();
+        let mut guide = SourceFile::new("src/02-guide.md");
+        let mut nested = SourceFile::new("src/02-guide/01-intro.md");
+        nested.content = b"## Setup\n".to_vec();
+        guide.children.push(nested);
+        let files = vec![page, guide];
+
+        let mut anchors = HashMap::new();
+        collect_anchors_iter(&files, Path::new("src"), &mut anchors).unwrap();
+        // NB: the nested page has the same name as the first page
+        assert_eq!(anchors[Path::new("src/01-intro.md")], "01-intro");
+        assert_eq!(
+            anchors[Path::new("src/02-guide/01-intro.md")],
+            "02-guide-01-intro"
+        );
+
+        let mut book = TextBook {
+            opts: comrak_options(),
+            wrap: None,
+            anchors,
+            callouts: CalloutRenderer::new(&Config::default(), CalloutMarkup::Markdown),
+            math: MathIndex::default(),
+            md: String::new(),
+            txt: String::new(),
+        };
+        book.process_src_files_iter(&files, 0).unwrap();
+        assert!(book.md.contains("[setup](#02-guide-01-intro--setup)"));
+        assert!(book.md.contains("[below](#01-intro--intro)"));
+        assert!(book
+            .md
+            .contains("### <a id=\"02-guide-01-intro--setup\"></a>Setup"));
+    }
+}
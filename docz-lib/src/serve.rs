@@ -8,6 +8,7 @@ use salvo::{prelude::*, sse};
 use tokio::sync::broadcast;
 
 use crate::{
+    src::SourceFilter,
     watch::{EventExt, WatchOptions, Watcher},
     Service,
 };
@@ -82,7 +83,7 @@ impl Service {
 
         // watch task
         let watched_dirs = self.watched_dirs();
-        let mut watcher = Watcher::new(watched_dirs, Some(200))?;
+        let mut watcher = Watcher::new(watched_dirs, SourceFilter::new(&self.config)?, Some(200))?;
         let (rx_watch, on_rebuilt) = if let Some(watch_opts) = watch_opts {
             (Some(watcher.start()?), watch_opts.on_rebuilt)
         } else {
@@ -1,15 +1,28 @@
 //! Source
 
+#[cfg(feature = "archive")]
+mod archive;
+mod filter;
+mod format;
+mod provider;
+
+#[cfg(feature = "archive")]
+pub use archive::*;
+pub use filter::*;
+pub use format::*;
+pub use provider::*;
+
 use std::{
-    fs,
+    collections::{BTreeMap, BTreeSet},
     path::{Path, PathBuf},
 };
 
-use anyhow::Result;
-use log::trace;
+use anyhow::{anyhow, Context, Result};
+use log::{trace, warn};
 use serde::Deserialize;
+use slug::slugify;
 
-use crate::Service;
+use crate::{cfg::Config, rend::RAW_HTML_MARKER, vars::substitute_page_vars, Service};
 
 /// A representation of the source directory
 #[derive(Debug, Clone, Default)]
@@ -17,7 +30,7 @@ pub struct SourceData {
     /// Files
     pub files: Vec<SourceFile>,
     /// Static assets
-    pub assets: Vec<PathBuf>,
+    pub assets: Vec<SourceAsset>,
 }
 
 impl SourceData {
@@ -28,6 +41,24 @@ impl SourceData {
     }
 }
 
+/// Static asset
+#[derive(Clone, Default)]
+pub struct SourceAsset {
+    /// Path
+    pub path: PathBuf,
+    /// Content
+    pub content: Vec<u8>,
+}
+
+impl std::fmt::Debug for SourceAsset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Asset")
+            .field("file", &self.path)
+            .field("size", &self.content.len())
+            .finish()
+    }
+}
+
 /// Source file
 #[derive(Clone, Default)]
 pub struct SourceFile {
@@ -69,95 +100,291 @@ impl SourceFile {
     }
 }
 
-impl Service {
-    /// Loads the source directory
-    pub(crate) fn load_src_dir(&self) -> Result<SourceData> {
-        let src_dir = self.config.src_dir();
-        let assets_dir = self.config.src_assets_dir();
-        let files = self.load_src_dir_iter(&src_dir, &[&assets_dir])?;
-        let assets = self.load_assets_dir_iter(&assets_dir)?;
-        let src_tree = SourceData { files, assets };
-        Ok(src_tree)
-    }
-
-    /// Loads a source directory recursively
-    #[allow(clippy::only_used_in_recursion)]
-    fn load_src_dir_iter(&self, dir: &Path, exc_dirs: &[&Path]) -> Result<Vec<SourceFile>> {
-        let mut files = vec![];
-        let mut dirs = vec![];
-        'loop_entry: for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let entry_type = entry.file_type()?;
-
-            for exc_dir in exc_dirs {
-                if entry.path().starts_with(exc_dir) {
-                    continue 'loop_entry;
-                }
-            }
+impl SourceData {
+    /// Builds the source data from the files of the source dir (paths relative to the source dir)
+    ///
+    /// NB: a dir with the same name as a file (without extension) holds the children of that file,
+    /// and the files inside the assets dir are static assets. The ignored files (`[src] exclude`
+    /// and `include`) are left out, and the unsupported files are reported and skipped.
+    pub fn from_files(cfg: &Config, files: BTreeMap<PathBuf, Vec<u8>>) -> Result<Self> {
+        let src_dir = cfg.src_dir();
+        let assets_dir = cfg.src_assets_dir();
+        let assets_rel_dir = assets_dir.strip_prefix(&src_dir)?;
+        let filter = SourceFilter::new(cfg)?;
 
-            if entry_type.is_dir() {
-                dirs.push(entry.path());
+        let mut pages = BTreeMap::new();
+        let mut assets = vec![];
+        for (path, content) in files {
+            if filter.is_ignored(&path, false) {
+                trace!("ignored source file: {}", path.display());
+                continue;
             }
-
-            if entry_type.is_file() {
-                files.push(entry.path());
+            if path.starts_with(assets_rel_dir) {
+                assets.push(SourceAsset {
+                    path: src_dir.join(path),
+                    content,
+                });
+            } else if cfg.is_page(&path) {
+                pages.insert(path, content);
+            } else {
+                warn!("Unsupported source file {} (skipped)", path.display());
             }
         }
 
-        let mut src_files = vec![];
-        for file in &files {
-            let path = file.to_owned();
-            let content = fs::read(file.as_path())?;
-            let mut children = vec![];
-
-            // children
-            if let Some(index) = dirs.iter().position(|dir| {
-                // NB: the dir is related to the file if it has the same name (without extension)
-                let mut file_no_ext = file.clone();
-                file_no_ext.set_extension("");
-                dir.file_name() == file_no_ext.file_name()
-            }) {
-                let dir = dirs.remove(index);
-                children = self.load_src_dir_iter(&dir, &[])?;
-            }
+        let files = src_files_iter(&src_dir, Path::new(""), &mut pages, cfg.file().src.strict)?;
+        Ok(SourceData { files, assets })
+    }
+}
+
+/// Names of the index pages of a dir (eg `guides/index.md`)
+const INDEX_PAGE_STEMS: &[&str] = &["index", "README", "readme"];
 
-            src_files.push(SourceFile {
-                path,
+/// Builds the source files of a dir recursively (and removes them from the pending files)
+///
+/// NB: a sub-dir without a page of the same name (orphan) gets its index page as section page
+/// (`index.md` or `README.md`), or an auto-generated listing page. In strict mode, it is an error.
+fn src_files_iter(
+    src_dir: &Path,
+    dir: &Path,
+    pending: &mut BTreeMap<PathBuf, Vec<u8>>,
+    strict: bool,
+) -> Result<Vec<SourceFile>> {
+    let paths = pending
+        .keys()
+        .filter(|path| path.parent() == Some(dir))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    // NB: sorted by the path without extension, so that sections stay in place
+    let mut src_files = vec![];
+    for path in paths {
+        let content = pending.remove(&path).unwrap_or_default();
+        // NB: the dir is related to the file if it has the same name (without extension)
+        let children_dir = path.with_extension("");
+        let children = if pending.keys().any(|p| p.starts_with(&children_dir)) {
+            src_files_iter(src_dir, &children_dir, pending, strict)?
+        } else {
+            vec![]
+        };
+        src_files.push((
+            children_dir,
+            SourceFile {
+                path: src_dir.join(path),
                 content,
                 children,
-            });
-        }
+            },
+        ));
+    }
 
-        // check that no sub-dirs is orphan
-        for dir in dirs {
-            trace!("orphan source dir: {:#?}", dir);
-        }
+    // orphan dirs
+    let orphan_dirs = pending
+        .keys()
+        .filter_map(|path| {
+            let rel_path = path.strip_prefix(dir).ok()?;
+            let name = rel_path.components().next()?;
+            Some(dir.join(name))
+        })
+        .collect::<BTreeSet<_>>();
+    for orphan_dir in orphan_dirs {
+        let section = orphan_section(src_dir, &orphan_dir, pending, strict)?;
+        src_files.push((orphan_dir, section));
+    }
 
-        Ok(src_files)
+    src_files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(src_files.into_iter().map(|(_, file)| file).collect())
+}
+
+/// Returns the section page of an orphan dir (with its files as children)
+fn orphan_section(
+    src_dir: &Path,
+    dir: &Path,
+    pending: &mut BTreeMap<PathBuf, Vec<u8>>,
+    strict: bool,
+) -> Result<SourceFile> {
+    // index page
+    let index_path = pending
+        .keys()
+        .find(|path| {
+            path.parent() == Some(dir)
+                && path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_some_and(|stem| INDEX_PAGE_STEMS.contains(&stem))
+        })
+        .cloned();
+    if let Some(index_path) = index_path {
+        trace!("orphan source dir with index page: {}", dir.display());
+        let content = pending.remove(&index_path).unwrap_or_default();
+        let children = src_files_iter(src_dir, dir, pending, strict)?;
+        return Ok(SourceFile {
+            path: src_dir.join(index_path),
+            content,
+            children,
+        });
     }
 
-    /// Loads the static assets recursively
-    #[allow(clippy::only_used_in_recursion)]
-    fn load_assets_dir_iter(&self, dir: &Path) -> Result<Vec<PathBuf>> {
-        let mut assets = vec![];
-        if dir.is_dir() {
-            for entry in fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_dir() {
-                    let mut new_assets = self.load_assets_dir_iter(&path)?;
-                    assets.append(&mut new_assets);
-                } else {
-                    assets.push(path);
-                }
-            }
+    if strict {
+        return Err(anyhow!(
+            "Orphan source dir {} (add {}.md or an index page)",
+            dir.display(),
+            dir.display()
+        ));
+    }
+
+    // listing page
+    warn!(
+        "Orphan source dir {}, generating a listing page",
+        dir.display()
+    );
+    let children = src_files_iter(src_dir, dir, pending, strict)?;
+    let title = dir_title(dir);
+    let mut content = format!(
+        "---\ntitle: {}\n---\n\n# {title}\n\n",
+        serde_json::to_string(&title)?
+    );
+    // NB: the links target the output pages (eg `guide/01-intro.md` -> `guide/01-intro.html`)
+    for child in &children {
+        let child_path = child.path.strip_prefix(src_dir)?;
+        let link = child_path.strip_prefix(dir.parent().unwrap_or(Path::new("")))?;
+        let stem = link.file_stem().unwrap_or_default().to_string_lossy();
+        let link = link.with_file_name(slugify(stem)).with_extension("html");
+        content.push_str(&format!(
+            "- [{}](<{}>)\n",
+            page_title(child),
+            link.to_string_lossy().replace('\\', "/")
+        ));
+    }
+    // NB: the page is a sibling of the dir (eg `guide/` -> `guide.md`)
+    let mut page_path = src_dir.join(dir).into_os_string();
+    page_path.push(".md");
+    Ok(SourceFile {
+        path: page_path.into(),
+        content: content.into_bytes(),
+        children,
+    })
+}
+
+/// Returns the title of a dir (eg `02-user_guide` -> `User guide`)
+fn dir_title(dir: &Path) -> String {
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = name.trim_start_matches(|c: char| c.is_ascii_digit() || c == '-' || c == '_');
+    let name = if name.is_empty() { "Untitled" } else { name };
+    let name = name.replace(['-', '_'], " ");
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+/// Returns the title of a page (frontmatter title or file name)
+fn page_title(file: &SourceFile) -> String {
+    let title = std::str::from_utf8(&file.content)
+        .ok()
+        .and_then(frontmatter_str)
+        .and_then(|fm| serde_yaml::from_str::<FileMetadata>(fm).ok())
+        .and_then(|metadata| metadata.title);
+    match title {
+        Some(title) => title,
+        None => file
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// Returns the raw frontmatter of a Markdown source, if any
+pub(crate) fn frontmatter_str(md: &str) -> Option<&str> {
+    md.strip_prefix("---\n")
+        .or_else(|| md.strip_prefix("---\r\n"))
+        .and_then(|fm| fm.find("\n---").map(|end| &fm[..end]))
+}
+
+impl Service {
+    /// Loads the source directory
+    pub(crate) fn load_src_dir(&self) -> Result<SourceData> {
+        self.load_src_dir_from(&self.config)
+    }
+
+    /// Loads the source directory of a specific config (eg a locale)
+    pub(crate) fn load_src_dir_from(&self, config: &Config) -> Result<SourceData> {
+        load_src_data(self.source_provider.as_ref(), &self.source_formats, config)
+    }
+}
+
+/// Loads the source data with a provider, converts the pages to Markdown
+/// and substitutes the variables in the pages
+pub(crate) fn load_src_data(
+    provider: &dyn SourceProvider,
+    formats: &[Box<dyn SourceFormat>],
+    config: &Config,
+) -> Result<SourceData> {
+    let mut src_data = provider.load(config)?;
+    convert_pages_iter(&mut src_data.files, formats, &config.variables())?;
+    Ok(src_data)
+}
+
+/// Converts the pages to Markdown and substitutes the variables recursively
+///
+/// NB: the pages keep their original path (eg `notebook.ipynb`)
+fn convert_pages_iter(
+    files: &mut [SourceFile],
+    formats: &[Box<dyn SourceFormat>],
+    vars: &BTreeMap<String, String>,
+) -> Result<()> {
+    for file in files {
+        // NB: generated listing pages are Markdown
+        if let Some(format) = find_format(formats, &file.path) {
+            let md = format
+                .to_markdown(&file.content)
+                .and_then(|md| substitute_page_vars(&md, vars))
+                .with_context(|| format!("Invalid page {}", file.path.display()))?;
+            // NB: only the generated raw HTML is marked (eg callouts)
+            file.content = md.replace(RAW_HTML_MARKER, "").into_bytes();
         }
-        Ok(assets)
+        convert_pages_iter(&mut file.children, formats, vars)?;
     }
+    Ok(())
 }
 
 /// File metadata
 #[derive(Debug, Deserialize, Default)]
 pub struct FileMetadata {
     pub title: Option<String>,
+    /// Layout (eg `slides`)
+    pub layout: Option<String>,
+    /// Variables (override the doc variables)
+    #[serde(default)]
+    pub variables: BTreeMap<String, serde_yaml::Value>,
+    /// Keywords, added to the index (eg `memory safety!borrow checker` for a sub-entry)
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orphan_listing_page() {
+        let mut cfg = Config::default();
+        cfg.set_root_dir(Path::new("/doc"));
+        let files = BTreeMap::from([
+            (PathBuf::from("01-intro.md"), b"# Intro".to_vec()),
+            (PathBuf::from("02-user_guide/01-First Step.md"), vec![]),
+        ]);
+        let data = SourceData::from_files(&cfg, files).unwrap();
+
+        assert_eq!(data.files.len(), 2);
+        let section = &data.files[1];
+        assert_eq!(section.path, Path::new("/doc/src/02-user_guide.md"));
+        assert_eq!(section.children.len(), 1);
+        let content = String::from_utf8(section.content.clone()).unwrap();
+        assert!(content.starts_with("---\ntitle: \"User guide\"\n---\n"));
+        assert!(content.contains("- [01-First Step](<02-user_guide/01-first-step.html>)\n"));
+    }
 }
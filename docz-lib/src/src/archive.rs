@@ -0,0 +1,219 @@
+//! Archive source provider
+
+use std::{
+    fs,
+    io::{Cursor, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use flate2::read::GzDecoder;
+
+use crate::cfg::Config;
+
+use super::{src_files, SourceData, SourceProvider};
+
+/// Provider reading the sources from a tar (`.tar`, `.tar.gz`, `.tgz`) or zip archive
+///
+/// The archive holds the root dir (eg `src/01-intro.md`), possibly under a prefix
+/// (eg `docs-1.0/` for a release tarball).
+#[derive(Debug, Clone)]
+pub struct ArchiveSourceProvider {
+    /// Archive file (relative to the root dir)
+    path: PathBuf,
+    /// Dir of the root dir inside the archive
+    prefix: PathBuf,
+}
+
+impl ArchiveSourceProvider {
+    /// Creates a new archive provider
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            prefix: PathBuf::new(),
+        }
+    }
+
+    /// Sets the dir of the root dir inside the archive
+    pub fn prefix(mut self, prefix: impl Into<PathBuf>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+}
+
+impl SourceProvider for ArchiveSourceProvider {
+    fn load(&self, cfg: &Config) -> Result<SourceData> {
+        let path = cfg.root_dir().join(&self.path);
+        let data =
+            fs::read(&path).with_context(|| format!("Archive not found ({})", path.display()))?;
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        let entries = if file_name.ends_with(".zip") {
+            read_zip(data)
+        } else if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+            read_tar(GzDecoder::new(Cursor::new(data)))
+        } else if file_name.ends_with(".tar") {
+            read_tar(Cursor::new(data))
+        } else {
+            Err(anyhow!("Unsupported archive format"))
+        }
+        .with_context(|| format!("Invalid archive {}", path.display()))?;
+
+        let entries = entries.into_iter().filter_map(|(path, content)| {
+            let path = normalize_entry_path(&path)?;
+            Some((path.strip_prefix(&self.prefix).ok()?.to_owned(), content))
+        });
+        SourceData::from_files(cfg, src_files(cfg, entries)?)
+    }
+}
+
+/// Reads the files of a tar archive
+fn read_tar(reader: impl Read) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut files = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let mut content = vec![];
+        entry.read_to_end(&mut content)?;
+        files.push((path, content));
+    }
+    Ok(files)
+}
+
+/// Reads the files of a zip archive
+fn read_zip(data: Vec<u8>) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))?;
+    let mut files = vec![];
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if !file.is_file() {
+            continue;
+        }
+        let path = match file.enclosed_name() {
+            Some(path) => path.to_owned(),
+            None => continue,
+        };
+        let mut content = vec![];
+        file.read_to_end(&mut content)?;
+        files.push((path, content));
+    }
+    Ok(files)
+}
+
+/// Normalizes the path of an archive entry (eg `./src/a.md`), and rejects the paths escaping the archive
+fn normalize_entry_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(c) => normalized.push(c),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io::Write};
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    /// Creates a root dir with an archive
+    fn root_dir_with_archive(name: &str, file_name: &str, data: &[u8]) -> PathBuf {
+        let root_dir = env::temp_dir().join(format!("docz-archive-{name}-{}", std::process::id()));
+        fs::create_dir_all(&root_dir).unwrap();
+        fs::write(root_dir.join(file_name), data).unwrap();
+        root_dir
+    }
+
+    /// Creates a gzipped tar archive
+    ///
+    /// NB: the entry names are written as is, so that they can escape the archive (eg `../a.md`)
+    fn tar_gz(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+        for (name, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, *content).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    /// Creates a zip archive
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+        for (name, content) in entries {
+            writer
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_normalize_entry_path() {
+        assert_eq!(
+            normalize_entry_path(Path::new("./src/a.md")),
+            Some(PathBuf::from("src/a.md"))
+        );
+        assert_eq!(normalize_entry_path(Path::new("src/../../a.md")), None);
+        assert_eq!(normalize_entry_path(Path::new("/etc/passwd")), None);
+    }
+
+    #[test]
+    fn test_tar_gz_provider() {
+        let data = tar_gz(&[
+            ("docs-1.0/src/01-intro.md", b"# Intro"),
+            ("docs-1.0/src/_assets/logo.png", &[0, 1]),
+            ("docs-1.0/../src/02-escaped.md", b"# Escaped"),
+            ("other/src/03-other.md", b"# Other"),
+        ]);
+        let root_dir = root_dir_with_archive("tar", "docs.tar.gz", &data);
+        let mut cfg = Config::default();
+        cfg.set_root_dir(&root_dir);
+        let data = ArchiveSourceProvider::new("docs.tar.gz")
+            .prefix("docs-1.0")
+            .load(&cfg)
+            .unwrap();
+
+        // NB: the entries outside the prefix or escaping the archive are left out
+        assert_eq!(data.files.len(), 1);
+        assert_eq!(data.files[0].path, root_dir.join("src/01-intro.md"));
+        assert_eq!(data.files[0].content, b"# Intro");
+        assert_eq!(data.assets.len(), 1);
+        assert_eq!(data.assets[0].path, root_dir.join("src/_assets/logo.png"));
+        fs::remove_dir_all(root_dir).unwrap();
+    }
+
+    #[test]
+    fn test_zip_provider() {
+        let data = zip(&[
+            ("src/01-intro.md", b"# Intro"),
+            ("../src/02-escaped.md", b""),
+        ]);
+        let root_dir = root_dir_with_archive("zip", "docs.zip", &data);
+        let mut cfg = Config::default();
+        cfg.set_root_dir(&root_dir);
+        let data = ArchiveSourceProvider::new("docs.zip").load(&cfg).unwrap();
+        assert_eq!(data.files.len(), 1);
+        assert_eq!(data.files[0].path, root_dir.join("src/01-intro.md"));
+
+        assert!(ArchiveSourceProvider::new("docs.rar").load(&cfg).is_err());
+        fs::remove_dir_all(root_dir).unwrap();
+    }
+}
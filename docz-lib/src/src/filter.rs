@@ -0,0 +1,112 @@
+//! Source filter
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::cfg::Config;
+
+/// Patterns always excluded (hidden files like `.DS_Store`, editor swap and backup files)
+const DEFAULT_EXCLUDE: &[&str] = &[".*", "*~", "*.swp", "*.swo", "*.tmp", "#*#"];
+
+/// Filter of the source tree, from the `.gitignore`-style `[src] exclude` and `include` patterns
+///
+/// NB: patterns are relative to the source dir. Paths outside the source dir (eg extra watched dirs)
+/// are matched by file name, against the exclude patterns only.
+#[derive(Debug, Clone)]
+pub struct SourceFilter {
+    /// Source dir
+    src_dir: PathBuf,
+    /// Excluded paths
+    exclude: Gitignore,
+    /// Included paths (all if empty)
+    include: Gitignore,
+}
+
+impl SourceFilter {
+    /// Creates the filter of a config
+    pub fn new(cfg: &Config) -> Result<Self> {
+        let src_dir = cfg.src_dir();
+        let src_cfg = &cfg.file().src;
+
+        let exclude = DEFAULT_EXCLUDE
+            .iter()
+            .copied()
+            .chain(src_cfg.exclude.iter().map(|p| p.as_str()));
+        let exclude = build_matcher(&src_dir, exclude).context("Invalid [src] exclude pattern")?;
+        let include = build_matcher(&src_dir, src_cfg.include.iter().map(|p| p.as_str()))
+            .context("Invalid [src] include pattern")?;
+
+        Ok(Self {
+            src_dir,
+            exclude,
+            include,
+        })
+    }
+
+    /// Checks if a path is ignored (absolute or relative to the source dir)
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let (rel_path, in_src_dir) = match path.strip_prefix(&self.src_dir) {
+            Ok(rel_path) => (rel_path, true),
+            Err(_) if path.is_relative() => (path, true),
+            Err(_) => match path.file_name() {
+                Some(file_name) => (Path::new(file_name), false),
+                None => return false,
+            },
+        };
+        if rel_path.as_os_str().is_empty() {
+            return false;
+        }
+
+        if self
+            .exclude
+            .matched_path_or_any_parents(rel_path, is_dir)
+            .is_ignore()
+        {
+            return true;
+        }
+        // NB: dirs are traversed, only the files must be included
+        !is_dir
+            && in_src_dir
+            && !self.include.is_empty()
+            && !self
+                .include
+                .matched_path_or_any_parents(rel_path, is_dir)
+                .is_ignore()
+    }
+}
+
+/// Builds a gitignore matcher
+fn build_matcher<'a>(root: &Path, patterns: impl Iterator<Item = &'a str>) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in patterns {
+        builder.add_line(None, pattern)?;
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ignored() {
+        let src_dir = PathBuf::from("/doc/src");
+        let exclude = DEFAULT_EXCLUDE.iter().copied().chain(["drafts/"]);
+        let filter = SourceFilter {
+            exclude: build_matcher(&src_dir, exclude).unwrap(),
+            include: build_matcher(&src_dir, ["*.md"].into_iter()).unwrap(),
+            src_dir,
+        };
+
+        assert!(!filter.is_ignored(Path::new("/doc/src/01-intro.md"), false));
+        assert!(!filter.is_ignored(Path::new("guides"), true));
+        assert!(filter.is_ignored(Path::new("/doc/src/drafts/a.md"), false));
+        assert!(filter.is_ignored(Path::new("/doc/src/.DS_Store"), false));
+        assert!(filter.is_ignored(Path::new("notes.txt"), false));
+        // NB: the include patterns do not apply outside the source dir (eg extra watched dirs)
+        assert!(!filter.is_ignored(Path::new("/doc/theme/index.hbs"), false));
+        assert!(filter.is_ignored(Path::new("/doc/theme/index.hbs.swp"), false));
+    }
+}
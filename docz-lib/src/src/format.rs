@@ -0,0 +1,88 @@
+//! Source formats
+//!
+//! Pages are converted to Markdown when they are loaded, which is the document representation
+//! shared by the renderers. Built-in formats: Markdown, HTML fragments, Jupyter notebooks
+//! and a subset of AsciiDoc.
+
+mod asciidoc;
+mod html;
+mod ipynb;
+
+pub use asciidoc::*;
+pub use html::*;
+pub use ipynb::*;
+
+use std::path::Path;
+
+use anyhow::Result;
+
+/// Format of the source pages
+pub trait SourceFormat: Send + Sync {
+    /// File extensions (lowercase, eg `["adoc", "asciidoc"]`)
+    fn extensions(&self) -> &[&str];
+
+    /// Converts a page to Markdown
+    fn to_markdown(&self, content: &[u8]) -> Result<String>;
+}
+
+/// Markdown format (as is)
+#[derive(Debug, Default)]
+pub struct MarkdownFormat {}
+
+impl SourceFormat for MarkdownFormat {
+    fn extensions(&self) -> &[&str] {
+        &["md", "markdown"]
+    }
+
+    fn to_markdown(&self, content: &[u8]) -> Result<String> {
+        Ok(String::from_utf8(content.to_vec())?)
+    }
+}
+
+/// Returns the built-in formats
+pub(crate) fn builtin_formats() -> Vec<Box<dyn SourceFormat>> {
+    vec![
+        Box::<MarkdownFormat>::default(),
+        Box::<HTMLFormat>::default(),
+        Box::<NotebookFormat>::default(),
+        Box::<AsciiDocFormat>::default(),
+    ]
+}
+
+/// Returns the format of a page
+pub(crate) fn find_format<'a>(
+    formats: &'a [Box<dyn SourceFormat>],
+    path: &Path,
+) -> Option<&'a dyn SourceFormat> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    formats
+        .iter()
+        .find(|format| format.extensions().contains(&ext.as_str()))
+        .map(|format| format.as_ref())
+}
+
+/// Returns a code fence longer than the backtick runs of a text
+pub(crate) fn code_fence(text: &str) -> String {
+    let mut max_run = 0;
+    let mut run = 0;
+    for c in text.chars() {
+        if c == '`' {
+            run += 1;
+            max_run = max_run.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    "`".repeat((max_run + 1).max(3))
+}
+
+/// Returns the frontmatter of a converted page
+pub(crate) fn title_frontmatter(title: Option<&str>) -> String {
+    match title {
+        Some(title) => format!(
+            "---\ntitle: {}\n---\n\n",
+            serde_json::to_string(title).unwrap_or_default()
+        ),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,610 @@
+//! AsciiDoc
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use crate::rend::{comrak_options, node_text};
+
+use super::{code_fence, title_frontmatter, SourceFormat};
+
+/// AsciiDoc format (`.adoc`, `.asciidoc`), a subset meant for migrated content
+///
+/// Supported:
+/// - document header (title, author and revision lines, attribute entries) and sections
+/// - paragraphs, with constrained/unconstrained bold and italic, monospace, links, cross-references,
+///   inline images and attribute references
+/// - unordered, ordered and description lists
+/// - listing, literal, quote, sidebar, example and passthrough blocks, block titles, admonitions
+/// - tables, block images, thematic breaks and comments
+#[derive(Debug, Default)]
+pub struct AsciiDocFormat {}
+
+impl SourceFormat for AsciiDocFormat {
+    fn extensions(&self) -> &[&str] {
+        &["adoc", "asciidoc"]
+    }
+
+    fn to_markdown(&self, content: &[u8]) -> Result<String> {
+        let adoc = String::from_utf8(content.to_vec())?;
+        let lines = adoc.lines().collect::<Vec<_>>();
+        let mut converter = Converter::default();
+        let body = converter.convert(&lines);
+        Ok(format!(
+            "{}{}\n",
+            title_frontmatter(converter.title.as_deref()),
+            body.trim()
+        ))
+    }
+}
+
+/// Admonition labels
+const ADMONITIONS: &[&str] = &["NOTE", "TIP", "IMPORTANT", "WARNING", "CAUTION"];
+
+/// AsciiDoc to Markdown converter
+#[derive(Debug, Default)]
+struct Converter {
+    /// Document title
+    title: Option<String>,
+    /// Attributes
+    attrs: BTreeMap<String, String>,
+}
+
+impl Converter {
+    /// Converts lines to Markdown
+    fn convert(&mut self, lines: &[&str]) -> String {
+        let mut md = String::new();
+        let mut block_attr: Option<String> = None;
+        let mut block_title: Option<String> = None;
+        let mut i = 0;
+        while i < lines.len() {
+            let line = lines[i];
+            let trimmed = line.trim_end();
+            i += 1;
+
+            // comments
+            if trimmed == "////" {
+                i = block_end(lines, i, trimmed) + 1;
+                continue;
+            }
+            if trimmed.starts_with("//") {
+                continue;
+            }
+
+            // document title and header (author and revision lines)
+            if let Some(title) = trimmed.strip_prefix("= ") {
+                if self.title.is_none() {
+                    let title = self.inline(title.trim());
+                    md.push_str(&format!("# {title}\n"));
+                    // NB: the front matter title is plain text (eg for the TOC)
+                    self.title = Some(plain_text(&title));
+                    while i < lines.len()
+                        && !lines[i].trim().is_empty()
+                        && !lines[i].starts_with(':')
+                    {
+                        i += 1;
+                    }
+                    continue;
+                }
+            }
+
+            // sections
+            if let Some(level) = section_level(trimmed) {
+                let title = self.inline(trimmed[level..].trim());
+                md.push_str(&format!("\n{} {title}\n\n", "#".repeat(level)));
+                continue;
+            }
+
+            // attribute entries
+            if let Some((name, value)) = attribute_entry(trimmed) {
+                match name.strip_suffix('!') {
+                    Some(name) => {
+                        self.attrs.remove(name);
+                    }
+                    None => {
+                        self.attrs.insert(name.to_string(), value.to_string());
+                    }
+                }
+                continue;
+            }
+
+            // anchors
+            if trimmed.starts_with("[[") && trimmed.ends_with("]]") {
+                let id = &trimmed[2..trimmed.len() - 2];
+                let id = id.split(',').next().unwrap_or(id);
+                md.push_str(&format!("<a id=\"{id}\"></a>\n"));
+                continue;
+            }
+
+            // block attributes and titles
+            if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                block_attr = Some(trimmed[1..trimmed.len() - 1].to_string());
+                continue;
+            }
+            if let Some(title) = trimmed.strip_prefix('.') {
+                if !title.is_empty() && !title.starts_with(['.', ' ']) {
+                    block_title = Some(self.inline(title));
+                    continue;
+                }
+            }
+
+            // delimited blocks
+            if is_delimiter(trimmed) {
+                let end = block_end(lines, i, trimmed);
+                let inner = &lines[i..end.min(lines.len())];
+                i = end + 1;
+                if let Some(title) = block_title.take() {
+                    md.push_str(&format!("**{title}**\n\n"));
+                }
+                let attr = block_attr.take().unwrap_or_default();
+                md.push_str(&self.delimited_block(trimmed, &attr, inner));
+                md.push('\n');
+                continue;
+            }
+            if let Some(title) = block_title.take() {
+                md.push_str(&format!("**{title}**\n\n"));
+            }
+
+            // literal paragraph (`[source]` or `[listing]` followed by a paragraph)
+            if let Some(attr) = block_attr.take() {
+                if attr.starts_with("source") || attr == "listing" || attr == "literal" {
+                    let start = i - 1;
+                    while i < lines.len() && !lines[i].trim().is_empty() {
+                        i += 1;
+                    }
+                    md.push_str(&self.delimited_block("----", &attr, &lines[start..i]));
+                    continue;
+                }
+                if ADMONITIONS.contains(&attr.as_str()) {
+                    md.push_str(&format!("> **{}:** ", capitalize(&attr)));
+                }
+            }
+
+            // admonition paragraphs
+            if let Some((label, text)) = trimmed.split_once(": ") {
+                if ADMONITIONS.contains(&label) {
+                    md.push_str(&format!(
+                        "> **{}:** {}\n",
+                        capitalize(label),
+                        self.inline(text)
+                    ));
+                    while i < lines.len() && !lines[i].trim().is_empty() {
+                        md.push_str(&format!("> {}\n", self.inline(lines[i].trim())));
+                        i += 1;
+                    }
+                    continue;
+                }
+            }
+
+            // breaks
+            if trimmed == "'''" {
+                md.push_str("\n---\n\n");
+                continue;
+            }
+            if trimmed == "<<<" || trimmed == "+" {
+                continue;
+            }
+
+            // block images
+            if let Some(image) = trimmed.strip_prefix("image::") {
+                if let Some((target, alt)) = image.split_once('[') {
+                    let alt = alt.trim_end_matches(']').split(',').next().unwrap_or("");
+                    md.push_str(&format!("![{}]({})\n", alt, self.subst_attrs(target)));
+                    continue;
+                }
+            }
+
+            // lists
+            if let Some((marker, depth, text)) = list_item(trimmed) {
+                let indent = "    ".repeat(depth - 1);
+                md.push_str(&format!("{indent}{marker} {}\n", self.inline(text)));
+                continue;
+            }
+            if let Some((term, def)) = trimmed.split_once(":: ") {
+                md.push_str(&format!(
+                    "{}\n\n: {}\n\n",
+                    self.inline(term),
+                    self.inline(def)
+                ));
+                continue;
+            }
+            if let Some(term) = trimmed.strip_suffix("::") {
+                if !term.trim().is_empty() {
+                    md.push_str(&format!("{}\n\n: ", self.inline(term)));
+                    if i < lines.len() {
+                        md.push_str(&format!("{}\n\n", self.inline(lines[i].trim())));
+                        i += 1;
+                    }
+                    continue;
+                }
+            }
+
+            // paragraph lines
+            md.push_str(&self.inline(trimmed));
+            md.push('\n');
+        }
+        md
+    }
+
+    /// Converts a delimited block
+    fn delimited_block(&mut self, delimiter: &str, attr: &str, inner: &[&str]) -> String {
+        let attrs = attr.split(',').map(|a| a.trim()).collect::<Vec<_>>();
+        match delimiter.chars().next() {
+            // listing, literal
+            Some('-') | Some('.') => {
+                let lang = match attrs.first() {
+                    Some(&"source") => attrs.get(1).copied().unwrap_or(""),
+                    _ => "",
+                };
+                let code = inner.join("\n");
+                let fence = code_fence(&code);
+                format!("{fence}{lang}\n{code}\n{fence}\n")
+            }
+            // passthrough
+            Some('+') => format!("{}\n", inner.join("\n")),
+            // table
+            Some('|') => self.table(&attrs, inner),
+            // quote
+            Some('_') => {
+                let mut md = prefix_lines(&self.convert(inner), "> ");
+                if let Some(author) = attrs.get(1).filter(|a| !a.is_empty()) {
+                    md.push_str(&format!(">\n> — {author}\n"));
+                }
+                md
+            }
+            // example, sidebar (or admonition)
+            _ => {
+                let md = self.convert(inner);
+                match attrs.first() {
+                    Some(label) if ADMONITIONS.contains(label) => prefix_lines(
+                        &format!("**{}:** {}", capitalize(label), md.trim_start()),
+                        "> ",
+                    ),
+                    _ => md,
+                }
+            }
+        }
+    }
+
+    /// Converts a table
+    fn table(&mut self, attrs: &[&str], inner: &[&str]) -> String {
+        let mut cells = vec![];
+        let mut first_row_cells = None;
+        let mut implicit_header = false;
+        for (i, line) in inner.iter().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                if i == 1 {
+                    implicit_header = true;
+                }
+                continue;
+            }
+            let line_cells = line
+                .split('|')
+                .skip(1)
+                .map(|cell| self.inline(cell.trim()).replace('|', "\\|"))
+                .collect::<Vec<_>>();
+            if first_row_cells.is_none() {
+                first_row_cells = Some(line_cells.len());
+            }
+            cells.extend(line_cells);
+        }
+
+        let cols = attrs
+            .iter()
+            .find_map(|a| a.strip_prefix("cols="))
+            .map(|cols| cols.trim_matches('"').split(',').count())
+            .or(first_row_cells)
+            .unwrap_or(1)
+            .max(1);
+        let header = implicit_header
+            || attrs
+                .iter()
+                .any(|a| a.contains("header") && !a.contains("noheader"));
+
+        let mut rows = cells
+            .chunks(cols)
+            .map(|row| row.to_vec())
+            .collect::<Vec<_>>();
+        let header_row = if header && !rows.is_empty() {
+            rows.remove(0)
+        } else {
+            vec![String::new(); cols]
+        };
+
+        let mut md = String::new();
+        let row_md = |row: &[String]| {
+            let mut row = row.to_vec();
+            row.resize(cols, String::new());
+            format!("| {} |\n", row.join(" | "))
+        };
+        md.push_str(&row_md(&header_row));
+        md.push_str(&format!("|{}\n", " --- |".repeat(cols)));
+        for row in &rows {
+            md.push_str(&row_md(row));
+        }
+        md
+    }
+
+    /// Converts the inline markup of a text
+    fn inline(&self, text: &str) -> String {
+        // NB: monospace spans are left as is
+        let mut out = String::new();
+        for (i, segment) in text.split('`').enumerate() {
+            if i > 0 {
+                out.push('`');
+            }
+            if i % 2 == 1 {
+                out.push_str(segment);
+                continue;
+            }
+            let segment = self.subst_attrs(segment);
+            let segment = convert_emphasis(&segment, '*', "**");
+            let segment = convert_emphasis(&segment, '_', "*");
+            out.push_str(&convert_macros(&segment));
+        }
+        out
+    }
+
+    /// Substitutes the attribute references (eg `{version}`)
+    fn subst_attrs(&self, text: &str) -> String {
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            match after.find('}') {
+                Some(end) if self.attrs.contains_key(&after[..end]) => {
+                    out.push_str(&self.attrs[&after[..end]]);
+                    rest = &after[end + 1..];
+                }
+                _ => {
+                    out.push('{');
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// Returns the plain text of inline Markdown (eg `*Intro* to _docz_` -> `Intro to docz`)
+fn plain_text(md: &str) -> String {
+    let arena = comrak::Arena::new();
+    let root = comrak::parse_document(&arena, md, &comrak_options());
+    node_text(root)
+}
+
+/// Returns the level of a section title (eg `== Title` -> 2)
+fn section_level(line: &str) -> Option<usize> {
+    let level = line.chars().take_while(|c| *c == '=').count();
+    if (2..=6).contains(&level) && line[level..].starts_with(' ') {
+        Some(level)
+    } else {
+        None
+    }
+}
+
+/// Returns an attribute entry (eg `:version: 1.0`)
+fn attribute_entry(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix(':')?;
+    let (name, value) = rest.split_once(':')?;
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((name, value.trim()))
+}
+
+/// Checks if a line delimits a block
+fn is_delimiter(line: &str) -> bool {
+    if line == "|===" {
+        return true;
+    }
+    let c = match line.chars().next() {
+        Some(c) => c,
+        None => return false,
+    };
+    matches!(c, '-' | '.' | '_' | '*' | '=' | '+')
+        && line.len() >= 4
+        && line.chars().all(|x| x == c)
+}
+
+/// Returns the index of the line closing a block (or the number of lines)
+fn block_end(lines: &[&str], start: usize, delimiter: &str) -> usize {
+    lines[start..]
+        .iter()
+        .position(|line| line.trim_end() == delimiter)
+        .map(|pos| start + pos)
+        .unwrap_or(lines.len())
+}
+
+/// Returns a list item (Markdown marker, depth, text)
+fn list_item(line: &str) -> Option<(&'static str, usize, &str)> {
+    let line = line.trim_start();
+    if let Some(text) = line.strip_prefix("- ") {
+        return Some(("-", 1, text));
+    }
+    for (c, marker) in [('*', "-"), ('.', "1.")] {
+        let depth = line.chars().take_while(|x| *x == c).count();
+        if depth > 0 && line[depth..].starts_with(' ') {
+            return Some((marker, depth, line[depth..].trim()));
+        }
+    }
+    None
+}
+
+/// Converts constrained (`*a*`) and unconstrained (`**a**`) emphasis
+fn convert_emphasis(text: &str, marker: char, replacement: &str) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == marker {
+            // unconstrained
+            if chars.get(i + 1) == Some(&marker) {
+                let end = (i + 2..chars.len().saturating_sub(1))
+                    .find(|j| chars[*j] == marker && chars[j + 1] == marker);
+                if let Some(end) = end {
+                    out.push_str(replacement);
+                    out.extend(&chars[i + 2..end]);
+                    out.push_str(replacement);
+                    i = end + 2;
+                    continue;
+                }
+            } else {
+                // constrained (at word boundaries)
+                let opens = (i == 0 || !chars[i - 1].is_alphanumeric())
+                    && chars.get(i + 1).is_some_and(|c| !c.is_whitespace());
+                let end = (i + 2..chars.len()).find(|j| {
+                    chars[*j] == marker
+                        && !chars[j - 1].is_whitespace()
+                        && chars.get(j + 1).is_none_or(|c| !c.is_alphanumeric())
+                });
+                if let (true, Some(end)) = (opens, end) {
+                    out.push_str(replacement);
+                    out.extend(&chars[i + 1..end]);
+                    out.push_str(replacement);
+                    i = end + 1;
+                    continue;
+                }
+            }
+        }
+        out.push(chars[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Converts the inline macros (links, images, cross-references)
+fn convert_macros(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    loop {
+        let next = [
+            "<<", "link:", "xref:", "image:", "https://", "http://", "mailto:",
+        ]
+        .iter()
+        .filter_map(|token| rest.find(token).map(|pos| (pos, *token)))
+        .min_by_key(|(pos, _)| *pos);
+        let (pos, token) = match next {
+            Some(next) => next,
+            None => break,
+        };
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos..];
+
+        // cross-references (eg `<<install,Installation>>`)
+        if token == "<<" {
+            if let Some(end) = after.find(">>") {
+                let inner = &after[2..end];
+                let (id, label) = inner.split_once(',').unwrap_or((inner, inner));
+                out.push_str(&format!("[{}](#{})", label.trim(), id.trim()));
+                rest = &after[end + 2..];
+                continue;
+            }
+        } else {
+            // macros (eg `link:url[text]`, `https://url[text]`)
+            let target_start = match token {
+                "link:" | "xref:" | "image:" => token.len(),
+                _ => 0,
+            };
+            let target_end = after
+                .find(|c: char| c.is_whitespace() || c == '[')
+                .unwrap_or(after.len());
+            if after[target_end..].starts_with('[') {
+                if let Some(label_end) = after[target_end..].find(']') {
+                    let target = &after[target_start..target_end];
+                    let label = &after[target_end + 1..target_end + label_end];
+                    let label = label.split(',').next().unwrap_or("");
+                    let md = match (token, label.is_empty()) {
+                        ("image:", _) => format!("![{label}]({target})"),
+                        (_, true) => format!("<{target}>"),
+                        (_, false) => format!("[{label}]({target})"),
+                    };
+                    out.push_str(&md);
+                    rest = &after[target_end + label_end + 1..];
+                    continue;
+                }
+            }
+        }
+
+        // not a macro
+        out.push_str(&after[..token.len()]);
+        rest = &after[token.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Prefixes the lines of a text
+fn prefix_lines(text: &str, prefix: &str) -> String {
+    let mut out = String::new();
+    for line in text.trim().lines() {
+        out.push_str(prefix.trim_end());
+        if !line.is_empty() {
+            out.push(' ');
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Capitalizes a label (eg `NOTE` -> `Note`)
+fn capitalize(label: &str) -> String {
+    let lower = label.to_lowercase();
+    let mut chars = lower.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => lower,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_markdown() {
+        let adoc = "\
+= The *docz* guide
+Jane Doe
+:product: docz
+
+== Usage
+
+Run *{product}* with `build`, see https://example.com[the site].
+
+.Example
+[source,sh]
+----
+docz build
+----
+
+* one
+* two
+
+NOTE: Mind the gap.
+";
+        let md = AsciiDocFormat::default()
+            .to_markdown(adoc.as_bytes())
+            .unwrap();
+        // NB: the front matter title is plain text
+        assert!(md.starts_with("---\ntitle: \"The docz guide\"\n---\n\n# The **docz** guide\n"));
+        assert!(!md.contains("Jane Doe"));
+        assert!(md.contains("\n## Usage\n"));
+        assert!(md.contains("Run **docz** with `build`, see [the site](https://example.com).\n"));
+        assert!(md.contains("**Example**\n\n```sh\ndocz build\n```\n"));
+        assert!(md.contains("- one\n- two\n"));
+        assert!(md.contains("> **Note:** Mind the gap.\n"));
+    }
+
+    #[test]
+    fn test_inline_markup() {
+        let md = AsciiDocFormat::default()
+            .to_markdown(b"a *b* c_d_ __e__f")
+            .unwrap();
+        assert_eq!(md, "a **b** c_d_ *e*f\n");
+    }
+}
@@ -0,0 +1,123 @@
+//! HTML fragments
+
+use anyhow::Result;
+
+use super::{title_frontmatter, SourceFormat};
+
+/// HTML fragment format (`.html`, `.htm`)
+///
+/// The fragment is kept as a raw HTML block, and the title comes from the `<title>`
+/// or the first `<h1>`. For a full document, only the body is kept.
+///
+/// NB: the scripts and styles are kept with `[src] raw_html`
+#[derive(Debug, Default)]
+pub struct HTMLFormat {}
+
+impl SourceFormat for HTMLFormat {
+    fn extensions(&self) -> &[&str] {
+        &["html", "htm"]
+    }
+
+    fn to_markdown(&self, content: &[u8]) -> Result<String> {
+        let html = String::from_utf8(content.to_vec())?;
+        let title = element_text(&html, "title").or_else(|| element_text(&html, "h1"));
+        let body = element_inner(&html, "body").unwrap_or(&html);
+
+        // NB: a raw HTML block ends at a blank line, so blank lines are replaced by empty comments
+        let body = body
+            .trim()
+            .lines()
+            .map(|line| {
+                if line.trim().is_empty() {
+                    "<!---->"
+                } else {
+                    line
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        // NB: wrapped in a div, so that the whole fragment is a single HTML block
+        Ok(format!(
+            "{}<div class=\"html-fragment\">\n{}\n</div>\n",
+            title_frontmatter(title.as_deref()),
+            body
+        ))
+    }
+}
+
+/// Returns the inner HTML of the first element with a tag (case insensitive)
+fn element_inner<'a>(html: &'a str, tag: &str) -> Option<&'a str> {
+    let lower = html.to_ascii_lowercase();
+    let open = lower.find(&format!("<{tag}"))?;
+    // NB: the tag name must end (eg `<header>` is not `<h>`)
+    let after_name = lower[open + tag.len() + 1..].chars().next()?;
+    if !(after_name == '>' || after_name.is_whitespace()) {
+        return None;
+    }
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find(&format!("</{tag}"))?;
+    Some(&html[start..end])
+}
+
+/// Returns the text of the first element with a tag (without the nested tags)
+fn element_text(html: &str, tag: &str) -> Option<String> {
+    let inner = element_inner(html, tag)?;
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in inner.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_markdown() {
+        let html = "\
+<html>
+<head><title>The &lt;docz&gt; guide</title></head>
+<body>
+<h1>Guide</h1>
+
+<p>Text</p>
+</body>
+</html>";
+        let md = HTMLFormat::default().to_markdown(html.as_bytes()).unwrap();
+        assert_eq!(
+            md,
+            "---\ntitle: \"The <docz> guide\"\n---\n\n\
+            <div class=\"html-fragment\">\n<h1>Guide</h1>\n<!---->\n<p>Text</p>\n</div>\n"
+        );
+    }
+
+    #[test]
+    fn test_fragment_title() {
+        let md = HTMLFormat::default()
+            .to_markdown(b"<header><h1>A <em>b</em></h1></header>")
+            .unwrap();
+        assert!(md.starts_with("---\ntitle: \"A b\"\n---\n\n"));
+        assert_eq!(element_inner("<header>x</header>", "h"), None);
+    }
+}
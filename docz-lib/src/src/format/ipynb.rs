@@ -0,0 +1,240 @@
+//! Jupyter notebooks
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{code_fence, title_frontmatter, SourceFormat};
+
+/// Jupyter notebook format (`.ipynb`, nbformat 4)
+///
+/// Cells are rendered with their stored outputs, no kernel is needed:
+/// - Markdown cells are kept as is
+/// - code cells are code blocks, in the language of the notebook
+/// - outputs are images (PNG, JPEG, SVG), HTML, Markdown or text blocks
+#[derive(Debug, Default)]
+pub struct NotebookFormat {}
+
+/// Notebook
+#[derive(Debug, Deserialize)]
+struct Notebook {
+    #[serde(default)]
+    metadata: NotebookMetadata,
+    cells: Vec<Cell>,
+}
+
+/// Notebook metadata
+#[derive(Debug, Deserialize, Default)]
+struct NotebookMetadata {
+    title: Option<String>,
+    language_info: Option<LanguageInfo>,
+    kernelspec: Option<KernelSpec>,
+}
+
+/// Language info
+#[derive(Debug, Deserialize)]
+struct LanguageInfo {
+    name: Option<String>,
+}
+
+/// Kernel spec
+#[derive(Debug, Deserialize)]
+struct KernelSpec {
+    language: Option<String>,
+}
+
+/// Cell
+#[derive(Debug, Deserialize)]
+struct Cell {
+    cell_type: String,
+    #[serde(default)]
+    source: MultilineString,
+    #[serde(default)]
+    outputs: Vec<CellOutput>,
+}
+
+/// Cell output
+#[derive(Debug, Deserialize)]
+struct CellOutput {
+    output_type: String,
+    /// Stream output (`stream`)
+    #[serde(default)]
+    text: MultilineString,
+    /// Mime bundle (`execute_result`, `display_data`)
+    #[serde(default)]
+    data: serde_json::Map<String, Value>,
+    /// Error name (`error`)
+    ename: Option<String>,
+    /// Error value (`error`)
+    evalue: Option<String>,
+}
+
+/// String stored as a string or a list of lines
+#[derive(Debug, Deserialize, Default)]
+#[serde(from = "Value")]
+struct MultilineString(String);
+
+impl From<Value> for MultilineString {
+    fn from(value: Value) -> Self {
+        Self(multiline_string(&value))
+    }
+}
+
+/// Returns a string stored as a string or a list of lines
+fn multiline_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.to_string(),
+        Value::Array(lines) => lines.iter().filter_map(|line| line.as_str()).collect(),
+        _ => String::new(),
+    }
+}
+
+impl SourceFormat for NotebookFormat {
+    fn extensions(&self) -> &[&str] {
+        &["ipynb"]
+    }
+
+    fn to_markdown(&self, content: &[u8]) -> Result<String> {
+        let notebook = serde_json::from_slice::<Notebook>(content)
+            .map_err(|err| anyhow!("Invalid notebook: {err}"))?;
+        let lang = notebook
+            .metadata
+            .language_info
+            .and_then(|info| info.name)
+            .or(notebook.metadata.kernelspec.and_then(|spec| spec.language))
+            .unwrap_or_default();
+
+        let mut md = title_frontmatter(notebook.metadata.title.as_deref());
+        for cell in &notebook.cells {
+            let source = cell.source.0.trim_end();
+            match cell.cell_type.as_str() {
+                "markdown" => push_block(&mut md, source),
+                "code" => {
+                    if !source.is_empty() {
+                        push_code_block(&mut md, &lang, source);
+                    }
+                    for output in &cell.outputs {
+                        push_output(&mut md, output);
+                    }
+                }
+                // NB: raw cells are not rendered
+                _ => {}
+            }
+        }
+        Ok(md)
+    }
+}
+
+/// Appends a block
+fn push_block(md: &mut String, block: &str) {
+    md.push_str(block.trim_end());
+    md.push_str("\n\n");
+}
+
+/// Appends a code block
+fn push_code_block(md: &mut String, info: &str, code: &str) {
+    let fence = code_fence(code);
+    push_block(md, &format!("{fence}{info}\n{code}\n{fence}"));
+}
+
+/// Appends a cell output
+fn push_output(md: &mut String, output: &CellOutput) {
+    match output.output_type.as_str() {
+        "stream" => push_code_block(md, "text", output.text.0.trim_end()),
+        "execute_result" | "display_data" => {
+            let data = &output.data;
+            if let Some(image) = ["image/png", "image/jpeg"]
+                .iter()
+                .find_map(|mime| data.get(*mime).map(|value| (mime, value)))
+            {
+                let (mime, value) = image;
+                // NB: base64 data may be split in lines
+                let base64 = multiline_string(value).replace('\n', "");
+                push_block(md, &format!("![output](data:{mime};base64,{base64})"));
+            } else if let Some(svg) = data.get("image/svg+xml") {
+                push_html_block(md, &multiline_string(svg));
+            } else if let Some(html) = data.get("text/html") {
+                push_html_block(md, &multiline_string(html));
+            } else if let Some(markdown) = data.get("text/markdown") {
+                push_block(md, &multiline_string(markdown));
+            } else if let Some(text) = data.get("text/plain") {
+                push_code_block(md, "text", multiline_string(text).trim_end());
+            }
+        }
+        "error" => {
+            let error = format!(
+                "{}: {}",
+                output.ename.as_deref().unwrap_or("Error"),
+                output.evalue.as_deref().unwrap_or_default()
+            );
+            push_code_block(md, "text", &error);
+        }
+        _ => {}
+    }
+}
+
+/// Appends a raw HTML block
+///
+/// NB: a raw HTML block ends at a blank line, so blank lines are removed
+fn push_html_block(md: &mut String, html: &str) {
+    let html = html
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    push_block(md, &format!("<div class=\"nb-output\">\n{html}\n</div>"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_markdown() {
+        let notebook = serde_json::json!({
+            "metadata": {
+                "title": "Analysis",
+                "language_info": { "name": "python" }
+            },
+            "cells": [
+                { "cell_type": "markdown", "source": ["# Data\n", "\n", "Some text"] },
+                {
+                    "cell_type": "code",
+                    "source": "print(1)",
+                    "outputs": [
+                        { "output_type": "stream", "text": ["1\n"] },
+                        {
+                            "output_type": "execute_result",
+                            "data": { "text/html": ["<table>\n", "\n", "</table>"], "text/plain": "df" }
+                        },
+                        {
+                            "output_type": "display_data",
+                            "data": { "image/png": "iVBO\nRw0=\n" }
+                        },
+                        { "output_type": "error", "ename": "ValueError", "evalue": "bad" }
+                    ]
+                },
+                { "cell_type": "raw", "source": "not rendered" }
+            ]
+        });
+        let md = NotebookFormat::default()
+            .to_markdown(notebook.to_string().as_bytes())
+            .unwrap();
+        assert_eq!(
+            md,
+            "---\ntitle: \"Analysis\"\n---\n\n\
+            # Data\n\nSome text\n\n\
+            ```python\nprint(1)\n```\n\n\
+            ```text\n1\n```\n\n\
+            <div class=\"nb-output\">\n<table>\n</table>\n</div>\n\n\
+            ![output](data:image/png;base64,iVBORw0=)\n\n\
+            ```text\nValueError: bad\n```\n\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_notebook() {
+        let err = NotebookFormat::default().to_markdown(b"{}").unwrap_err();
+        assert!(err.to_string().starts_with("Invalid notebook"));
+    }
+}
@@ -0,0 +1,176 @@
+//! Source providers
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::cfg::Config;
+
+use super::{SourceData, SourceFilter};
+
+/// Provider of the source files
+///
+/// The filesystem provider is used by default, see [crate::ServiceBuilder::source_provider]
+/// to build from generated content or archives.
+pub trait SourceProvider: Send + Sync {
+    /// Loads the source files and assets
+    ///
+    /// NB: the paths of the source data are within the source dir of the config ([Config::src_dir]),
+    /// see [SourceData::from_files].
+    fn load(&self, cfg: &Config) -> Result<SourceData>;
+}
+
+/// Provider reading the source dir from the filesystem
+#[derive(Debug, Default)]
+pub struct FsSourceProvider {}
+
+impl FsSourceProvider {
+    /// Creates a new filesystem provider
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SourceProvider for FsSourceProvider {
+    fn load(&self, cfg: &Config) -> Result<SourceData> {
+        let src_dir = cfg.src_dir();
+        let assets_dir = cfg.src_assets_dir();
+        let filter = SourceFilter::new(cfg)?;
+        let mut files = BTreeMap::new();
+        read_dir_iter(cfg, &src_dir, &assets_dir, &filter, &mut files)
+            .with_context(|| format!("Failed to read the source dir {}", src_dir.display()))?;
+        SourceData::from_files(cfg, files)
+    }
+}
+
+/// Reads the files of a dir recursively (paths relative to the source dir)
+///
+/// NB: ignored files are skipped, and unsupported files are not read
+/// (they are reported by [SourceData::from_files]).
+fn read_dir_iter(
+    cfg: &Config,
+    dir: &Path,
+    assets_dir: &Path,
+    filter: &SourceFilter,
+    files: &mut BTreeMap<PathBuf, Vec<u8>>,
+) -> Result<()> {
+    let src_dir = cfg.src_dir();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let is_dir = entry.file_type()?.is_dir();
+        if filter.is_ignored(&path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            read_dir_iter(cfg, &path, assets_dir, filter, files)?;
+        } else {
+            let rel_path = path.strip_prefix(&src_dir)?.to_owned();
+            let content = if cfg.is_page(&path) || path.starts_with(assets_dir) {
+                fs::read(&path)?
+            } else {
+                vec![]
+            };
+            files.insert(rel_path, content);
+        }
+    }
+    Ok(())
+}
+
+/// Provider of in-memory files (eg generated content)
+///
+/// Paths are relative to the root dir (eg `src/01-intro.md`).
+#[derive(Debug, Clone, Default)]
+pub struct MemorySourceProvider {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl MemorySourceProvider {
+    /// Creates a new in-memory provider
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file
+    pub fn file(mut self, path: impl Into<PathBuf>, content: impl Into<Vec<u8>>) -> Self {
+        self.files.insert(path.into(), content.into());
+        self
+    }
+}
+
+impl SourceProvider for MemorySourceProvider {
+    fn load(&self, cfg: &Config) -> Result<SourceData> {
+        let files = src_files(cfg, self.files.clone())?;
+        SourceData::from_files(cfg, files)
+    }
+}
+
+/// Keeps the files of the source dir (paths relative to the root dir) and makes them relative to it
+pub(crate) fn src_files(
+    cfg: &Config,
+    files: impl IntoIterator<Item = (PathBuf, Vec<u8>)>,
+) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+    let root_dir = cfg.root_dir();
+    let src_dir = cfg.src_dir();
+    let src_rel_dir = src_dir
+        .strip_prefix(&root_dir)
+        .context("Source dir is not within the root dir")?;
+    Ok(files
+        .into_iter()
+        .filter_map(|(path, content)| {
+            let rel_path = path.strip_prefix(src_rel_dir).ok()?.to_owned();
+            Some((rel_path, content))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_provider() {
+        let mut cfg = Config::default();
+        cfg.set_root_dir(Path::new("/doc"));
+        let provider = MemorySourceProvider::new()
+            .file("src/01-intro.md", "# Intro")
+            .file("src/01-intro/01-child.md", "# Child")
+            .file("src/_assets/logo.png", vec![0u8, 1])
+            .file("README.md", "# Outside the source dir");
+        let data = provider.load(&cfg).unwrap();
+
+        assert_eq!(data.files.len(), 1);
+        let intro = &data.files[0];
+        assert_eq!(intro.path, Path::new("/doc/src/01-intro.md"));
+        assert_eq!(intro.content, b"# Intro");
+        assert_eq!(intro.children.len(), 1);
+        assert_eq!(
+            intro.children[0].path,
+            Path::new("/doc/src/01-intro/01-child.md")
+        );
+        assert_eq!(data.assets.len(), 1);
+        assert_eq!(data.assets[0].path, Path::new("/doc/src/_assets/logo.png"));
+    }
+
+    #[test]
+    fn test_src_files() {
+        let mut cfg = Config::default();
+        cfg.set_root_dir(Path::new("/doc"));
+        let files = src_files(
+            &cfg,
+            [
+                (PathBuf::from("src/a.md"), vec![]),
+                (PathBuf::from("other/b.md"), vec![]),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            files.into_keys().collect::<Vec<_>>(),
+            [PathBuf::from("a.md")]
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Variables
+//!
+//! Variables are substituted in the Markdown sources with the `{{ var.name }}` syntax,
+//! before the sources are rendered.
+//!
+//! Values come from the `[variables]` section of doc.toml, overridden by the CLI (`--var key=value`),
+//! and by the `variables` field of the page frontmatter.
+//!
+//! Escape rules:
+//! - `\{{` is written as a literal `{{`
+//! - fenced code blocks are left as is, unless their info string contains `vars` (eg ` ```sh vars`)
+//! - `{{ ... }}` expressions which do not start with `var.` are left as is
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+
+use crate::src::{frontmatter_str, FileMetadata};
+
+/// Prefix of the variable expressions
+const VAR_PREFIX: &str = "var.";
+
+/// Info string attribute enabling substitution in a fenced code block
+const FENCE_VARS_ATTR: &str = "vars";
+
+/// Substitutes the variables in a Markdown source (including its frontmatter overrides)
+pub fn substitute_page_vars(md: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    let page_vars = page_vars(md)?;
+    if page_vars.is_empty() {
+        return substitute_vars(md, vars);
+    }
+
+    let mut vars = vars.clone();
+    vars.extend(page_vars);
+    substitute_vars(md, &vars)
+}
+
+/// Substitutes the variables in a Markdown text
+pub fn substitute_vars(md: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    let mut out = String::with_capacity(md.len());
+    // open fence (char, length, substitution enabled)
+    let mut fence: Option<(char, usize, bool)> = None;
+    for (i, line) in md.split_inclusive('\n').enumerate() {
+        let marker = fence_marker(line);
+        match (fence, marker) {
+            (Some((c, len, _)), Some((m_c, m_len, info)))
+                if m_c == c && m_len >= len && info.is_empty() =>
+            {
+                fence = None;
+                out.push_str(line);
+                continue;
+            }
+            (Some((_, _, false)), _) => {
+                out.push_str(line);
+                continue;
+            }
+            (None, Some((c, len, info))) => {
+                let enabled = info.split_whitespace().any(|attr| attr == FENCE_VARS_ATTR);
+                fence = Some((c, len, enabled));
+                out.push_str(line);
+                continue;
+            }
+            _ => {}
+        }
+
+        substitute_line(line, vars, &mut out).map_err(|err| anyhow!("{err} (line {})", i + 1))?;
+    }
+    Ok(out)
+}
+
+/// Returns the variables set in the frontmatter of a page
+fn page_vars(md: &str) -> Result<BTreeMap<String, String>> {
+    let fm = match frontmatter_str(md) {
+        Some(fm) => fm,
+        None => return Ok(BTreeMap::new()),
+    };
+
+    let metadata = serde_yaml::from_str::<FileMetadata>(fm)?;
+    let vars = metadata
+        .variables
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                serde_yaml::Value::String(s) => s,
+                serde_yaml::Value::Null => String::new(),
+                value => serde_yaml::to_string(&value)
+                    .map(|s| s.trim_end().to_string())
+                    .unwrap_or_default(),
+            };
+            (key, value)
+        })
+        .collect();
+    Ok(vars)
+}
+
+/// Returns the fence marker of a line (char, length, info string), if any
+fn fence_marker(line: &str) -> Option<(char, usize, &str)> {
+    let trimmed = line.trim_start();
+    let c = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = trimmed.chars().take_while(|x| *x == c).count();
+    if len < 3 {
+        return None;
+    }
+    let info = trimmed[len..].trim();
+    if c == '`' && info.contains('`') {
+        return None;
+    }
+    Some((c, len, info))
+}
+
+/// Substitutes the variables in a line
+fn substitute_line(line: &str, vars: &BTreeMap<String, String>, out: &mut String) -> Result<()> {
+    let mut rest = line;
+    while let Some(start) = rest.find("{{") {
+        // escaped
+        if rest[..start].ends_with('\\') {
+            out.push_str(&rest[..start - 1]);
+            out.push_str("{{");
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        out.push_str(&rest[..start]);
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        };
+        let expr = rest[start + 2..end].trim();
+        match expr.strip_prefix(VAR_PREFIX) {
+            Some(name) => {
+                let name = name.trim();
+                let value = vars
+                    .get(name)
+                    .ok_or(anyhow!("Undefined variable `{name}`"))?;
+                out.push_str(value);
+            }
+            None => out.push_str(&rest[start..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> BTreeMap<String, String> {
+        BTreeMap::from([("product".to_string(), "Docz".to_string())])
+    }
+
+    #[test]
+    fn test_substitute_vars() {
+        let md = substitute_vars("# {{ var.product }} and {{var.product}}\n", &vars()).unwrap();
+        assert_eq!(md, "# Docz and Docz\n");
+    }
+
+    #[test]
+    fn test_escapes_and_other_expressions() {
+        let md = substitute_vars(
+            "\\{{ var.product }} {{index \"term\"}} {{ unclosed\n",
+            &vars(),
+        )
+        .unwrap();
+        assert_eq!(md, "{{ var.product }} {{index \"term\"}} {{ unclosed\n");
+    }
+
+    #[test]
+    fn test_fenced_code_blocks() {
+        let md = "```sh\necho {{ var.product }}\n```\n\n```sh vars\necho {{ var.product }}\n```\n";
+        let md = substitute_vars(md, &vars()).unwrap();
+        assert_eq!(
+            md,
+            "```sh\necho {{ var.product }}\n```\n\n```sh vars\necho Docz\n```\n"
+        );
+
+        // NB: a shorter fence does not close the block
+        let md = "````\n```\n{{ var.product }}\n````\n{{ var.product }}\n";
+        let md = substitute_vars(md, &vars()).unwrap();
+        assert_eq!(md, "````\n```\n{{ var.product }}\n````\nDocz\n");
+    }
+
+    #[test]
+    fn test_undefined_variable() {
+        let err = substitute_vars("a\n{{ var.missing }}\n", &vars()).unwrap_err();
+        assert_eq!(err.to_string(), "Undefined variable `missing` (line 2)");
+    }
+
+    #[test]
+    fn test_page_vars() {
+        let md = "---\nvariables:\n  product: Other\n---\n\n{{ var.product }}\n";
+        let md = substitute_page_vars(md, &vars()).unwrap();
+        assert!(md.ends_with("\nOther\n"));
+    }
+}
@@ -0,0 +1,201 @@
+//! Versions
+//!
+//! Each version listed in `[versions]` is built from a git ref, into `build/html/<version>/`,
+//! with its locales (`build/html/<version>/<locale>/`).
+//! The sources are read from the object database of the local repository, the working tree
+//! is never touched. The `latest` alias is a copy of its version, when it is built.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use log::{trace, warn};
+
+use crate::{
+    cfg::Config,
+    git::GitRepo,
+    i18n::strip_translations,
+    src::{load_src_data, FsSourceProvider, SourceData},
+    Service,
+};
+
+/// Name of the `latest` alias
+pub const LATEST_VERSION: &str = "latest";
+
+/// Temporary directory (inside the build dir) where the versions are extracted
+const VERSIONS_TMP_DIR: &str = ".versions";
+
+/// Directory (inside the versions dir) where the per-locale source dirs are extracted
+///
+/// NB: git refs cannot start with a `.`, so it does not clash with a version
+const LOCALES_TMP_DIR: &str = ".locales";
+
+/// Doc version
+#[derive(Debug, Clone)]
+pub struct DocVersion {
+    /// Name (eg `v1.0`)
+    pub name: String,
+    /// Git ref
+    pub git_ref: String,
+}
+
+impl Service {
+    /// Returns the versions to build (including the `latest` alias)
+    pub(crate) fn resolve_versions(&self) -> Result<Vec<DocVersion>> {
+        let versions_cfg = match &self.config.file().versions {
+            Some(versions_cfg) => versions_cfg,
+            None => return Ok(vec![]),
+        };
+        let repo = GitRepo::open(&self.config.root_dir())?;
+
+        let mut refs = versions_cfg.refs.clone();
+        let tags = match &versions_cfg.tags {
+            Some(glob) => repo.tags(glob)?,
+            None => vec![],
+        };
+        for tag in &tags {
+            if !refs.contains(tag) {
+                refs.push(tag.to_string());
+            }
+        }
+
+        let mut versions = refs
+            .iter()
+            .map(|git_ref| DocVersion {
+                name: version_name(git_ref),
+                git_ref: git_ref.to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        let latest = versions_cfg
+            .latest
+            .as_ref()
+            .or(tags.first())
+            .or(versions_cfg.refs.first());
+        if let Some(latest) = latest {
+            versions.push(DocVersion {
+                name: LATEST_VERSION.to_string(),
+                git_ref: latest.to_string(),
+            });
+        }
+
+        Ok(versions)
+    }
+
+    /// Builds the versions with the renderers which support it
+    pub(crate) fn build_versions(&self, cfg: &Config, versions: &[DocVersion]) -> Result<()> {
+        if versions.is_empty() {
+            return Ok(());
+        }
+        let repo = GitRepo::open(&cfg.root_dir())?;
+        let src_path = repo.relative_path(&cfg.src_dir())?;
+        let tmp_guard = TmpDir(cfg.build_dir().join(VERSIONS_TMP_DIR));
+        let tmp_dir = tmp_guard.0.as_path();
+
+        // configs of the built versions, by git ref
+        let mut built_cfgs: HashMap<&str, Config> = HashMap::new();
+        for version in versions {
+            // NB: an alias (eg `latest`) of a built version is copied
+            if let Some(built_cfg) = built_cfgs.get(version.git_ref.as_str()) {
+                trace!("Aliasing version {} ({})", version.name, version.git_ref);
+                for id in cfg.output_ids() {
+                    match self.renderers.get(id) {
+                        Some(renderer) if renderer.versioned() => {
+                            renderer.alias_version(built_cfg, &version.name)?;
+                        }
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+
+            trace!("Building version {} ({})", version.name, version.git_ref);
+            let version_dir = tmp_dir.join(&version.name);
+            repo.extract(&version.git_ref, &src_path, &version_dir)?;
+
+            let mut version_cfg = cfg.clone();
+            version_cfg.set_version(Some(&version.name));
+            version_cfg.set_src_dir(&version_dir);
+            self.extract_locale_dirs(&repo, version, tmp_dir, &mut version_cfg)?;
+            // NB: the version is extracted to the filesystem, whatever the source provider
+            let provider = FsSourceProvider::new();
+            let version_data = load_src_data(&provider, &self.source_formats, &version_cfg)
+                .with_context(|| format!("Failed to load version {}", version.name))?;
+            let src_data = SourceData {
+                files: strip_translations(&version_data.files, &version_cfg),
+                assets: version_data.assets.clone(),
+            };
+
+            for id in cfg.output_ids() {
+                match self.renderers.get(id) {
+                    Some(renderer) if renderer.versioned() => {
+                        trace!("Rendering output ({id}) for version {}", version.name);
+                        renderer.render(&version_cfg, &src_data)?;
+                    }
+                    _ => {}
+                }
+            }
+            self.build_locales(&version_cfg, &version_data, &provider)?;
+            built_cfgs.insert(&version.git_ref, version_cfg);
+        }
+
+        Ok(())
+    }
+
+    /// Extracts the per-locale source dirs of a version (`[languages.dirs]`)
+    ///
+    /// NB: a locale dir which does not exist in the version is ignored
+    fn extract_locale_dirs(
+        &self,
+        repo: &GitRepo,
+        version: &DocVersion,
+        tmp_dir: &Path,
+        version_cfg: &mut Config,
+    ) -> Result<()> {
+        let locale_dirs = match &self.config.file().languages {
+            Some(languages) => languages.dirs.clone(),
+            None => return Ok(()),
+        };
+        for (locale, locale_dir) in locale_dirs {
+            let dest = tmp_dir
+                .join(LOCALES_TMP_DIR)
+                .join(&version.name)
+                .join(&locale);
+            let extracted = repo
+                .relative_path(&self.config.root_dir().join(&locale_dir))
+                .and_then(|path| repo.extract(&version.git_ref, &path, &dest));
+            match extracted {
+                Ok(()) => version_cfg.set_locale_dir(&locale, Some(&dest)),
+                Err(err) => {
+                    warn!(
+                        "No sources for locale {locale} in version {} ({err:#})",
+                        version.name
+                    );
+                    version_cfg.set_locale_dir(&locale, None)
+                }
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Temporary dir, removed when dropped (ie also when a version fails to build)
+struct TmpDir(PathBuf);
+
+impl Drop for TmpDir {
+    fn drop(&mut self) {
+        if self.0.exists() {
+            if let Err(err) = fs::remove_dir_all(&self.0) {
+                warn!("Failed to remove {} ({err})", self.0.display());
+            }
+        }
+    }
+}
+
+/// Returns the name of a version from its git ref (eg `release/2.x` -> `release-2.x`)
+fn version_name(git_ref: &str) -> String {
+    git_ref.replace(['/', '\\'], "-")
+}
@@ -7,7 +7,7 @@ use log::{debug, warn};
 use notify::{Event, EventKind, FsEventWatcher, RecursiveMode, Watcher as _};
 use tokio::sync::watch;
 
-use crate::Service;
+use crate::{src::SourceFilter, Service};
 
 /// Watch event
 pub type WatchEvent = Event;
@@ -23,14 +23,23 @@ pub(crate) struct Watcher {
 impl Watcher {
     /// Creates a new watcher
     ///
-    /// We pass the dirs to watch, and an optional debounce time (in ms)
-    pub fn new(dirs: Vec<PathBuf>, debounce: Option<u128>) -> Result<Self> {
+    /// We pass the dirs to watch, the filter of the ignored paths, and an optional debounce time (in ms)
+    pub fn new(dirs: Vec<PathBuf>, filter: SourceFilter, debounce: Option<u128>) -> Result<Self> {
         let (tx, rx) = watch::channel(Event::default());
         let mut last_event = Instant::now();
         let watcher =
             notify::recommended_watcher(move |res: Result<notify::Event, notify::Error>| {
                 match res {
                     Ok(event) => {
+                        // NB: ignored paths (eg editor swap files) do not trigger a rebuild
+                        if !event.paths.is_empty()
+                            && event
+                                .paths
+                                .iter()
+                                .all(|path| filter.is_ignored(path, path.is_dir()))
+                        {
+                            return;
+                        }
                         debug!("Watch event: {:?}", event);
 
                         // NB: debouncing
@@ -16,7 +16,9 @@ fn init_service() -> Service {
         // NB: current_dir() points to the root of the crate (tests only)
         .root_dir("./tests/build")
         .dbg_renderer()
+        .json_renderer()
         .html_renderer()
+        .text_renderer()
         .build()
         .unwrap()
 }
@@ -25,4 +27,12 @@ fn init_service() -> Service {
 async fn test_build() {
     let service = init_service();
     service.build().unwrap();
+
+    // NB: the print outputs show the callouts, tabs and math as plain Markdown
+    let book = std::fs::read_to_string("./tests/build/build/book.md").unwrap();
+    assert!(!book.contains(":::"));
+    assert!(!book.contains("$E"));
+    assert!(!book.contains("{{index"));
+    assert!(book.contains("> **ℹ️ Note**"));
+    assert!(book.contains("**Rust**\n\n``` rust\n"));
 }